@@ -0,0 +1,38 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use phantom_of_server::server::Server;
+use scopa_lib::protocol::{decode, encode, ClientMessage, ServerMessage, PROTOCOL_VERSION};
+use scopa_lib::rules::RuleSet;
+use std::time::Duration;
+
+// The input is a list of messages, each one prefixed by its length in a single byte
+fuzz_target!(|data: &[u8]| {
+    let _ = decode::<ClientMessage>(data);
+
+    let mut server = Server::new(RuleSet::default());
+    // One client says hello first so the messages get past the handshake
+    for connection in 1..=2 {
+        server.client_connected(connection);
+    }
+    let hello = ClientMessage::Hello {
+        version: PROTOCOL_VERSION,
+        name: "Fuzzer".to_string(),
+    };
+    server.handle_message(1, &encode(&hello).unwrap());
+
+    let mut rest = data;
+    let mut now = Duration::ZERO;
+    while let Some((&len, tail)) = rest.split_first() {
+        let (message, tail) = tail.split_at(tail.len().min(len as usize));
+        rest = tail;
+        // The low bit picks the client, the one that said hello or the one that didn't
+        server.handle_message(1 + u64::from(len & 1), message);
+        now += Duration::from_millis(100);
+        server.update(now);
+        // Whatever the clients send, the server only answers with messages they can read
+        for (_, message) in server.drain_outbox() {
+            assert!(decode::<ServerMessage>(&message).is_ok());
+        }
+    }
+});
@@ -0,0 +1,158 @@
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary, Result, Unstructured};
+use libfuzzer_sys::fuzz_target;
+use phantom_of_server::clock::TimeControl;
+use phantom_of_server::server::Server;
+use scopa_lib::card::{Card, Deck};
+use scopa_lib::protocol::{decode, encode, ClientMessage, ServerMessage, PROTOCOL_VERSION};
+use scopa_lib::rules::RuleSet;
+use scopa_lib::PlayerId;
+use std::time::Duration;
+
+// Any card of the deck, mostly ones that are somewhere else than the move says
+#[derive(Debug)]
+struct AnyCard(Card);
+
+impl<'a> Arbitrary<'a> for AnyCard {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self(*u.choose(Deck::default().cards())?))
+    }
+}
+
+// Moves are made of cards the player holds and cards on the table where possible, so most of
+// them get as far as the rules of the game before they are rejected
+#[derive(Debug, Arbitrary)]
+enum Move {
+    // Card of the hand by its position
+    Put { card: u8 },
+    // Cards of the table by their positions, taken with a card of the hand
+    Take { take: Vec<u8>, with: u8 },
+    PutAny { card: AnyCard },
+    TakeAny { take: Vec<AnyCard>, with: AnyCard },
+}
+
+#[derive(Debug, Arbitrary)]
+struct Turn {
+    // Move of the other player, who is out of turn
+    out_of_turn: bool,
+    play: Move,
+    // Time passing before the move, long waits let the clocks run out
+    wait: u8,
+}
+
+// Cards each player sees, followed from what the server tells the clients. Players 1 and 2 take
+// turns, so the one to move next is always the other one.
+#[derive(Default)]
+struct Seen {
+    hands: [Vec<Card>; 2],
+    table: Vec<Card>,
+    active_player: PlayerId,
+}
+
+impl Seen {
+    fn follow(&mut self, to: PlayerId, message: &ServerMessage) {
+        match message {
+            ServerMessage::RoundStarted { active_player } => {
+                self.active_player = *active_player;
+                self.table.clear();
+            }
+            ServerMessage::TablePlaced { table } | ServerMessage::TableRedealt { table } => {
+                self.table = table.to_vec();
+            }
+            ServerMessage::HandDealt { hand } => self.hands[to as usize - 1] = hand.to_vec(),
+            ServerMessage::CardPut { id, card } if to == *id => {
+                self.hands[*id as usize - 1].retain(|held| held != card);
+                self.table.push(*card);
+                self.active_player = 3 - id;
+            }
+            ServerMessage::CardsTaken { id, take, with } if to == *id => {
+                self.hands[*id as usize - 1].retain(|held| held != with);
+                self.table.retain(|card| !take.contains(card));
+                self.active_player = 3 - id;
+            }
+            _ => {}
+        }
+    }
+
+    fn deliver(&mut self, server: &mut Server) {
+        for (to, message) in server.drain_outbox() {
+            // Whatever the clients send, the server only answers with messages they can read
+            let message = decode::<ServerMessage>(&message).unwrap();
+            self.follow(to, &message);
+        }
+    }
+
+    fn message(&self, id: PlayerId, turn: &Move) -> ClientMessage {
+        let hand = &self.hands[id as usize - 1];
+        // Hand is empty once the game is over
+        let held = |i: u8| {
+            hand.get(i as usize % hand.len().max(1))
+                .copied()
+                .unwrap_or(Deck::default().cards()[0])
+        };
+        match turn {
+            Move::Put { card } => ClientMessage::PutCard { card: held(*card) },
+            Move::Take { take, with } => ClientMessage::TakeCards {
+                take: take
+                    .iter()
+                    .filter_map(|i| self.table.get(*i as usize % self.table.len().max(1)))
+                    .copied()
+                    .collect(),
+                with: held(*with),
+            },
+            Move::PutAny { card } => ClientMessage::PutCard { card: card.0 },
+            Move::TakeAny { take, with } => ClientMessage::TakeCards {
+                take: take.iter().map(|card| card.0).collect(),
+                with: with.0,
+            },
+        }
+    }
+}
+
+// Two clients are seated in a room whose game has started, then take turns as the input says
+fuzz_target!(|turns: Vec<Turn>| {
+    let time_control = TimeControl {
+        turn: Duration::from_secs(30),
+        bank: Duration::from_secs(60),
+        max_timeouts: 3,
+    };
+    let mut server = Server::new(RuleSet::default()).with_time_control(Some(time_control));
+    let mut seen = Seen::default();
+    for id in 1..=2 {
+        server.client_connected(id);
+        let name = format!("Fuzzer {}", id);
+        let hello = ClientMessage::Hello {
+            version: PROTOCOL_VERSION,
+            name,
+        };
+        server.handle_message(id, &encode(&hello).unwrap());
+    }
+    let create = ClientMessage::CreateRoom { rules: None };
+    server.handle_message(1, &encode(&create).unwrap());
+    let code = server
+        .drain_outbox()
+        .find_map(|(_, message)| match decode(&message) {
+            Ok(ServerMessage::RoomJoined { code, .. }) => Some(code),
+            _ => None,
+        })
+        .unwrap();
+    server.handle_message(2, &encode(&ClientMessage::JoinRoom { code }).unwrap());
+    seen.deliver(&mut server);
+    assert_ne!(seen.active_player, 0, "The game didn't start");
+
+    let mut now = Duration::ZERO;
+    for turn in turns {
+        // Never faster than the rate limit lets an honest client go
+        now += Duration::from_millis(100) + Duration::from_secs(turn.wait.into());
+        server.update(now);
+        seen.deliver(&mut server);
+        let id = match turn.out_of_turn {
+            false => seen.active_player,
+            true => 3 - seen.active_player,
+        };
+        let message = seen.message(id, &turn.play);
+        server.handle_message(id, &encode(&message).unwrap());
+        seen.deliver(&mut server);
+    }
+});
@@ -1,9 +1,11 @@
 use crate::error::Result;
 
 use bevy::ecs::system::Resource;
+use scopa_lib::card::DeckStyle;
 use serde::{Deserialize, Serialize};
 use std::fs::{read_to_string, File};
 use std::io::Write;
+use std::net::SocketAddr;
 use std::path::Path;
 
 pub const CONFIG_PATH: &str = "config.toml";
@@ -14,16 +16,28 @@ pub const VOLUME_STEP: f32 = MAX_VOLUME / 10.0;
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 struct PlayerInfo {
     name: String,
+    // Proves to servers that the name is ours, configs from before it get one on loading
+    #[serde(default = "new_secret")]
+    secret: String,
+}
+
+fn new_secret() -> String {
+    format!("{:032x}", rand::random::<u128>())
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 struct Settings {
     volume: usize,
+    #[serde(default)]
+    deck: DeckStyle,
 }
 
 impl Default for Settings {
     fn default() -> Self {
-        Self { volume: 5 }
+        Self {
+            volume: 5,
+            deck: DeckStyle::default(),
+        }
     }
 }
 
@@ -58,7 +72,10 @@ pub struct Config {
 impl Config {
     pub fn default_with_username(username: String) -> Self {
         Self {
-            player: PlayerInfo { name: username },
+            player: PlayerInfo {
+                name: username,
+                secret: new_secret(),
+            },
             settings: Settings::default(),
             connection: ConnectionInfo::default(),
         }
@@ -97,6 +114,14 @@ impl Config {
         self.settings.volume = volume_level
     }
 
+    pub fn deck_style(&self) -> DeckStyle {
+        self.settings.deck
+    }
+
+    pub fn set_deck_style(&mut self, deck: DeckStyle) {
+        self.settings.deck = deck
+    }
+
     pub fn save(&self) -> Result<()> {
         let mut file = File::create(CONFIG_PATH)?;
         let config_string = toml::to_string(self)?;
@@ -107,4 +132,19 @@ impl Config {
     pub fn connection_str(&self) -> String {
         self.connection.to_string()
     }
+
+    pub fn set_connection(&mut self, addr: SocketAddr) {
+        self.connection = ConnectionInfo {
+            ip: addr.ip().to_string(),
+            port: addr.port().to_string(),
+        };
+    }
+
+    pub fn player_name(&self) -> &str {
+        &self.player.name
+    }
+
+    pub fn player_secret(&self) -> &str {
+        &self.player.secret
+    }
 }
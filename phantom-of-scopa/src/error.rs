@@ -1,3 +1,5 @@
+use bevy_renet::renet::transport::NetcodeError;
+use scopa_lib::protocol::ProtocolError;
 use std::error::Error;
 use std::fmt;
 
@@ -7,6 +9,9 @@ pub type Result<T> = std::result::Result<T, BaseError>;
 pub enum BaseError {
     Gameplay(String),
     Io(std::io::Error),
+    AddrParse(std::net::AddrParseError),
+    Netcode(NetcodeError),
+    Protocol(ProtocolError),
     TomlDeserialize(toml::de::Error),
     TomlSer(toml::ser::Error),
 }
@@ -17,6 +22,9 @@ impl std::fmt::Display for BaseError {
         match self {
             Gameplay(msg) => write!(f, "{}", msg),
             Io(e) => e.fmt(f),
+            AddrParse(e) => e.fmt(f),
+            Netcode(e) => e.fmt(f),
+            Protocol(e) => e.fmt(f),
             TomlDeserialize(e) => e.fmt(f),
             TomlSer(e) => e.fmt(f),
         }
@@ -49,3 +57,21 @@ impl From<toml::ser::Error> for BaseError {
         BaseError::TomlSer(value)
     }
 }
+
+impl From<std::net::AddrParseError> for BaseError {
+    fn from(value: std::net::AddrParseError) -> Self {
+        BaseError::AddrParse(value)
+    }
+}
+
+impl From<NetcodeError> for BaseError {
+    fn from(value: NetcodeError) -> Self {
+        BaseError::Netcode(value)
+    }
+}
+
+impl From<ProtocolError> for BaseError {
+    fn from(value: ProtocolError) -> Self {
+        BaseError::Protocol(value)
+    }
+}
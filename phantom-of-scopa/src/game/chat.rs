@@ -0,0 +1,272 @@
+use super::components::*;
+use super::resources::*;
+use crate::network::{FromServer, LocalPlayer, ToServer};
+use crate::popups::*;
+use crate::styles::*;
+use scopa_lib::protocol::{ClientMessage, Emote, ServerMessage};
+use scopa_lib::PlayerId;
+
+use bevy::prelude::*;
+use bevy_simple_text_input::{TextInputBundle, TextInputInactive, TextInputSubmitEvent};
+
+// Panel in the corner left of the table, collapsed until the player opens it
+pub fn setup_chat(asset_server: Res<AssetServer>, mut commands: Commands) {
+    commands.insert_resource(ChatLog::default());
+    let chat_text = || TextStyle {
+        font: asset_server.load(DEFAULT_FONT),
+        font_size: CHAT_FONT_SIZE,
+        color: TEXT_COLOR,
+    };
+    let small_button = |width: Val| ButtonBundle {
+        style: Style {
+            width,
+            height: Val::Px(CHAT_BUTTON_HEIGHT),
+            border: UiRect::all(Val::Px(2.0)),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            margin: UiRect::all(Val::Px(2.0)),
+            ..default()
+        },
+        background_color: DEFAULT_BG.into(),
+        border_color: INACTIVE_UI.into(),
+        ..default()
+    };
+    commands
+        .spawn((
+            InGameComponent,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(CHAT_X),
+                    top: Val::Px(CHAT_Y),
+                    width: Val::Px(CHAT_WIDTH),
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((ChatToggleButton, small_button(Val::Percent(100.0))))
+                .with_children(|button| {
+                    button.spawn(TextBundle::from_section("Chat", chat_text()));
+                });
+            parent
+                .spawn((
+                    ChatPanel,
+                    NodeBundle {
+                        style: Style {
+                            display: Display::None,
+                            flex_direction: FlexDirection::Column,
+                            ..default()
+                        },
+                        background_color: DEFAULT_BG.into(),
+                        ..default()
+                    },
+                ))
+                .with_children(|panel| {
+                    panel
+                        .spawn(NodeBundle {
+                            style: Style {
+                                height: Val::Px(CHAT_LOG_HEIGHT),
+                                padding: UiRect::all(Val::Px(4.0)),
+                                flex_direction: FlexDirection::Column,
+                                justify_content: JustifyContent::End,
+                                overflow: Overflow::clip(),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|log| {
+                            log.spawn((ChatLogText, TextBundle::from_section("", chat_text())));
+                        });
+                    panel.spawn((
+                        ChatInput,
+                        NodeBundle {
+                            style: Style {
+                                border: UiRect::all(Val::Px(2.0)),
+                                padding: UiRect::all(Val::Px(3.0)),
+                                margin: UiRect::all(Val::Px(2.0)),
+                                ..default()
+                            },
+                            background_color: Color::WHITE.into(),
+                            border_color: INACTIVE_UI.into(),
+                            ..default()
+                        },
+                        TextInputBundle::default()
+                            .with_text_style(TextStyle {
+                                font: asset_server.load(DEFAULT_FONT),
+                                font_size: CHAT_FONT_SIZE,
+                                color: Color::BLACK,
+                            })
+                            .with_inactive(true),
+                    ));
+                    panel
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_wrap: FlexWrap::Wrap,
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|emotes| {
+                            for emote in Emote::ALL {
+                                emotes
+                                    .spawn((EmoteButton(emote), small_button(Val::Auto)))
+                                    .with_children(|button| {
+                                        button.spawn(TextBundle::from_section(
+                                            emote.to_string(),
+                                            chat_text(),
+                                        ));
+                                    });
+                            }
+                        });
+                    panel
+                        .spawn((MuteButton, small_button(Val::Auto)))
+                        .with_children(|button| {
+                            button.spawn(TextBundle::from_section("Mute others", chat_text()));
+                        });
+                });
+        });
+}
+
+// Typing goes to the chat only while the panel is open
+pub fn toggle_chat(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<ChatToggleButton>)>,
+    mut panel_q: Query<&mut Style, With<ChatPanel>>,
+    mut input_q: Query<&mut TextInputInactive, With<ChatInput>>,
+) {
+    let Ok(Interaction::Pressed) = interactions.get_single() else {
+        return;
+    };
+    let (Ok(mut panel), Ok(mut inactive)) = (panel_q.get_single_mut(), input_q.get_single_mut())
+    else {
+        return;
+    };
+    let open = panel.display == Display::None;
+    panel.display = if open { Display::Flex } else { Display::None };
+    inactive.0 = !open;
+}
+
+pub fn send_chat(
+    mut text_input_events: EventReader<TextInputSubmitEvent>,
+    input_q: Query<(), With<ChatInput>>,
+    mut to_server: EventWriter<ToServer>,
+) {
+    for input in text_input_events.read() {
+        if input_q.contains(input.entity) && !input.value.trim().is_empty() {
+            to_server.send(ToServer(ClientMessage::Chat {
+                text: input.value.clone(),
+            }));
+        }
+    }
+}
+
+pub fn emote_buttons(
+    interactions: Query<(&Interaction, &EmoteButton), Changed<Interaction>>,
+    mut to_server: EventWriter<ToServer>,
+) {
+    for (interaction, EmoteButton(emote)) in &interactions {
+        if *interaction == Interaction::Pressed {
+            to_server.send(ToServer(ClientMessage::Emote { emote: *emote }));
+        }
+    }
+}
+
+// Mutes everybody at the table and whoever else was heard, the server stops sending their chat
+pub fn mute_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<MuteButton>)>,
+    children_q: Query<&Children, With<MuteButton>>,
+    mut text_q: Query<&mut Text>,
+    mut chat_log: ResMut<ChatLog>,
+    seats: Res<Seats>,
+    local_player: Res<LocalPlayer>,
+    mut to_server: EventWriter<ToServer>,
+) {
+    let Ok(Interaction::Pressed) = interactions.get_single() else {
+        return;
+    };
+    chat_log.muted = !chat_log.muted;
+    let muted = chat_log.muted;
+    let others: Vec<PlayerId> = seats
+        .ids()
+        .chain(chat_log.speakers())
+        .filter(|id| *id != local_player.0)
+        .collect();
+    for id in others {
+        to_server.send(ToServer(ClientMessage::Mute { id, muted }));
+    }
+    for child in children_q.iter().flatten() {
+        if let Ok(mut text) = text_q.get_mut(*child) {
+            text.sections[0].value = if muted {
+                "Unmute others"
+            } else {
+                "Mute others"
+            }
+            .into();
+        }
+    }
+}
+
+// Emotes of the others show over the top of the table, our own over the hand
+pub fn handle_chat_messages(
+    mut from_server: EventReader<FromServer>,
+    mut chat_log: ResMut<ChatLog>,
+    seats: Res<Seats>,
+    local_player: Res<LocalPlayer>,
+    mut to_server: EventWriter<ToServer>,
+    mut popup_events: EventWriter<PopUpEvent>,
+) {
+    let me = local_player.0;
+    for FromServer(message) in from_server.read() {
+        let id = match message {
+            ServerMessage::Chat { id, .. } | ServerMessage::Emote { id, .. } => *id,
+            ServerMessage::ChatRejected { reason } => {
+                popup_events.send(PopUpEvent {
+                    text: reason.to_string(),
+                    ..default()
+                });
+                continue;
+            }
+            _ => continue,
+        };
+        // Somebody new spoke up while the others are muted, mute them too
+        if chat_log.heard(id) && chat_log.muted && id != me {
+            to_server.send(ToServer(ClientMessage::Mute { id, muted: true }));
+            continue;
+        }
+        match message {
+            ServerMessage::Chat { name, text, .. } => {
+                chat_log.push(format!("{}: {}", name, text));
+            }
+            ServerMessage::Emote { emote, .. } if id == me => {
+                popup_events.send(emote_popup(emote.to_string(), PopUpLocation::Bottom));
+            }
+            ServerMessage::Emote { emote, .. } => {
+                let text = format!("{}: {}", seats.name(id), emote);
+                popup_events.send(emote_popup(text, PopUpLocation::Top));
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn update_chat_log(chat_log: Res<ChatLog>, mut text_q: Query<&mut Text, With<ChatLogText>>) {
+    if !chat_log.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = text_q.get_single_mut() {
+        text.sections[0].value = chat_log.text();
+    }
+}
+
+fn emote_popup(text: String, location: PopUpLocation) -> PopUpEvent {
+    PopUpEvent {
+        text,
+        duration: 2.0,
+        location,
+        width: Val::Percent(30.0),
+        height: Val::Percent(8.0),
+    }
+}
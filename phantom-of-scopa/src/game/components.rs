@@ -1,6 +1,8 @@
 use bevy::ecs::component::Component;
 use bevy::ecs::entity::Entity;
 use scopa_lib::card::*;
+use scopa_lib::french::*;
+use scopa_lib::protocol::Emote;
 
 #[derive(Component, Debug)]
 pub struct InGameComponent;
@@ -41,11 +43,42 @@ pub struct SoundEffect;
 #[derive(Component, Debug)]
 pub struct TableArea;
 
+#[derive(Component, Debug)]
+pub struct PilesArea;
+
+#[derive(Component, Debug)]
+pub struct TurnTimerText;
+
+#[derive(Component, Debug)]
+pub struct ChatToggleButton;
+
+#[derive(Component, Debug)]
+pub struct ChatPanel;
+
+#[derive(Component, Debug)]
+pub struct ChatLogText;
+
+#[derive(Component, Debug)]
+pub struct FairnessBadge;
+
+#[derive(Component, Debug)]
+pub struct ChatInput;
+
+#[derive(Component, Debug)]
+pub struct MuteButton;
+
+#[derive(Component, Debug)]
+pub struct EmoteButton(pub Emote);
+
 #[derive(Component, Debug)]
 pub struct TableSlot;
 
 #[derive(Component, Debug)]
-pub struct TableCard;
+pub struct TableCard(pub UiCard);
+
+// Card in one of the taken piles shown to spectators
+#[derive(Component, Debug)]
+pub struct PileCard(pub UiCard);
 
 #[derive(Component, Debug)]
 pub struct Draggable;
@@ -83,15 +116,40 @@ impl UiCard {
         Self { card }
     }
 
-    pub fn asset_path(&self) -> String {
-        use Suite::*;
-        let suite = match self.card.suite {
-            Clubs => "clubs",
-            Coins => "coins",
-            Cups => "cups",
-            Swords => "swords",
-        };
-        let value = self.card.value().to_string();
-        format!("cards/{}_{}.png", suite, value)
+    pub fn card(&self) -> Card {
+        self.card
+    }
+
+    pub fn asset_path(&self, style: DeckStyle) -> String {
+        match style {
+            DeckStyle::Italian => {
+                use Suite::*;
+                let suite = match self.card.suite {
+                    Clubs => "clubs",
+                    Coins => "coins",
+                    Cups => "cups",
+                    Swords => "swords",
+                };
+                let value = self.card.value().to_string();
+                format!("cards/{}_{}.png", suite, value)
+            }
+            DeckStyle::French => {
+                let card = FrenchCard::from(self.card);
+                let suit = match card.suit {
+                    FrenchSuit::Hearts => "hearts",
+                    FrenchSuit::Diamonds => "diamonds",
+                    FrenchSuit::Clubs => "clubs",
+                    FrenchSuit::Spades => "spades",
+                };
+                let rank = match card.rank {
+                    FrenchRank::Ace => "ace".into(),
+                    FrenchRank::Jack => "jack".into(),
+                    FrenchRank::Queen => "queen".into(),
+                    FrenchRank::King => "king".into(),
+                    _ => self.card.value().to_string(),
+                };
+                format!("cards/french/{}_{}.png", suit, rank)
+            }
+        }
     }
 }
@@ -0,0 +1,84 @@
+use super::components::*;
+use super::resources::*;
+use crate::network::{FromServer, LocalPlayer, RoomRules};
+use crate::popups::*;
+use crate::styles::*;
+use scopa_lib::fairness::{DeckAudit, FairnessError};
+use scopa_lib::protocol::ServerMessage;
+use scopa_lib::PlayerId;
+
+use bevy::prelude::*;
+
+// Stays empty until the first round is checked
+pub fn setup_fairness_badge(asset_server: Res<AssetServer>, mut commands: Commands) {
+    commands.spawn((
+        InGameComponent,
+        FairnessBadge,
+        TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load(DEFAULT_FONT),
+                    font_size: CHAT_FONT_SIZE,
+                    color: FAIR_COLOR,
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(FAIRNESS_BADGE_X),
+                top: Val::Px(FAIRNESS_BADGE_Y),
+                ..default()
+            },
+            ..default()
+        },
+    ));
+}
+
+// Follows the deal of every round and checks it against the seed the server reveals at its end
+pub fn audit_deck(
+    mut from_server: EventReader<FromServer>,
+    mut audit: Local<DeckAudit>,
+    seats: Res<Seats>,
+    rules: Option<Res<RoomRules>>,
+    local_player: Res<LocalPlayer>,
+    mut badge_q: Query<&mut Text, With<FairnessBadge>>,
+    mut popup_events: EventWriter<PopUpEvent>,
+) {
+    for FromServer(message) in from_server.read() {
+        match message {
+            ServerMessage::RoundStarted { active_player } => audit.start(*active_player),
+            ServerMessage::DeckCommitted { commitment } => audit.committed(*commitment),
+            ServerMessage::TableRedealt { table } | ServerMessage::TablePlaced { table } => {
+                audit.table(*table);
+            }
+            ServerMessage::HandDealt { hand } => audit.hand(*hand),
+            ServerMessage::CardPut { id, card }
+            | ServerMessage::CardsTaken { id, with: card, .. } => audit.played(*id, *card),
+            // Deals made before we came in can't be checked
+            ServerMessage::Resumed { .. } | ServerMessage::Spectating { .. } => {
+                *audit = DeckAudit::default();
+            }
+            ServerMessage::DeckRevealed { seed } => {
+                let (Some(rules), Ok(mut badge)) = (rules.as_deref(), badge_q.get_single_mut())
+                else {
+                    continue;
+                };
+                let seats: Vec<PlayerId> = seats.ids().collect();
+                let badge = &mut badge.sections[0];
+                match audit.verify(*seed, &seats, local_player.0, rules.0.redeal) {
+                    Ok(()) => {
+                        badge.value = "Verified fair".into();
+                        badge.style.color = FAIR_COLOR;
+                    }
+                    Err(FairnessError::NotCommitted) => {}
+                    Err(e) => {
+                        badge.value = "Unfair deal!".into();
+                        badge.style.color = UNFAIR_COLOR;
+                        popup_events.send(error_popup(format!("Shuffle check failed: {}", e)));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
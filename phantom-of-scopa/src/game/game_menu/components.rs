@@ -1,4 +1,5 @@
 use bevy::prelude::Component;
+use scopa_lib::protocol::Difficulty;
 
 #[derive(Component, Debug)]
 pub struct InGameMenuUI;
@@ -22,7 +23,13 @@ pub struct SettingsButton;
 pub struct SelectedVolume;
 
 #[derive(Component, Debug)]
-pub struct MainMenuButton;
+pub struct LeaveRoomButton;
+
+#[derive(Component, Debug)]
+pub struct AddBotButton(pub Difficulty);
 
 #[derive(Component, Debug)]
 pub struct BackToRootButton;
+
+#[derive(Component, Debug)]
+pub struct DeckStyleButton;
@@ -45,13 +45,17 @@ pub fn game_menu_plugin(app: &mut App) {
             create_settings_in_game_menu,
         )
         // .add_systems(Update, (highlight_buttons).in_set(InGameMenuSet))
-        .add_systems(Update, (open_settings, main_menu).in_set(RootInGameMenuSet))
+        .add_systems(
+            Update,
+            (open_settings, add_bot, leave_room).in_set(RootInGameMenuSet),
+        )
         .add_systems(
             Update,
             (
                 back_to_root,
                 highlight_volume_buttons,
                 selected_volume_button,
+                deck_style_button,
             )
                 .in_set(SettingsSet),
         )
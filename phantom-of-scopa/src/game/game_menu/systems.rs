@@ -2,10 +2,13 @@ use super::components::*;
 use super::InGameMenuState;
 use crate::config::Config;
 use crate::game::GameState;
+use crate::network::{Session, Spectator, ToServer};
 use crate::popups::PopUpEvent;
 use crate::styles::*;
 use crate::AppState;
 use bevy::prelude::*;
+use scopa_lib::card::DeckStyle;
+use scopa_lib::protocol::{ClientMessage, Difficulty};
 
 pub fn setup_menu(mut commands: Commands, mut next_state: ResMut<NextState<InGameMenuState>>) {
     commands.spawn((
@@ -32,6 +35,7 @@ pub fn setup_menu(mut commands: Commands, mut next_state: ResMut<NextState<InGam
 
 pub fn create_root_in_game_menu(
     root_q: Query<Entity, With<InGameMenuRootNode>>,
+    spectator: Option<Res<Spectator>>,
     mut popup_events: EventWriter<PopUpEvent>,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
@@ -63,11 +67,56 @@ pub fn create_root_in_game_menu(
                         },
                     ));
                 });
+            // Server only lets the host of the room add bots to free seats
+            if spectator.is_none() {
+                parent.spawn((
+                    InGameMenuUI,
+                    RootInGameMenuUI,
+                    TextBundle {
+                        text: default_text("Add a bot", &asset_server),
+                        ..default()
+                    },
+                ));
+                parent
+                    .spawn((
+                        InGameMenuUI,
+                        RootInGameMenuUI,
+                        NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Row,
+                                ..default()
+                            },
+                            ..default()
+                        },
+                    ))
+                    .with_children(|row| {
+                        for difficulty in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard] {
+                            let mut button = default_button();
+                            button.style.width = Val::Px(BOT_BUTTON_WIDTH);
+                            row.spawn((
+                                InGameMenuUI,
+                                RootInGameMenuUI,
+                                AddBotButton(difficulty),
+                                button,
+                            ))
+                            .with_children(|button| {
+                                button.spawn((
+                                    InGameMenuUI,
+                                    RootInGameMenuUI,
+                                    TextBundle {
+                                        text: default_text(&difficulty.to_string(), &asset_server),
+                                        ..default()
+                                    },
+                                ));
+                            });
+                        }
+                    });
+            }
             parent
                 .spawn((
                     InGameMenuUI,
                     RootInGameMenuUI,
-                    MainMenuButton,
+                    LeaveRoomButton,
                     default_button(),
                 ))
                 .with_children(|button| {
@@ -75,7 +124,7 @@ pub fn create_root_in_game_menu(
                         InGameMenuUI,
                         RootInGameMenuUI,
                         TextBundle {
-                            text: default_text("Main menu", &asset_server),
+                            text: default_text("Leave room", &asset_server),
                             ..default()
                         },
                     ));
@@ -104,14 +153,34 @@ pub fn open_settings(
     }
 }
 
-pub fn main_menu(
-    main_menu_button_q: Query<&Interaction, (Changed<Interaction>, With<MainMenuButton>)>,
+pub fn leave_room(
+    leave_room_button_q: Query<&Interaction, (Changed<Interaction>, With<LeaveRoomButton>)>,
     mut app_state: ResMut<NextState<AppState>>,
     mut game_state: ResMut<NextState<GameState>>,
+    mut to_server: EventWriter<ToServer>,
+    mut commands: Commands,
 ) {
-    if let Ok(Interaction::Pressed) = main_menu_button_q.get_single() {
+    if let Ok(Interaction::Pressed) = leave_room_button_q.get_single() {
+        to_server.send(ToServer(ClientMessage::LeaveRoom));
+        commands.remove_resource::<Session>();
+        commands.remove_resource::<Spectator>();
         game_state.set(GameState::Playing);
-        app_state.set(AppState::MainMenu);
+        app_state.set(AppState::Lobby);
+    }
+}
+
+pub fn add_bot(
+    add_bot_button_q: Query<(&Interaction, &AddBotButton), Changed<Interaction>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut to_server: EventWriter<ToServer>,
+) {
+    for (interaction, AddBotButton(difficulty)) in &add_bot_button_q {
+        if *interaction == Interaction::Pressed {
+            to_server.send(ToServer(ClientMessage::AddBot {
+                difficulty: *difficulty,
+            }));
+            game_state.set(GameState::Playing);
+        }
     }
 }
 
@@ -161,6 +230,26 @@ pub fn create_settings_in_game_menu(
                         }
                     }
                 });
+            parent.spawn((
+                InGameMenuUI,
+                SettingsUi,
+                TextBundle {
+                    text: default_text("Cards", &asset_server),
+                    ..default()
+                },
+            ));
+            parent
+                .spawn((InGameMenuUI, SettingsUi, DeckStyleButton, default_button()))
+                .with_children(|button| {
+                    button.spawn((
+                        InGameMenuUI,
+                        SettingsUi,
+                        TextBundle {
+                            text: default_text(deck_style_name(config.deck_style()), &asset_server),
+                            ..default()
+                        },
+                    ));
+                });
             parent
                 .spawn((InGameMenuUI, SettingsUi, BackToRootButton, default_button()))
                 .with_children(|button| {
@@ -248,6 +337,34 @@ pub fn selected_volume_button(
     }
 }
 
+fn deck_style_name(style: DeckStyle) -> &'static str {
+    match style {
+        DeckStyle::Italian => "Italian",
+        DeckStyle::French => "French",
+    }
+}
+
+pub fn deck_style_button(
+    interaction_q: Query<(&Interaction, &Children), (Changed<Interaction>, With<DeckStyleButton>)>,
+    mut text_q: Query<&mut Text>,
+    mut config: ResMut<Config>,
+    mut popup_events: EventWriter<PopUpEvent>,
+) {
+    if let Ok((Interaction::Pressed, children)) = interaction_q.get_single() {
+        let style = match config.deck_style() {
+            DeckStyle::Italian => DeckStyle::French,
+            DeckStyle::French => DeckStyle::Italian,
+        };
+        config.set_deck_style(style);
+        if let Ok(mut text) = text_q.get_mut(children[0]) {
+            text.sections[0].value = deck_style_name(style).into();
+        }
+        if let Err(e) = config.save() {
+            popup_events.send(error_popup(e.to_string()));
+        }
+    }
+}
+
 pub fn back_to_root(
     settings_button_q: Query<
         &Interaction,
@@ -1,14 +1,25 @@
 #![allow(clippy::type_complexity)]
+mod chat;
 mod components;
+mod fairness;
 mod game_menu;
 mod resources;
+mod server_messages;
+mod spectator;
 mod systems;
+mod turn_timer;
 
 use bevy::prelude::*;
 use components::InGameComponent;
 
 use super::{despawn_screen, AppState};
+use crate::network::{LocalPlayer, Spectator};
+use chat::*;
+use fairness::*;
+use server_messages::handle_server_messages;
+use spectator::*;
 use systems::*;
+use turn_timer::*;
 
 #[derive(States, Hash, Debug, PartialEq, Eq, Copy, Clone, Default)]
 enum GameState {
@@ -49,8 +60,69 @@ pub fn game_plugin(app: &mut App) {
                 DragAndDrop.in_set(PlayerSet),
             ),
         )
-        .add_systems(OnEnter(AppState::InGame), game_setup)
+        .add_systems(
+            OnEnter(AppState::InGame),
+            (
+                game_setup,
+                setup_turn_timer,
+                setup_chat,
+                setup_fairness_badge,
+            ),
+        )
+        .add_systems(
+            OnEnter(AppState::InGame),
+            setup_piles_area.run_if(resource_exists::<Spectator>),
+        )
+        .add_systems(
+            Update,
+            update_piles
+                .after(handle_server_messages)
+                .in_set(InGameSet)
+                .run_if(resource_exists::<Spectator>),
+        )
+        .add_systems(
+            Update,
+            update_turn_timer
+                .after(handle_server_messages)
+                .in_set(InGameSet)
+                .run_if(resource_exists::<LocalPlayer>),
+        )
+        .add_systems(
+            Update,
+            audit_deck
+                .after(handle_server_messages)
+                .in_set(InGameSet)
+                .run_if(resource_exists::<LocalPlayer>),
+        )
+        .add_systems(
+            Update,
+            (
+                toggle_chat,
+                send_chat,
+                emote_buttons,
+                mute_button,
+                handle_chat_messages,
+                update_chat_log.after(handle_chat_messages),
+            )
+                .in_set(InGameSet)
+                .run_if(resource_exists::<LocalPlayer>),
+        )
         .add_systems(Update, (toggle_in_game_menu, update_hand).in_set(InGameSet))
+        .add_systems(
+            Update,
+            (reskin_cards, fall_back_to_italian_cards)
+                .chain()
+                .after(update_hand)
+                .after(update_piles)
+                .in_set(InGameSet),
+        )
+        .add_systems(
+            Update,
+            handle_server_messages
+                .before(update_hand)
+                .in_set(InGameSet)
+                .run_if(resource_exists::<LocalPlayer>),
+        )
         .add_systems(
             Update,
             (
@@ -1,6 +1,14 @@
 use bevy::prelude::*;
+use scopa_lib::card::Card;
+use scopa_lib::rules::TEAM_PLAYERS;
+use scopa_lib::PlayerId;
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
 use std::vec::Drain;
 
+// Older chat lines scroll out of the panel
+const CHAT_LOG_LINES: usize = 10;
+
 #[derive(Resource)]
 pub struct SelectedCardImage(pub Handle<Image>);
 
@@ -45,6 +53,12 @@ impl TableSlots {
         self.slots.push(TableSlotEntity::new(entity));
     }
 
+    pub fn release(&mut self, slot: Entity) {
+        if let Some(slot) = self.slots.iter_mut().find(|s| s.id() == slot) {
+            slot.free();
+        }
+    }
+
     pub fn free_slot(&mut self) -> Option<Entity> {
         for i in 0..self.slots.len() {
             if self.slots[i].is_vacant() {
@@ -91,3 +105,200 @@ impl DragCursor {
         self.to_drag.take()
     }
 }
+
+// Chat of the current game, oldest line first
+#[derive(Resource, Debug, Default)]
+pub struct ChatLog {
+    lines: VecDeque<String>,
+    // Everybody heard from so far, muting applies to all of them
+    speakers: HashSet<PlayerId>,
+    pub muted: bool,
+}
+
+impl ChatLog {
+    pub fn push(&mut self, line: String) {
+        self.lines.push_back(line);
+        if self.lines.len() > CHAT_LOG_LINES {
+            self.lines.pop_front();
+        }
+    }
+
+    // Whether the player is heard for the first time
+    pub fn heard(&mut self, id: PlayerId) -> bool {
+        self.speakers.insert(id)
+    }
+
+    pub fn speakers(&self) -> impl Iterator<Item = PlayerId> + '_ {
+        self.speakers.iter().copied()
+    }
+
+    pub fn text(&self) -> String {
+        self.lines
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+// Players at the table in the order they move
+#[derive(Resource, Debug, Default)]
+pub struct Seats {
+    players: Vec<(PlayerId, String)>,
+    active: PlayerId,
+}
+
+impl Seats {
+    pub fn clear(&mut self) {
+        self.players.clear();
+    }
+
+    pub fn join(&mut self, id: PlayerId, name: String) {
+        self.players.push((id, name));
+    }
+
+    pub fn leave(&mut self, id: PlayerId) {
+        self.players.retain(|(player, _)| *player != id);
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = PlayerId> + '_ {
+        self.players.iter().map(|(id, _)| *id)
+    }
+
+    pub fn name(&self, id: PlayerId) -> &str {
+        self.players
+            .iter()
+            .find(|(player, _)| *player == id)
+            .map_or("Somebody", |(_, name)| name.as_str())
+    }
+
+    pub fn active(&self) -> PlayerId {
+        self.active
+    }
+
+    // Player sitting opposite at a table of four, who plays in the same team
+    pub fn partner(&self, id: PlayerId) -> Option<PlayerId> {
+        if self.players.len() != TEAM_PLAYERS {
+            return None;
+        }
+        let seat = self.players.iter().position(|(player, _)| *player == id)?;
+        Some(self.players[(seat + 2) % TEAM_PLAYERS].0)
+    }
+
+    pub fn set_active(&mut self, id: PlayerId) {
+        self.active = id;
+    }
+
+    // Turn passes to the player seated after the one who just moved
+    pub fn moved(&mut self, id: PlayerId) {
+        if let Some(seat) = self.players.iter().position(|(player, _)| *player == id) {
+            self.active = self.players[(seat + 1) % self.players.len()].0;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Pile {
+    pub id: PlayerId,
+    pub points: u8,
+    pub hand: usize,
+    pub taken: Vec<Card>,
+}
+
+impl Pile {
+    pub fn new(id: PlayerId) -> Self {
+        Self {
+            id,
+            points: 0,
+            hand: 0,
+            taken: Vec::new(),
+        }
+    }
+}
+
+// What everybody can see of the players, shown to spectators
+#[derive(Resource, Debug, Default)]
+pub struct Piles {
+    piles: Vec<Pile>,
+}
+
+impl Piles {
+    pub fn set(&mut self, piles: Vec<Pile>) {
+        self.piles = piles;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Pile> {
+        self.piles.iter()
+    }
+
+    pub fn join(&mut self, id: PlayerId) {
+        self.piles.push(Pile::new(id));
+    }
+
+    pub fn leave(&mut self, id: PlayerId) {
+        self.piles.retain(|pile| pile.id != id);
+    }
+
+    pub fn new_round(&mut self) {
+        for pile in self.piles.iter_mut() {
+            pile.taken.clear();
+        }
+    }
+
+    pub fn dealt(&mut self, id: PlayerId) {
+        if let Some(pile) = self.get_mut(id) {
+            pile.hand = 3;
+        }
+    }
+
+    pub fn played(&mut self, id: PlayerId) {
+        if let Some(pile) = self.get_mut(id) {
+            pile.hand = pile.hand.saturating_sub(1);
+        }
+    }
+
+    pub fn took(&mut self, id: PlayerId, cards: impl IntoIterator<Item = Card>) {
+        if let Some(pile) = self.get_mut(id) {
+            pile.taken.extend(cards);
+        }
+    }
+
+    pub fn scored(&mut self, id: PlayerId, points: u8) {
+        if let Some(pile) = self.get_mut(id) {
+            pile.points += points;
+        }
+    }
+
+    fn get_mut(&mut self, id: PlayerId) -> Option<&mut Pile> {
+        self.piles.iter_mut().find(|pile| pile.id == id)
+    }
+}
+
+// Clock of the player to move as last reported by the server, counted down locally
+#[derive(Resource, Debug)]
+pub struct TurnTimer {
+    pub id: PlayerId,
+    turn: Duration,
+    bank: Duration,
+}
+
+impl TurnTimer {
+    pub fn new(id: PlayerId, turn: Duration, bank: Duration) -> Self {
+        Self { id, turn, bank }
+    }
+
+    // Turn time runs out first, then the bank
+    pub fn tick(&mut self, delta: Duration) {
+        let overtime = delta.saturating_sub(self.turn);
+        self.turn = self.turn.saturating_sub(delta);
+        self.bank = self.bank.saturating_sub(overtime);
+    }
+
+    pub fn turn(&self) -> Duration {
+        self.turn
+    }
+
+    pub fn bank(&self) -> Duration {
+        self.bank
+    }
+}
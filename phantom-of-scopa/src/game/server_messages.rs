@@ -0,0 +1,455 @@
+use super::components::*;
+use super::resources::*;
+use super::systems::{play_audio, put_card_on_table, GameEvent};
+use super::ScopaState;
+use crate::config::Config;
+use crate::network::{FromServer, LocalPlayer, RoomRules, Session, Spectator};
+use crate::popups::*;
+use crate::styles::*;
+use crate::AppState;
+use scopa_lib::card::Card;
+use scopa_lib::protocol::{PileState, RoundScore, SeatState, ServerMessage};
+
+use bevy::prelude::*;
+
+#[allow(clippy::too_many_arguments)]
+pub fn handle_server_messages(
+    mut from_server: EventReader<FromServer>,
+    local_player: Res<LocalPlayer>,
+    mut seats: ResMut<Seats>,
+    mut piles: ResMut<Piles>,
+    mut table_slots: ResMut<TableSlots>,
+    hand_cards_q: Query<(Entity, &PlayerCard)>,
+    table_cards_q: Query<(Entity, &TableCard, &Parent)>,
+    selected_table_cards_q: Query<Entity, (With<TableCard>, With<SelectedCard>)>,
+    mut game_events: EventWriter<GameEvent>,
+    mut scopa_state: ResMut<NextState<ScopaState>>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut popup_events: EventWriter<PopUpEvent>,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    mut commands: Commands,
+) {
+    let me = local_player.0;
+    let mut turn_changed = false;
+    let mut waiting = false;
+    for FromServer(message) in from_server.read() {
+        match message {
+            ServerMessage::PlayerJoined { id, name } => {
+                seats.join(*id, name.clone());
+                piles.join(*id);
+                if *id != me {
+                    popup_events.send(PopUpEvent {
+                        text: format!("{} joined the game", name),
+                        location: PopUpLocation::Top,
+                        ..default()
+                    });
+                }
+            }
+            ServerMessage::PlayerLeft { id, name } => {
+                commands.remove_resource::<TurnTimer>();
+                seats.leave(*id);
+                piles.leave(*id);
+                clear_table(&mut table_slots, &mut commands);
+                for (card, _) in &hand_cards_q {
+                    commands.entity(card).despawn_recursive();
+                }
+                waiting = true;
+                popup_events.send(PopUpEvent {
+                    text: format!("{} left the game. Waiting for another player...", name),
+                    duration: 5.0,
+                    location: PopUpLocation::Center,
+                    ..default()
+                });
+            }
+            ServerMessage::RoundStarted { active_player } => {
+                seats.set_active(*active_player);
+                piles.new_round();
+                clear_table(&mut table_slots, &mut commands);
+                turn_changed = true;
+            }
+            ServerMessage::TablePlaced { table } => {
+                for card in table {
+                    spawn_table_card(
+                        *card,
+                        &mut table_slots,
+                        &asset_server,
+                        &config,
+                        &mut commands,
+                    );
+                }
+            }
+            ServerMessage::TableRedealt { .. } => {
+                popup_events.send(PopUpEvent {
+                    text: "Too many cards of the same value on the table, dealing again".into(),
+                    location: PopUpLocation::Top,
+                    ..default()
+                });
+            }
+            ServerMessage::HandDealt { hand } => {
+                piles.dealt(me);
+                game_events.send(GameEvent::NewHand(hand.to_vec()));
+            }
+            ServerMessage::HandDealtTo { id } => {
+                piles.dealt(*id);
+            }
+            ServerMessage::CardPut { id, card } => {
+                if *id == me {
+                    if let Some((entity, PlayerCard(ui_card))) =
+                        hand_cards_q.iter().find(|(_, c)| c.0.card() == *card)
+                    {
+                        if let Err(e) =
+                            put_card_on_table(entity, *ui_card, &mut table_slots, &mut commands)
+                        {
+                            popup_events.send(error_popup(e.to_string()));
+                        }
+                    }
+                    for selected in &selected_table_cards_q {
+                        commands
+                            .entity(selected)
+                            .remove::<SelectedCard>()
+                            .insert(RemovedCardSelection);
+                    }
+                } else {
+                    spawn_table_card(
+                        *card,
+                        &mut table_slots,
+                        &asset_server,
+                        &config,
+                        &mut commands,
+                    );
+                }
+                play_audio(
+                    asset_server.load("audio/Card_place02.ogg"),
+                    config.volume_as_f32(),
+                    &mut commands,
+                );
+                seats.moved(*id);
+                piles.played(*id);
+                turn_changed = true;
+            }
+            ServerMessage::CardsTaken { id, take, with } => {
+                // Taken cards leave the table right away, so they are named as well
+                if *id != me {
+                    let style = config.deck_style();
+                    let taken: Vec<String> = take.iter().map(|card| card.name(style)).collect();
+                    popup_events.send(PopUpEvent {
+                        text: format!(
+                            "{} took {} with {}",
+                            seats.name(*id),
+                            taken.join(", "),
+                            with.name(style)
+                        ),
+                        location: PopUpLocation::Top,
+                        ..default()
+                    });
+                }
+                piles.played(*id);
+                piles.took(*id, take.iter().copied().chain([*with]));
+                for (entity, TableCard(ui_card), slot) in &table_cards_q {
+                    if take.contains(&ui_card.card()) {
+                        commands.entity(entity).despawn_recursive();
+                        table_slots.release(slot.get());
+                    }
+                }
+                if *id == me {
+                    if let Some((entity, _)) =
+                        hand_cards_q.iter().find(|(_, c)| c.0.card() == *with)
+                    {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+                play_audio(
+                    asset_server.load("audio/Card_place02.ogg"),
+                    config.volume_as_f32(),
+                    &mut commands,
+                );
+                seats.moved(*id);
+                turn_changed = true;
+            }
+            ServerMessage::Scopa { id } => {
+                popup_events.send(PopUpEvent {
+                    text: format!("Scopa! ({})", seats.name(*id)),
+                    location: PopUpLocation::Center,
+                    ..default()
+                });
+            }
+            ServerMessage::RoundEnded { scores } => {
+                for score in scores {
+                    piles.scored(score.id, score.points);
+                }
+                clear_table(&mut table_slots, &mut commands);
+                popup_events.send(PopUpEvent {
+                    text: scores_text(scores, &seats),
+                    duration: 6.0,
+                    location: PopUpLocation::Center,
+                    height: Val::Percent(40.0),
+                    ..default()
+                });
+            }
+            ServerMessage::GameWon { id } => {
+                commands.remove_resource::<TurnTimer>();
+                waiting = true;
+                let winners = match seats.partner(*id) {
+                    Some(partner) => format!("{} and {}", seats.name(*id), seats.name(partner)),
+                    None => seats.name(*id).to_string(),
+                };
+                popup_events.send(PopUpEvent {
+                    text: format!("{} won the game!", winners),
+                    duration: 10.0,
+                    location: PopUpLocation::Center,
+                    ..default()
+                });
+            }
+            ServerMessage::Resumed {
+                rules,
+                seats: seated,
+                hand,
+                table,
+                active_player,
+                ..
+            } => {
+                commands.insert_resource(RoomRules(rules.clone()));
+                seats.clear();
+                for seat in seated {
+                    seats.join(seat.id, seat.name.clone());
+                }
+                seats.set_active(*active_player);
+                piles.set(watched_piles(seated, &[]));
+                clear_table(&mut table_slots, &mut commands);
+                for (card, _) in &hand_cards_q {
+                    commands.entity(card).despawn_recursive();
+                }
+                for card in table {
+                    spawn_table_card(
+                        *card,
+                        &mut table_slots,
+                        &asset_server,
+                        &config,
+                        &mut commands,
+                    );
+                }
+                game_events.send(GameEvent::NewHand(hand.clone()));
+                popup_events.send(PopUpEvent {
+                    text: points_text(seated),
+                    duration: 5.0,
+                    location: PopUpLocation::Center,
+                    ..default()
+                });
+                turn_changed = true;
+            }
+            ServerMessage::Spectating {
+                seats: seated,
+                piles: public,
+                table,
+                active_player,
+                ..
+            } => {
+                seats.clear();
+                for seat in seated {
+                    seats.join(seat.id, seat.name.clone());
+                }
+                piles.set(watched_piles(seated, public));
+                clear_table(&mut table_slots, &mut commands);
+                for card in table {
+                    spawn_table_card(
+                        *card,
+                        &mut table_slots,
+                        &asset_server,
+                        &config,
+                        &mut commands,
+                    );
+                }
+                if let Some(active_player) = active_player {
+                    seats.set_active(*active_player);
+                    turn_changed = true;
+                }
+            }
+            ServerMessage::RoomLeft => {
+                popup_events.send(error_popup("All players left the room".into()));
+                app_state.set(AppState::Lobby);
+            }
+            ServerMessage::PlayerAway { id } => {
+                popup_events.send(PopUpEvent {
+                    text: format!(
+                        "{} lost the connection, waiting for them...",
+                        seats.name(*id)
+                    ),
+                    duration: 5.0,
+                    location: PopUpLocation::Top,
+                    ..default()
+                });
+            }
+            ServerMessage::PlayerBack { id } => {
+                if *id != me {
+                    popup_events.send(PopUpEvent {
+                        text: format!("{} is back", seats.name(*id)),
+                        location: PopUpLocation::Top,
+                        ..default()
+                    });
+                }
+            }
+            ServerMessage::TurnTimer { id, turn, bank } => {
+                commands.insert_resource(TurnTimer::new(*id, *turn, *bank));
+            }
+            ServerMessage::BotTookOver { id } => {
+                popup_events.send(PopUpEvent {
+                    text: format!("{} left, a bot plays for them now", seats.name(*id)),
+                    duration: 5.0,
+                    location: PopUpLocation::Top,
+                    ..default()
+                });
+            }
+            // Rooms are handled by the lobby, but bots are added from the game
+            ServerMessage::RoomRejected { reason } => {
+                popup_events.send(error_popup(reason.to_string()));
+            }
+            ServerMessage::TimedOut { id } => {
+                let text = if *id == me {
+                    "You ran out of time, a card was played for you".into()
+                } else {
+                    format!("{} ran out of time", seats.name(*id))
+                };
+                popup_events.send(PopUpEvent {
+                    text,
+                    location: PopUpLocation::Top,
+                    ..default()
+                });
+            }
+            ServerMessage::Forfeited { id } if *id == me => {
+                commands.remove_resource::<Session>();
+                popup_events.send(error_popup(
+                    "You ran out of time too many times and lost your seat".into(),
+                ));
+                app_state.set(AppState::Lobby);
+            }
+            ServerMessage::Forfeited { id } => {
+                popup_events.send(PopUpEvent {
+                    text: format!("{} forfeited the game", seats.name(*id)),
+                    duration: 5.0,
+                    location: PopUpLocation::Center,
+                    ..default()
+                });
+            }
+            ServerMessage::RoomClosed => {
+                commands.remove_resource::<TurnTimer>();
+                commands.remove_resource::<Session>();
+                commands.remove_resource::<Spectator>();
+                popup_events.send(error_popup("The game was ended by the server".into()));
+                app_state.set(AppState::Lobby);
+            }
+            ServerMessage::MoveRejected { reason } => {
+                popup_events.send(PopUpEvent {
+                    text: reason.clone(),
+                    ..default()
+                });
+            }
+            // Handshake, sessions and announcements are handled by the network plugin, rooms,
+            // ratings and quick matches by the lobby, chat by the chat panel, shuffle checks by
+            // the fairness badge
+            ServerMessage::Welcome { .. }
+            | ServerMessage::Rejected { .. }
+            | ServerMessage::Session { .. }
+            | ServerMessage::RoomList { .. }
+            | ServerMessage::RoomJoined { .. }
+            | ServerMessage::Leaderboard { .. }
+            | ServerMessage::Queued { .. }
+            | ServerMessage::QueueLeft
+            | ServerMessage::BotOffered
+            | ServerMessage::Chat { .. }
+            | ServerMessage::Emote { .. }
+            | ServerMessage::ChatRejected { .. }
+            | ServerMessage::Announcement { .. }
+            | ServerMessage::ShuttingDown { .. }
+            | ServerMessage::DeckCommitted { .. }
+            | ServerMessage::DeckRevealed { .. } => {}
+        }
+    }
+    if waiting {
+        scopa_state.set(ScopaState::Limbo);
+    } else if turn_changed {
+        if seats.active() == me {
+            scopa_state.set(ScopaState::PlayerTurn);
+        } else {
+            scopa_state.set(ScopaState::OpponentTurn);
+        }
+    }
+}
+
+fn scores_text(scores: &[RoundScore], seats: &Seats) -> String {
+    let mut text = String::from("Round is over\n");
+    for score in scores {
+        text.push_str(&format!(
+            "{}: +{} (cards {}, coins {}, primes {}, scopas {}{})\n",
+            seats.name(score.id),
+            score.points,
+            score.cards,
+            score.coins,
+            score.primes,
+            score.scopas,
+            if score.settebello { ", settebello" } else { "" },
+        ));
+    }
+    text
+}
+
+fn watched_piles(seats: &[SeatState], piles: &[PileState]) -> Vec<Pile> {
+    seats
+        .iter()
+        .map(|seat| {
+            let mut pile = Pile::new(seat.id);
+            pile.points = seat.points;
+            if let Some(public) = piles.iter().find(|p| p.id == seat.id) {
+                pile.hand = public.hand as usize;
+                pile.taken = public.taken.clone();
+            }
+            pile
+        })
+        .collect()
+}
+
+fn points_text(seats: &[SeatState]) -> String {
+    let mut text = String::from("Welcome back\n");
+    for seat in seats {
+        text.push_str(&format!("{}: {} points\n", seat.name, seat.points));
+    }
+    text
+}
+
+fn spawn_table_card(
+    card: Card,
+    table_slots: &mut TableSlots,
+    asset_server: &AssetServer,
+    config: &Config,
+    commands: &mut Commands,
+) {
+    let Some(slot) = table_slots.free_slot() else {
+        return;
+    };
+    let ui_card = UiCard::new(card);
+    let card_image = commands
+        .spawn((
+            TableCard(ui_card),
+            CardImage,
+            Interaction::None,
+            ImageBundle {
+                style: Style {
+                    width: Val::Px(CARD_WIDTH),
+                    height: Val::Px(CARD_HEIGHT),
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                image: UiImage::new(asset_server.load(ui_card.asset_path(config.deck_style()))),
+                ..default()
+            },
+        ))
+        .id();
+    commands.entity(slot).add_child(card_image);
+}
+
+fn clear_table(table_slots: &mut TableSlots, commands: &mut Commands) {
+    for slot in table_slots {
+        commands.entity(slot.id()).despawn_descendants();
+        slot.free();
+    }
+}
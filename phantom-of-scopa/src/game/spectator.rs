@@ -0,0 +1,96 @@
+use super::components::*;
+use super::resources::*;
+use crate::config::Config;
+use crate::styles::*;
+
+use bevy::prelude::*;
+
+// Taken piles of all players take the place of the hand
+pub fn setup_piles_area(mut commands: Commands) {
+    commands.spawn((
+        InGameComponent,
+        PilesArea,
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(PILES_X),
+                top: Val::Px(PILES_Y),
+                width: Val::Px(PILES_WIDTH),
+                height: Val::Px(PILES_HEIGHT),
+                flex_direction: FlexDirection::Row,
+                justify_content: JustifyContent::SpaceAround,
+                ..default()
+            },
+            ..default()
+        },
+    ));
+}
+
+pub fn update_piles(
+    piles: Res<Piles>,
+    seats: Res<Seats>,
+    piles_area_q: Query<Entity, With<PilesArea>>,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    mut commands: Commands,
+) {
+    if !piles.is_changed() {
+        return;
+    }
+    let Ok(piles_area) = piles_area_q.get_single() else {
+        return;
+    };
+    commands.entity(piles_area).despawn_descendants();
+    let width = Val::Percent(100.0 / piles.iter().count().max(1) as f32 - 2.0);
+    for pile in piles.iter() {
+        let label = format!(
+            "{}: {} points, {} in hand",
+            seats.name(pile.id),
+            pile.points,
+            pile.hand
+        );
+        commands
+            .spawn(NodeBundle {
+                style: Style {
+                    width,
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: default_text(&label, &asset_server),
+                    ..default()
+                });
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            flex_wrap: FlexWrap::Wrap,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|cards| {
+                        for card in &pile.taken {
+                            let ui_card = UiCard::new(*card);
+                            let path = ui_card.asset_path(config.deck_style());
+                            cards.spawn((
+                                PileCard(ui_card),
+                                ImageBundle {
+                                    style: Style {
+                                        width: Val::Px(PILE_CARD_WIDTH),
+                                        height: Val::Px(PILE_CARD_HEIGHT),
+                                        ..default()
+                                    },
+                                    image: UiImage::new(asset_server.load(path)),
+                                    ..default()
+                                },
+                            ));
+                        }
+                    });
+            })
+            .set_parent(piles_area);
+    }
+}
@@ -1,24 +1,34 @@
 use super::components::*;
 use super::resources::*;
-use super::GameState;
+use super::{GameState, ScopaState};
 use crate::config::Config;
 use crate::error::{BaseError, Result};
+use crate::network::{Spectator, ToServer};
 use crate::popups::*;
 use crate::styles::*;
 use scopa_lib::card::*;
+use scopa_lib::protocol::ClientMessage;
 
+use bevy::asset::LoadState;
 use bevy::audio::Volume;
 use bevy::prelude::*;
 use bevy::ui::RelativeCursorPosition;
-use rand::seq::SliceRandom;
-use rand::thread_rng;
 
 #[derive(Event)]
 pub enum GameEvent {
     NewHand(Vec<Card>),
 }
 
-pub fn game_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn game_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut scopa_state: ResMut<NextState<ScopaState>>,
+    spectator: Option<Res<Spectator>>,
+) {
+    // Nobody moves until the server starts a round
+    scopa_state.set(ScopaState::Limbo);
+    commands.insert_resource(Seats::default());
+    commands.insert_resource(Piles::default());
     // Insert image of selected card as a resource
     commands.insert_resource(SelectedCardImage(asset_server.load("card_selected.png")));
     // Spawn table background image
@@ -49,6 +59,84 @@ pub fn game_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
             });
         });
 
+    // Spectators only watch
+    if spectator.is_none() {
+        spawn_hand_and_buttons(&asset_server, &mut commands);
+    }
+
+    // Spawn table
+    let mut table_slots = TableSlots::new(Vec::with_capacity(10));
+    commands
+        .spawn((
+            InGameComponent,
+            TableArea,
+            Interaction::None,
+            NodeBundle {
+                style: Style {
+                    width: Val::Px(TABLE_WIDTH),
+                    height: Val::Px(TABLE_HEIGHT),
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(TABLE_X),
+                    top: Val::Px(TABLE_Y),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TableArea,
+                DropIn,
+                HighlightImage,
+                RelativeCursorPosition::default(),
+                ImageBundle {
+                    style: Style {
+                        width: Val::Px(TABLE_WIDTH),
+                        height: Val::Px(TABLE_HEIGHT),
+                        ..default()
+                    },
+                    image: UiImage::new(asset_server.load("table_highlight.png")),
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+            ));
+            table_slots.add(create_table_slot(parent, 0, 1));
+            table_slots.add(create_table_slot(parent, 1, 3));
+            table_slots.add(create_table_slot(parent, 1, 1));
+            table_slots.add(create_table_slot(parent, 0, 3));
+            table_slots.add(create_table_slot(parent, 0, 2));
+            table_slots.add(create_table_slot(parent, 1, 2));
+            table_slots.add(create_table_slot(parent, 0, 0));
+            table_slots.add(create_table_slot(parent, 1, 4));
+            table_slots.add(create_table_slot(parent, 1, 0));
+            table_slots.add(create_table_slot(parent, 0, 4));
+        });
+    commands.insert_resource(table_slots);
+
+    // Cursor tracking entity for drag and drop
+    let cursor_entity = DragCursor::new(
+        commands
+            .spawn((
+                InGameComponent,
+                CursorMarker,
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        width: Val::Px(1.0),
+                        height: Val::Px(1.0),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    ..default()
+                },
+            ))
+            .id(),
+    );
+    commands.insert_resource(cursor_entity);
+}
+
+fn spawn_hand_and_buttons(asset_server: &AssetServer, commands: &mut Commands) {
     // Spawn player's hand
     commands
         .spawn((
@@ -127,77 +215,6 @@ pub fn game_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                 HighlightImage,
             ));
         });
-
-    // Spawn table
-    let mut table_slots = TableSlots::new(Vec::with_capacity(10));
-    commands
-        .spawn((
-            InGameComponent,
-            TableArea,
-            Interaction::None,
-            NodeBundle {
-                style: Style {
-                    width: Val::Px(TABLE_WIDTH),
-                    height: Val::Px(TABLE_HEIGHT),
-                    position_type: PositionType::Absolute,
-                    left: Val::Px(TABLE_X),
-                    top: Val::Px(TABLE_Y),
-                    ..default()
-                },
-                ..default()
-            },
-        ))
-        .with_children(|parent| {
-            parent.spawn((
-                TableArea,
-                DropIn,
-                HighlightImage,
-                RelativeCursorPosition::default(),
-                ImageBundle {
-                    style: Style {
-                        width: Val::Px(TABLE_WIDTH),
-                        height: Val::Px(TABLE_HEIGHT),
-                        ..default()
-                    },
-                    image: UiImage::new(asset_server.load("table_highlight.png")),
-                    visibility: Visibility::Hidden,
-                    ..default()
-                },
-            ));
-            table_slots.add(create_table_slot(parent, 0, 1));
-            table_slots.add(create_table_slot(parent, 1, 3));
-            table_slots.add(create_table_slot(parent, 1, 1));
-            table_slots.add(create_table_slot(parent, 0, 3));
-            table_slots.add(create_table_slot(parent, 0, 2));
-            table_slots.add(create_table_slot(parent, 1, 2));
-            table_slots.add(create_table_slot(parent, 0, 0));
-            table_slots.add(create_table_slot(parent, 1, 4));
-            table_slots.add(create_table_slot(parent, 1, 0));
-            table_slots.add(create_table_slot(parent, 0, 4));
-        });
-    commands.insert_resource(table_slots);
-
-    // Cursor tracking entity for drag and drop
-    let cursor_entity = DragCursor::new(
-        commands
-            .spawn((
-                InGameComponent,
-                CursorMarker,
-                NodeBundle {
-                    style: Style {
-                        position_type: PositionType::Absolute,
-                        width: Val::Px(1.0),
-                        height: Val::Px(1.0),
-                        align_items: AlignItems::Center,
-                        justify_content: JustifyContent::Center,
-                        ..default()
-                    },
-                    ..default()
-                },
-            ))
-            .id(),
-    );
-    commands.insert_resource(cursor_entity);
 }
 
 fn create_player_hand_slot(parent: &mut ChildBuilder<'_>, left: Val) -> Entity {
@@ -264,92 +281,52 @@ pub fn button_highlights(
     }
 }
 
-fn random_hand() -> Vec<Card> {
-    let mut rand_hand: Vec<Card> = Vec::with_capacity(3);
-    let mut rng = thread_rng();
-    for _ in 0..3 {
-        let suite = *[Suite::Coins, Suite::Clubs, Suite::Cups, Suite::Swords]
-            .choose(&mut rng)
-            .unwrap();
-        let value = *[
-            CardValue::One,
-            CardValue::Two,
-            CardValue::Three,
-            CardValue::Four,
-            CardValue::Five,
-            CardValue::Six,
-            CardValue::Seven,
-            CardValue::Fante,
-            CardValue::Cavallo,
-            CardValue::Re,
-        ]
-        .choose(&mut rng)
-        .unwrap();
-        rand_hand.push(Card::new(suite, value));
-    }
-    rand_hand
-}
-
 pub fn take_button_pressed(
     interaction_query: Query<&Interaction, (Changed<Interaction>, With<TakeButton>)>,
-    mut game_events: EventWriter<GameEvent>,
+    player_selected_card: Query<&PlayerCard, With<SelectedCard>>,
+    table_selected_cards: Query<&TableCard, With<SelectedCard>>,
+    mut to_server: EventWriter<ToServer>,
+    mut popup_events: EventWriter<PopUpEvent>,
 ) {
     for interaction in &interaction_query {
         if let Interaction::Pressed = *interaction {
-            game_events.send(GameEvent::NewHand(random_hand()));
+            if let Ok(PlayerCard(with)) = player_selected_card.get_single() {
+                let take = table_selected_cards
+                    .iter()
+                    .map(|TableCard(card)| card.card())
+                    .collect();
+                to_server.send(ToServer(ClientMessage::TakeCards {
+                    take,
+                    with: with.card(),
+                }));
+            } else {
+                popup_events.send(PopUpEvent {
+                    text: "Select a card from your hand to take with".into(),
+                    ..default()
+                });
+            }
         }
     }
 }
 
+// Card is moved to the table once the server accepts the move
 pub fn put_button_pressed(
     interaction_query: Query<&Interaction, (Changed<Interaction>, With<PutButton>)>,
-    player_selected_card: Query<Entity, (With<PlayerCard>, With<SelectedCard>)>,
-    table_selected_cards: Query<Entity, (With<TableCard>, With<SelectedCard>)>,
-    asset_server: Res<AssetServer>,
-    config: Res<Config>,
-    mut popup_events: EventWriter<PopUpEvent>,
-    mut table_slots: ResMut<TableSlots>,
-    mut commands: Commands,
+    player_selected_card: Query<&PlayerCard, With<SelectedCard>>,
+    mut to_server: EventWriter<ToServer>,
 ) {
     for interaction in &interaction_query {
         if let Interaction::Pressed = *interaction {
-            // If there is selected card
-            if let Ok(card_id) = player_selected_card.get_single() {
-                match put_card_on_table(card_id, &mut table_slots, &mut commands) {
-                    Ok(_) => {
-                        play_audio(
-                            asset_server.load("audio/Card_place02.ogg"),
-                            config.volume_as_f32(),
-                            &mut commands,
-                        );
-                    }
-                    Err(e) => {
-                        popup_events.send(PopUpEvent {
-                            text: e.to_string(),
-                            duration: 2.0,
-                            ..default()
-                        });
-                    }
-                }
-                // Remove selection from all selected cards on the table
-                for selected_id in &table_selected_cards {
-                    let mut selected_card = commands.entity(selected_id);
-                    selected_card.remove::<SelectedCard>();
-                    selected_card.insert(RemovedCardSelection);
-                }
-            // If no card is selected we clear table FOR TEST REASONS
-            } else {
-                for slot in table_slots.as_mut() {
-                    commands.entity(slot.id()).despawn_descendants();
-                    slot.free();
-                }
+            if let Ok(PlayerCard(card)) = player_selected_card.get_single() {
+                to_server.send(ToServer(ClientMessage::PutCard { card: card.card() }));
             }
         }
     }
 }
 
-fn put_card_on_table(
+pub fn put_card_on_table(
     card_id: Entity,
+    ui_card: UiCard,
     table_slots: &mut ResMut<TableSlots>,
     commands: &mut Commands,
 ) -> Result<()> {
@@ -358,7 +335,7 @@ fn put_card_on_table(
         card.remove::<SelectedCard>();
         card.insert(RemovedCardSelection);
         card.remove::<PlayerCard>();
-        card.insert(TableCard);
+        card.insert(TableCard(ui_card));
         card.remove::<Draggable>();
         card.remove::<RelativeCursorPosition>();
         card.set_parent(slot_id);
@@ -385,11 +362,13 @@ pub fn update_hand(
                     .map(|card| UiCard::new(*card))
                     .collect::<Vec<UiCard>>();
                 for ((slot, slot_children), card) in hand_slots_query.iter_mut().zip(cards) {
-                    let new_card_image = UiImage::new(asset_server.load(card.asset_path()));
+                    let new_card_image =
+                        UiImage::new(asset_server.load(card.asset_path(config.deck_style())));
                     if let Some(children) = slot_children {
                         let slot_image = children.first().unwrap();
                         let mut card_image = slot_image_query.get_mut(*slot_image).unwrap();
                         *card_image = new_card_image;
+                        commands.entity(*slot_image).insert(PlayerCard(card));
                     } else {
                         let new_slot_image = commands
                             .spawn((
@@ -425,6 +404,54 @@ pub fn update_hand(
     }
 }
 
+// Cards already on screen follow the deck style picked in the menu
+pub fn reskin_cards(
+    config: Res<Config>,
+    mut cards_q: Query<(&mut UiImage, AnyOf<(&PlayerCard, &TableCard, &PileCard)>)>,
+    asset_server: Res<AssetServer>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    for (mut image, cards) in &mut cards_q {
+        let Some(card) = card_face(cards) else {
+            continue;
+        };
+        let texture = asset_server.load(card.asset_path(config.deck_style()));
+        if image.texture != texture {
+            image.texture = texture;
+        }
+    }
+}
+
+// French faces that aren't among the assets are drawn with the Italian ones
+pub fn fall_back_to_italian_cards(
+    config: Res<Config>,
+    mut cards_q: Query<(&mut UiImage, AnyOf<(&PlayerCard, &TableCard, &PileCard)>)>,
+    asset_server: Res<AssetServer>,
+) {
+    if config.deck_style() == DeckStyle::Italian {
+        return;
+    }
+    for (mut image, cards) in &mut cards_q {
+        if asset_server.load_state(&image.texture) != LoadState::Failed {
+            continue;
+        }
+        if let Some(card) = card_face(cards) {
+            image.texture = asset_server.load(card.asset_path(DeckStyle::Italian));
+        }
+    }
+}
+
+fn card_face(
+    cards: (Option<&PlayerCard>, Option<&TableCard>, Option<&PileCard>),
+) -> Option<UiCard> {
+    let (hand, table, pile) = cards;
+    hand.map(|card| card.0)
+        .or(table.map(|card| card.0))
+        .or(pile.map(|card| card.0))
+}
+
 pub fn select_hand_card(
     interacted_card_query: Query<(Entity, &Interaction), (Changed<Interaction>, With<PlayerCard>)>,
     selected_card_query: Query<Entity, (With<PlayerCard>, With<SelectedCard>)>,
@@ -503,7 +530,7 @@ pub fn update_selected_cards(
     }
 }
 
-fn play_audio(asset: Handle<AudioSource>, volume: f32, commands: &mut Commands) {
+pub fn play_audio(asset: Handle<AudioSource>, volume: f32, commands: &mut Commands) {
     commands.spawn((
         SoundEffect,
         AudioBundle {
@@ -567,58 +594,26 @@ pub fn update_drag_cursor(
     }
 }
 
-#[allow(clippy::too_many_arguments)]
 pub fn drop_in(
-    dragged_q: Query<(Entity, &Dragged), With<Dragged>>,
+    dragged_q: Query<(Entity, &Dragged, &PlayerCard), With<Dragged>>,
     drop_in_q: Query<&RelativeCursorPosition, (With<DropIn>, With<TableArea>)>,
-    table_selected_cards: Query<Entity, (With<TableCard>, With<SelectedCard>)>,
     mouse_pressed: Res<ButtonInput<MouseButton>>,
-    asset_server: Res<AssetServer>,
-    config: Res<Config>,
-    mut table_slots: ResMut<TableSlots>,
-    mut popup_events: EventWriter<PopUpEvent>,
+    mut to_server: EventWriter<ToServer>,
     mut commands: Commands,
 ) {
     // We are dragging something and releasing left mouse button
     if !dragged_q.is_empty() && mouse_pressed.just_released(MouseButton::Left) {
-        if let Ok((id, dragged)) = dragged_q.get_single() {
+        if let Ok((id, dragged, PlayerCard(card))) = dragged_q.get_single() {
             if let Ok(drop_in_area) = drop_in_q.get_single() {
-                // We dropped it over drop-in area
+                // We dropped it over drop-in area, so ask the server to put it on the table
                 if drop_in_area.mouse_over() {
-                    match put_card_on_table(id, &mut table_slots, &mut commands) {
-                        Ok(_) => {
-                            commands.entity(id).remove::<Dragged>();
-                            for selected in &table_selected_cards {
-                                commands
-                                    .entity(selected)
-                                    .remove::<SelectedCard>()
-                                    .insert(RemovedCardSelection);
-                            }
-                            play_audio(
-                                asset_server.load("audio/Card_place02.ogg"),
-                                config.volume_as_f32(),
-                                &mut commands,
-                            );
-                        }
-                        Err(e) => {
-                            // The table is full, so return it back to it's original parent
-                            commands
-                                .entity(id)
-                                .set_parent(dragged.return_to())
-                                .remove::<Dragged>();
-                            popup_events.send(PopUpEvent {
-                                text: e.to_string(),
-                                ..default()
-                            });
-                        }
-                    }
-                } else {
-                    // We dropped it over non drop-in area, so return it back to it's original parent
-                    commands
-                        .entity(id)
-                        .set_parent(dragged.return_to())
-                        .remove::<Dragged>();
+                    to_server.send(ToServer(ClientMessage::PutCard { card: card.card() }));
                 }
+                // Card goes back to the hand until the server accepts the move
+                commands
+                    .entity(id)
+                    .set_parent(dragged.return_to())
+                    .remove::<Dragged>();
             }
         }
     }
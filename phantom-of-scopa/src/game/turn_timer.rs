@@ -0,0 +1,66 @@
+use super::components::*;
+use super::resources::*;
+use crate::network::{LocalPlayer, Spectator};
+use crate::styles::*;
+
+use bevy::prelude::*;
+use std::time::Duration;
+
+pub fn setup_turn_timer(
+    spectator: Option<Res<Spectator>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    commands.remove_resource::<TurnTimer>();
+    let (left, top) = match spectator {
+        Some(_) => (SPECTATOR_TURN_TIMER_X, SPECTATOR_TURN_TIMER_Y),
+        None => (TURN_TIMER_X, TURN_TIMER_Y),
+    };
+    commands.spawn((
+        InGameComponent,
+        TurnTimerText,
+        TextBundle {
+            text: default_text("", &asset_server),
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(left),
+                top: Val::Px(top),
+                ..default()
+            },
+            ..default()
+        },
+    ));
+}
+
+pub fn update_turn_timer(
+    time: Res<Time>,
+    timer: Option<ResMut<TurnTimer>>,
+    seats: Res<Seats>,
+    local_player: Res<LocalPlayer>,
+    mut text_q: Query<&mut Text, With<TurnTimerText>>,
+) {
+    let Ok(mut text) = text_q.get_single_mut() else {
+        return;
+    };
+    let Some(mut timer) = timer else {
+        text.sections[0].value.clear();
+        return;
+    };
+    timer.tick(time.delta());
+    let who = if timer.id == local_player.0 {
+        "Your move"
+    } else {
+        seats.name(timer.id)
+    };
+    text.sections[0].value = if timer.turn() > Duration::ZERO {
+        format!("{}\n{}", who, clock(timer.turn()))
+    } else {
+        format!("{}\nbank {}", who, clock(timer.bank()))
+    };
+}
+
+fn clock(left: Duration) -> String {
+    // Rounded up, so 0:00 shows only once the time is really out
+    let seconds = left.as_millis().div_ceil(1000);
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
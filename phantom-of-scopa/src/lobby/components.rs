@@ -0,0 +1,54 @@
+use bevy::prelude::Component;
+
+#[derive(Component, Debug)]
+pub struct LobbyUIRoot;
+
+#[derive(Component, Debug)]
+pub struct LobbyUI;
+
+// Node holding a button for every open room
+#[derive(Component, Debug)]
+pub struct RoomList;
+
+// Text listing the best rated players
+#[derive(Component, Debug)]
+pub struct LeaderboardText;
+
+#[derive(Component, Debug)]
+pub struct JoinRoomButton(pub String);
+
+#[derive(Component, Debug)]
+pub struct SpectateButton(pub String);
+
+// Number of players of the room to create
+#[derive(Component, Debug)]
+pub struct CreateRoomButton(pub usize);
+
+#[derive(Component, Debug)]
+pub struct JoinByCodeButton;
+
+#[derive(Component, Debug)]
+pub struct SpectateByCodeButton;
+
+#[derive(Component, Debug)]
+pub struct RefreshButton;
+
+#[derive(Component, Debug)]
+pub struct BackButton;
+
+#[derive(Component, Debug)]
+pub struct FindMatchButton;
+
+// Row shown while waiting for a quick match
+#[derive(Component, Debug)]
+pub struct MatchSearchUI;
+
+#[derive(Component, Debug)]
+pub struct MatchSearchText;
+
+#[derive(Component, Debug)]
+pub struct CancelMatchButton;
+
+// Shown once the server offers a bot
+#[derive(Component, Debug)]
+pub struct PlayBotButton;
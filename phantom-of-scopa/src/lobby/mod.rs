@@ -0,0 +1,38 @@
+mod components;
+mod resources;
+mod systems;
+
+use crate::{despawn_screen, AppState};
+use components::*;
+use systems::*;
+
+pub use resources::{FindMatchOnConnect, Leaderboard};
+
+use bevy::prelude::*;
+
+pub fn lobby_plugin(app: &mut App) {
+    app.add_systems(OnEnter(AppState::Lobby), setup_lobby)
+        .add_systems(
+            Update,
+            (
+                handle_lobby_messages,
+                join_room_button,
+                spectate_button,
+                create_room_button,
+                join_by_code_button,
+                spectate_by_code_button,
+                handle_room_code_input,
+                refresh_button,
+                back_button,
+                find_match_button,
+                cancel_match_button,
+                play_bot_button,
+                update_match_search,
+            )
+                .run_if(in_state(AppState::Lobby)),
+        )
+        .add_systems(
+            OnExit(AppState::Lobby),
+            (despawn_screen::<LobbyUIRoot>, clear_match_search),
+        );
+}
@@ -0,0 +1,46 @@
+use bevy::prelude::Resource;
+use scopa_lib::protocol::PlayerRating;
+use std::time::Duration;
+
+// Last leaderboard sent by the server, kept to show in the main menu after disconnecting
+#[derive(Resource, Debug, Clone)]
+pub struct Leaderboard {
+    pub top: Vec<PlayerRating>,
+    pub me: Option<PlayerRating>,
+}
+
+impl Leaderboard {
+    // Our rating followed by a line for every player at the top
+    pub fn text(&self) -> String {
+        let mut text = match &self.me {
+            Some(me) => format!(
+                "Your rating: {} (#{}, won {} of {})\n\n",
+                me.rating, me.rank, me.wins, me.matches
+            ),
+            None => "Finish a match against people to get rated\n\n".into(),
+        };
+        if self.top.is_empty() {
+            text.push_str("Nobody is rated yet");
+        }
+        for rating in &self.top {
+            text.push_str(&format!(
+                "{}. {}  {}\n",
+                rating.rank, rating.name, rating.rating
+            ));
+        }
+        text
+    }
+}
+
+// Present while waiting for a quick match
+#[derive(Resource, Debug, Default)]
+pub struct MatchSearch {
+    pub waited: Duration,
+    // Players waiting, us included
+    pub players: u32,
+    pub bot_offered: bool,
+}
+
+// Left by the main menu to look for a match as soon as the lobby opens
+#[derive(Resource, Debug)]
+pub struct FindMatchOnConnect;
@@ -0,0 +1,636 @@
+use crate::network::{FromServer, RoomRules, Spectator, ToServer};
+use crate::popups::*;
+use crate::styles::*;
+use crate::AppState;
+
+use super::components::*;
+use super::resources::*;
+
+use bevy::prelude::*;
+use bevy_simple_text_input::{TextInputBundle, TextInputSubmitEvent, TextInputValue};
+use scopa_lib::protocol::{ClientMessage, RoomInfo, ServerMessage, ROOM_CODE_LENGTH};
+use scopa_lib::rules::{RuleSet, SUPPORTED_PLAYERS, TEAM_PLAYERS};
+
+pub fn setup_lobby(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    find_match: Option<Res<FindMatchOnConnect>>,
+    mut to_server: EventWriter<ToServer>,
+) {
+    let root = commands
+        .spawn((
+            LobbyUIRoot,
+            LobbyUI,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: DEFAULT_BG.into(),
+                ..default()
+            },
+        ))
+        .id();
+
+    // Title
+    commands
+        .spawn((
+            LobbyUI,
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(15.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((LobbyUI, game_title(&asset_server)));
+        })
+        .set_parent(root);
+
+    // Open rooms and the leaderboard, filled in once the server sends them
+    commands
+        .spawn((
+            LobbyUI,
+            NodeBundle {
+                style: Style {
+                    height: Val::Percent(45.0),
+                    flex_direction: FlexDirection::Row,
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                LobbyUI,
+                RoomList,
+                NodeBundle {
+                    style: Style {
+                        width: Val::Px(ROOM_LIST_WIDTH),
+                        height: Val::Percent(100.0),
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        overflow: Overflow::clip(),
+                        border: UiRect::all(Val::Px(2.0)),
+                        padding: UiRect::all(Val::Px(4.0)),
+                        ..default()
+                    },
+                    border_color: INACTIVE_UI.into(),
+                    ..default()
+                },
+            ));
+            parent
+                .spawn((
+                    LobbyUI,
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Px(LEADERBOARD_WIDTH),
+                            height: Val::Percent(100.0),
+                            overflow: Overflow::clip(),
+                            border: UiRect::all(Val::Px(2.0)),
+                            padding: UiRect::all(Val::Px(4.0)),
+                            margin: UiRect::left(Val::Px(6.0)),
+                            ..default()
+                        },
+                        border_color: INACTIVE_UI.into(),
+                        ..default()
+                    },
+                ))
+                .with_children(|panel| {
+                    panel.spawn((
+                        LobbyUI,
+                        LeaderboardText,
+                        TextBundle {
+                            text: default_text("", &asset_server),
+                            ..default()
+                        },
+                    ));
+                });
+        })
+        .set_parent(root);
+
+    // New rooms, refresh and back
+    commands
+        .spawn((
+            LobbyUI,
+            NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    margin: UiRect::top(Val::Px(6.0)),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            for players in SUPPORTED_PLAYERS {
+                let label = match players {
+                    TEAM_PLAYERS => "New for 4 in teams".to_string(),
+                    _ => format!("New for {}", players),
+                };
+                parent
+                    .spawn((LobbyUI, CreateRoomButton(players), default_button()))
+                    .with_children(|button| {
+                        button.spawn((
+                            LobbyUI,
+                            TextBundle {
+                                text: default_text(&label, &asset_server),
+                                ..default()
+                            },
+                        ));
+                    });
+            }
+            parent
+                .spawn((LobbyUI, FindMatchButton, default_button()))
+                .with_children(|button| {
+                    button.spawn((
+                        LobbyUI,
+                        TextBundle {
+                            text: default_text("Find match", &asset_server),
+                            ..default()
+                        },
+                    ));
+                });
+            parent
+                .spawn((LobbyUI, RefreshButton, default_button()))
+                .with_children(|button| {
+                    button.spawn((
+                        LobbyUI,
+                        TextBundle {
+                            text: default_text("Refresh", &asset_server),
+                            ..default()
+                        },
+                    ));
+                });
+            parent
+                .spawn((LobbyUI, BackButton, default_button()))
+                .with_children(|button| {
+                    button.spawn((
+                        LobbyUI,
+                        TextBundle {
+                            text: default_text("Disconnect", &asset_server),
+                            ..default()
+                        },
+                    ));
+                });
+        })
+        .set_parent(root);
+
+    // Join by code
+    commands
+        .spawn((
+            LobbyUI,
+            NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                LobbyUI,
+                TextBundle {
+                    text: default_text("Room code", &asset_server),
+                    ..default()
+                },
+            ));
+            parent.spawn((
+                LobbyUI,
+                NodeBundle {
+                    style: Style {
+                        width: Val::Px(100.0),
+                        border: UiRect::all(Val::Px(5.0)),
+                        padding: UiRect::all(Val::Px(5.0)),
+                        margin: UiRect::horizontal(Val::Px(10.0)),
+                        ..default()
+                    },
+                    background_color: Color::WHITE.into(),
+                    border_color: INACTIVE_UI.into(),
+                    ..default()
+                },
+                TextInputBundle::default().with_text_style(TextStyle {
+                    font: asset_server.load(DEFAULT_FONT),
+                    font_size: INPUT_FONT_SIZE,
+                    color: Color::BLACK,
+                }),
+            ));
+            parent
+                .spawn((LobbyUI, JoinByCodeButton, default_button()))
+                .with_children(|button| {
+                    button.spawn((
+                        LobbyUI,
+                        TextBundle {
+                            text: default_text("Join", &asset_server),
+                            ..default()
+                        },
+                    ));
+                });
+            parent
+                .spawn((LobbyUI, SpectateByCodeButton, default_button()))
+                .with_children(|button| {
+                    button.spawn((
+                        LobbyUI,
+                        TextBundle {
+                            text: default_text("Watch", &asset_server),
+                            ..default()
+                        },
+                    ));
+                });
+        })
+        .set_parent(root);
+
+    // Quick match, hidden until the server puts us in the queue
+    commands
+        .spawn((
+            LobbyUI,
+            MatchSearchUI,
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                LobbyUI,
+                MatchSearchText,
+                TextBundle {
+                    text: default_text("", &asset_server),
+                    style: Style {
+                        margin: UiRect::right(Val::Px(10.0)),
+                        ..default()
+                    },
+                    ..default()
+                },
+            ));
+            parent
+                .spawn((LobbyUI, CancelMatchButton, default_button()))
+                .with_children(|button| {
+                    button.spawn((
+                        LobbyUI,
+                        TextBundle {
+                            text: default_text("Cancel", &asset_server),
+                            ..default()
+                        },
+                    ));
+                });
+            parent
+                .spawn((LobbyUI, PlayBotButton, default_button()))
+                .with_children(|button| {
+                    button.spawn((
+                        LobbyUI,
+                        TextBundle {
+                            text: default_text("Play a bot", &asset_server),
+                            ..default()
+                        },
+                    ));
+                });
+        })
+        .set_parent(root);
+
+    to_server.send(ToServer(ClientMessage::ListRooms));
+    to_server.send(ToServer(ClientMessage::Leaderboard));
+    if find_match.is_some() {
+        commands.remove_resource::<FindMatchOnConnect>();
+        to_server.send(ToServer(ClientMessage::FindMatch));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn handle_lobby_messages(
+    mut from_server: EventReader<FromServer>,
+    room_list_q: Query<Entity, With<RoomList>>,
+    mut leaderboard_q: Query<&mut Text, With<LeaderboardText>>,
+    mut search: Option<ResMut<MatchSearch>>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut popup_events: EventWriter<PopUpEvent>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for FromServer(message) in from_server.read() {
+        match message {
+            ServerMessage::RoomList { rooms } => {
+                if let Ok(room_list) = room_list_q.get_single() {
+                    fill_room_list(room_list, rooms, &asset_server, &mut commands);
+                }
+            }
+            ServerMessage::Leaderboard { top, me } => {
+                let leaderboard = Leaderboard {
+                    top: top.clone(),
+                    me: me.clone(),
+                };
+                if let Ok(mut text) = leaderboard_q.get_single_mut() {
+                    text.sections[0].value = leaderboard.text();
+                }
+                commands.insert_resource(leaderboard);
+            }
+            ServerMessage::Queued { players } => match search.as_mut() {
+                Some(search) => search.players = *players,
+                None => commands.insert_resource(MatchSearch {
+                    players: *players,
+                    ..default()
+                }),
+            },
+            ServerMessage::QueueLeft => {
+                commands.remove_resource::<MatchSearch>();
+            }
+            ServerMessage::BotOffered => {
+                if let Some(search) = search.as_mut() {
+                    search.bot_offered = true;
+                }
+                popup_events.send(PopUpEvent {
+                    text: "Nobody to play with yet, you can play a bot instead".into(),
+                    location: PopUpLocation::Top,
+                    ..default()
+                });
+            }
+            ServerMessage::RoomJoined { code, rules } => {
+                commands.insert_resource(RoomRules(rules.clone()));
+                popup_events.send(PopUpEvent {
+                    text: format!(
+                        "Room {}. Game starts when {} players are seated",
+                        code, rules.players
+                    ),
+                    duration: 5.0,
+                    location: PopUpLocation::Top,
+                    ..default()
+                });
+                app_state.set(AppState::InGame);
+            }
+            ServerMessage::Spectating { code, rules, .. } => {
+                commands.insert_resource(Spectator);
+                commands.insert_resource(RoomRules(rules.clone()));
+                popup_events.send(PopUpEvent {
+                    text: format!("Watching room {}", code),
+                    location: PopUpLocation::Top,
+                    ..default()
+                });
+                app_state.set(AppState::InGame);
+            }
+            ServerMessage::RoomRejected { reason } => {
+                popup_events.send(error_popup(reason.to_string()));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn fill_room_list(
+    room_list: Entity,
+    rooms: &[RoomInfo],
+    asset_server: &Res<AssetServer>,
+    commands: &mut Commands,
+) {
+    commands.entity(room_list).despawn_descendants();
+    if rooms.is_empty() {
+        commands
+            .spawn((
+                LobbyUI,
+                TextBundle {
+                    text: default_text("No open rooms, create a new one", asset_server),
+                    ..default()
+                },
+            ))
+            .set_parent(room_list);
+    }
+    // Full rooms can only be watched
+    for room in rooms {
+        let full = room.players.len() >= room.rules.players;
+        let text = format!(
+            "{}    {}/{}    {}{}",
+            room.code,
+            room.players.len(),
+            room.rules.players,
+            room.players.join(", "),
+            if full { "    (watch)" } else { "" }
+        );
+        let mut row = commands.spawn(LobbyUI);
+        if full {
+            row.insert(SpectateButton(room.code.clone()));
+        } else {
+            row.insert(JoinRoomButton(room.code.clone()));
+        }
+        row.insert(ButtonBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                border: UiRect::all(Val::Px(2.0)),
+                padding: UiRect::all(Val::Px(4.0)),
+                margin: UiRect::bottom(Val::Px(4.0)),
+                ..default()
+            },
+            background_color: DEFAULT_BG.into(),
+            border_color: INACTIVE_UI.into(),
+            ..default()
+        })
+        .with_children(|button| {
+            button.spawn((
+                LobbyUI,
+                TextBundle {
+                    text: default_text(&text, asset_server),
+                    ..default()
+                },
+            ));
+        })
+        .set_parent(room_list);
+    }
+}
+
+pub fn join_room_button(
+    interactions: Query<(&Interaction, &JoinRoomButton), Changed<Interaction>>,
+    mut to_server: EventWriter<ToServer>,
+) {
+    for (interaction, JoinRoomButton(code)) in &interactions {
+        if *interaction == Interaction::Pressed {
+            to_server.send(ToServer(ClientMessage::JoinRoom { code: code.clone() }));
+        }
+    }
+}
+
+pub fn spectate_button(
+    interactions: Query<(&Interaction, &SpectateButton), Changed<Interaction>>,
+    mut to_server: EventWriter<ToServer>,
+) {
+    for (interaction, SpectateButton(code)) in &interactions {
+        if *interaction == Interaction::Pressed {
+            to_server.send(ToServer(ClientMessage::Spectate { code: code.clone() }));
+        }
+    }
+}
+
+pub fn create_room_button(
+    interactions: Query<(&Interaction, &CreateRoomButton), Changed<Interaction>>,
+    mut to_server: EventWriter<ToServer>,
+) {
+    for (interaction, CreateRoomButton(players)) in &interactions {
+        if *interaction == Interaction::Pressed {
+            let rules = RuleSet {
+                players: *players,
+                ..RuleSet::default()
+            };
+            to_server.send(ToServer(ClientMessage::CreateRoom { rules: Some(rules) }));
+        }
+    }
+}
+
+pub fn join_by_code_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<JoinByCodeButton>)>,
+    text_input_q: Query<&TextInputValue, With<LobbyUI>>,
+    mut to_server: EventWriter<ToServer>,
+    mut popup_events: EventWriter<PopUpEvent>,
+) {
+    if let Ok(Interaction::Pressed) = interactions.get_single() {
+        if let Ok(input) = text_input_q.get_single() {
+            join_by_code(&input.0, &mut to_server, &mut popup_events);
+        }
+    }
+}
+
+pub fn spectate_by_code_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<SpectateByCodeButton>)>,
+    text_input_q: Query<&TextInputValue, With<LobbyUI>>,
+    mut to_server: EventWriter<ToServer>,
+    mut popup_events: EventWriter<PopUpEvent>,
+) {
+    if let Ok(Interaction::Pressed) = interactions.get_single() {
+        if let Ok(input) = text_input_q.get_single() {
+            if let Some(code) = room_code(&input.0, &mut popup_events) {
+                to_server.send(ToServer(ClientMessage::Spectate { code }));
+            }
+        }
+    }
+}
+
+pub fn handle_room_code_input(
+    mut text_input_events: EventReader<TextInputSubmitEvent>,
+    mut to_server: EventWriter<ToServer>,
+    mut popup_events: EventWriter<PopUpEvent>,
+) {
+    for input in text_input_events.read() {
+        join_by_code(&input.value, &mut to_server, &mut popup_events);
+    }
+}
+
+fn join_by_code(
+    code: &str,
+    to_server: &mut EventWriter<ToServer>,
+    popup_events: &mut EventWriter<PopUpEvent>,
+) {
+    if let Some(code) = room_code(code, popup_events) {
+        to_server.send(ToServer(ClientMessage::JoinRoom { code }));
+    }
+}
+
+fn room_code(input: &str, popup_events: &mut EventWriter<PopUpEvent>) -> Option<String> {
+    let code = input.trim();
+    if code.chars().count() != ROOM_CODE_LENGTH {
+        popup_events.send(error_popup(format!(
+            "Room code should have {} characters",
+            ROOM_CODE_LENGTH
+        )));
+        return None;
+    }
+    Some(code.into())
+}
+
+pub fn refresh_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<RefreshButton>)>,
+    mut to_server: EventWriter<ToServer>,
+) {
+    if let Ok(Interaction::Pressed) = interactions.get_single() {
+        to_server.send(ToServer(ClientMessage::ListRooms));
+        to_server.send(ToServer(ClientMessage::Leaderboard));
+    }
+}
+
+// Connection is closed when the main menu opens
+pub fn back_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<BackButton>)>,
+    mut app_state: ResMut<NextState<AppState>>,
+) {
+    if let Ok(Interaction::Pressed) = interactions.get_single() {
+        app_state.set(AppState::MainMenu);
+    }
+}
+
+pub fn find_match_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<FindMatchButton>)>,
+    mut to_server: EventWriter<ToServer>,
+) {
+    if let Ok(Interaction::Pressed) = interactions.get_single() {
+        to_server.send(ToServer(ClientMessage::FindMatch));
+    }
+}
+
+pub fn cancel_match_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<CancelMatchButton>)>,
+    mut to_server: EventWriter<ToServer>,
+) {
+    if let Ok(Interaction::Pressed) = interactions.get_single() {
+        to_server.send(ToServer(ClientMessage::CancelMatch));
+    }
+}
+
+pub fn play_bot_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<PlayBotButton>)>,
+    mut to_server: EventWriter<ToServer>,
+) {
+    if let Ok(Interaction::Pressed) = interactions.get_single() {
+        to_server.send(ToServer(ClientMessage::PlayBot));
+    }
+}
+
+// Shows how long we have been waiting and the bot once it is offered
+#[allow(clippy::type_complexity)]
+pub fn update_match_search(
+    time: Res<Time>,
+    search: Option<ResMut<MatchSearch>>,
+    mut row_q: Query<&mut Style, (With<MatchSearchUI>, Without<PlayBotButton>)>,
+    mut bot_q: Query<&mut Style, (With<PlayBotButton>, Without<MatchSearchUI>)>,
+    mut text_q: Query<&mut Text, With<MatchSearchText>>,
+) {
+    let (Ok(mut row), Ok(mut bot)) = (row_q.get_single_mut(), bot_q.get_single_mut()) else {
+        return;
+    };
+    let Some(mut search) = search else {
+        row.display = Display::None;
+        return;
+    };
+    search.waited += time.delta();
+    row.display = Display::Flex;
+    bot.display = if search.bot_offered {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    if let Ok(mut text) = text_q.get_single_mut() {
+        let seconds = search.waited.as_secs();
+        text.sections[0].value = format!(
+            "Looking for a match {}:{:02}, {} waiting",
+            seconds / 60,
+            seconds % 60,
+            search.players
+        );
+    }
+}
+
+pub fn clear_match_search(mut commands: Commands) {
+    commands.remove_resource::<MatchSearch>();
+}
@@ -1,7 +1,9 @@
 mod config;
 mod error;
 mod game;
+mod lobby;
 mod menu;
+mod network;
 mod popups;
 mod startup;
 mod styles;
@@ -18,6 +20,7 @@ enum AppState {
     #[default]
     StartUp,
     MainMenu,
+    Lobby,
     InGame,
 }
 
@@ -45,8 +48,10 @@ fn main() {
             ..default()
         }))
         .add_plugins(TextInputPlugin)
+        .add_plugins(network::network_plugin)
         .add_plugins(startup::startup_plugin)
         .add_plugins(menu::menu_plugin)
+        .add_plugins(lobby::lobby_plugin)
         .add_plugins(game::game_plugin)
         .run();
 }
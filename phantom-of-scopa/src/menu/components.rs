@@ -1,4 +1,5 @@
 use bevy::prelude::Component;
+use std::net::SocketAddr;
 
 #[derive(Component, Debug)]
 pub struct MainMenuUIRoot;
@@ -8,3 +9,14 @@ pub struct MainMenuUI;
 
 #[derive(Component, Debug)]
 pub struct ConnectButton;
+
+// Connects and looks for a quick match right away
+#[derive(Component, Debug)]
+pub struct FindMatchButton;
+
+// Servers found on the local network are listed in here
+#[derive(Component, Debug)]
+pub struct LanServerList;
+
+#[derive(Component, Debug)]
+pub struct LanServerButton(pub SocketAddr);
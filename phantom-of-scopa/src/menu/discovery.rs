@@ -0,0 +1,252 @@
+use crate::config::Config;
+use crate::popups::PopUpEvent;
+use crate::styles::*;
+
+use super::components::*;
+
+use bevy::prelude::*;
+use bevy_simple_text_input::TextInputValue;
+use scopa_lib::protocol::{
+    decode, ServerAnnouncement, ANNOUNCE_INTERVAL, DISCOVERY_PORT, MAX_ANNOUNCEMENT_SIZE,
+    PROTOCOL_VERSION,
+};
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+// Servers drop off the list after missing this many announcements
+const MISSED_ANNOUNCEMENTS: u32 = 3;
+// Anybody on the network can announce servers, so only so many are listed, from each host and
+// altogether
+const MAX_SERVERS_PER_HOST: usize = 4;
+const MAX_SERVERS: usize = 16;
+
+// Announcements by the address to connect to, with the time each was last heard
+type Heard = BTreeMap<SocketAddr, (ServerAnnouncement, Duration)>;
+
+// Servers heard from on the local network while the main menu is open
+#[derive(Resource, Default)]
+pub struct LanServers {
+    // None when another client on this machine listens already
+    socket: Option<UdpSocket>,
+    servers: Heard,
+}
+
+pub fn start_discovery(mut commands: Commands) {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT))
+        .and_then(|socket| socket.set_nonblocking(true).map(|_| socket));
+    if let Err(e) = &socket {
+        warn!("Can't look for servers on the local network: {}", e);
+    }
+    commands.insert_resource(LanServers {
+        socket: socket.ok(),
+        servers: BTreeMap::new(),
+    });
+}
+
+pub fn stop_discovery(mut commands: Commands) {
+    commands.remove_resource::<LanServers>();
+}
+
+// List only changes when a server shows up, goes away or its rooms change, so it isn't rebuilt
+// on every announcement
+pub fn discover_servers(mut lan: ResMut<LanServers>, time: Res<Time>) {
+    let now = time.elapsed();
+    let LanServers { socket, servers } = lan.bypass_change_detection();
+    let mut changed = false;
+    let mut buffer = [0; MAX_ANNOUNCEMENT_SIZE];
+    while let Some(Ok((size, from))) = socket.as_ref().map(|socket| socket.recv_from(&mut buffer)) {
+        changed |= hear(servers, from, &buffer[..size], now);
+    }
+    changed |= forget_silent(servers, now);
+    if changed {
+        lan.set_changed();
+    }
+}
+
+// Takes in what came from one address, returns whether the list changed
+fn hear(servers: &mut Heard, from: SocketAddr, bytes: &[u8], now: Duration) -> bool {
+    // Servers speaking another version can't be joined anyway
+    let Ok(announcement) = decode::<ServerAnnouncement>(bytes) else {
+        return false;
+    };
+    if announcement.version != PROTOCOL_VERSION {
+        return false;
+    }
+    let addr = SocketAddr::new(from.ip(), announcement.port);
+    if !servers.contains_key(&addr) {
+        let from_host = servers
+            .keys()
+            .filter(|known| known.ip() == addr.ip())
+            .count();
+        if from_host >= MAX_SERVERS_PER_HOST || servers.len() >= MAX_SERVERS {
+            return false;
+        }
+    }
+    let previous = servers.insert(addr, (announcement.clone(), now));
+    previous.is_none_or(|(previous, _)| previous != announcement)
+}
+
+// Drops servers that stopped announcing themselves, returns whether there were any
+fn forget_silent(servers: &mut Heard, now: Duration) -> bool {
+    let before = servers.len();
+    servers.retain(|_, (_, heard)| now - *heard < ANNOUNCE_INTERVAL * MISSED_ANNOUNCEMENTS);
+    servers.len() != before
+}
+
+pub fn show_lan_servers(
+    lan: Res<LanServers>,
+    list_q: Query<Entity, With<LanServerList>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    let Ok(list) = list_q.get_single() else {
+        return;
+    };
+    commands.entity(list).despawn_descendants();
+    let title = if lan.servers.is_empty() {
+        "Looking for servers nearby..."
+    } else {
+        "Servers nearby"
+    };
+    commands
+        .spawn((
+            MainMenuUI,
+            TextBundle {
+                text: default_text(title, &asset_server),
+                ..default()
+            },
+        ))
+        .set_parent(list);
+    for (addr, (announcement, _)) in &lan.servers {
+        let text = format!("{}    {} rooms", announcement.name, announcement.rooms);
+        commands
+            .spawn((
+                MainMenuUI,
+                LanServerButton(*addr),
+                ButtonBundle {
+                    style: Style {
+                        width: Val::Percent(100.0),
+                        border: UiRect::all(Val::Px(2.0)),
+                        padding: UiRect::all(Val::Px(4.0)),
+                        margin: UiRect::top(Val::Px(4.0)),
+                        ..default()
+                    },
+                    background_color: DEFAULT_BG.into(),
+                    border_color: INACTIVE_UI.into(),
+                    ..default()
+                },
+            ))
+            .with_children(|button| {
+                button.spawn((
+                    MainMenuUI,
+                    TextBundle {
+                        text: default_text(&text, &asset_server),
+                        ..default()
+                    },
+                ));
+            })
+            .set_parent(list);
+    }
+}
+
+// Fills in the address, connecting is still up to the buttons next to it
+pub fn lan_server_button(
+    interactions: Query<(&Interaction, &LanServerButton), Changed<Interaction>>,
+    mut text_input_q: Query<&mut TextInputValue, With<MainMenuUI>>,
+    mut config: ResMut<Config>,
+    mut popup_events: EventWriter<PopUpEvent>,
+) {
+    for (interaction, LanServerButton(addr)) in &interactions {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let Ok(mut input) = text_input_q.get_single_mut() {
+            input.0 = addr.to_string();
+        }
+        config.set_connection(*addr);
+        if let Err(e) = config.save() {
+            popup_events.send(error_popup(e.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scopa_lib::protocol::encode;
+
+    fn announcement(version: u16, port: u16, rooms: usize) -> Vec<u8> {
+        let announcement = ServerAnnouncement {
+            version,
+            name: "Venice".into(),
+            port,
+            rooms,
+        };
+        encode(&announcement).unwrap()
+    }
+
+    #[test]
+    fn servers_come_and_go() {
+        let mut servers = Heard::new();
+        let from = SocketAddr::from(([192, 168, 1, 20], 50000));
+        let now = Duration::from_secs(10);
+        assert!(hear(
+            &mut servers,
+            from,
+            &announcement(PROTOCOL_VERSION, 6969, 1),
+            now
+        ));
+        assert!(servers.contains_key(&SocketAddr::from(([192, 168, 1, 20], 6969))));
+        // Same again isn't a change, other rooms are
+        let later = now + ANNOUNCE_INTERVAL;
+        assert!(!hear(
+            &mut servers,
+            from,
+            &announcement(PROTOCOL_VERSION, 6969, 1),
+            later
+        ));
+        assert!(hear(
+            &mut servers,
+            from,
+            &announcement(PROTOCOL_VERSION, 6969, 2),
+            later
+        ));
+        // Other versions and garbage are ignored
+        let other = announcement(PROTOCOL_VERSION + 1, 7000, 0);
+        assert!(!hear(&mut servers, from, &other, later));
+        assert!(!hear(&mut servers, from, b"nonsense", later));
+        assert_eq!(servers.len(), 1);
+
+        assert!(!forget_silent(&mut servers, later));
+        let silent = later + ANNOUNCE_INTERVAL * MISSED_ANNOUNCEMENTS;
+        assert!(forget_silent(&mut servers, silent));
+        assert!(servers.is_empty());
+    }
+
+    #[test]
+    fn fake_servers_are_capped() {
+        let mut servers = Heard::new();
+        let now = Duration::ZERO;
+        let flooder = SocketAddr::from(([192, 168, 1, 66], 50000));
+        for port in 1000..2000 {
+            hear(
+                &mut servers,
+                flooder,
+                &announcement(PROTOCOL_VERSION, port, 0),
+                now,
+            );
+        }
+        assert_eq!(servers.len(), MAX_SERVERS_PER_HOST);
+        for host in 0..=u8::MAX {
+            let from = SocketAddr::from(([10, 0, 0, host], 50000));
+            hear(
+                &mut servers,
+                from,
+                &announcement(PROTOCOL_VERSION, 6969, 0),
+                now,
+            );
+        }
+        assert_eq!(servers.len(), MAX_SERVERS);
+    }
+}
@@ -1,17 +1,36 @@
 mod components;
+mod discovery;
 mod systems;
 
 use crate::{despawn_screen, AppState};
 use components::*;
+use discovery::*;
 use systems::*;
 
 use bevy::prelude::*;
 
 pub fn menu_plugin(app: &mut App) {
-    app.add_systems(OnEnter(AppState::MainMenu), setup_menu)
+    app.add_systems(OnEnter(AppState::MainMenu), (setup_menu, start_discovery))
         .add_systems(
             Update,
-            (connect_button).run_if(in_state(AppState::MainMenu)),
+            (
+                connect_button,
+                find_match_button,
+                handle_connection_input,
+                lan_server_button,
+            )
+                .run_if(in_state(AppState::MainMenu)),
         )
-        .add_systems(OnExit(AppState::MainMenu), despawn_screen::<MainMenuUIRoot>);
+        .add_systems(
+            Update,
+            (
+                discover_servers.run_if(resource_exists::<LanServers>),
+                show_lan_servers.run_if(resource_exists_and_changed::<LanServers>),
+            )
+                .chain(),
+        )
+        .add_systems(
+            OnExit(AppState::MainMenu),
+            (despawn_screen::<MainMenuUIRoot>, stop_discovery),
+        );
 }
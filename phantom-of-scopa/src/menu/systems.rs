@@ -1,15 +1,21 @@
 use crate::config::Config;
 use crate::error::Result;
+use crate::lobby::{FindMatchOnConnect, Leaderboard};
+use crate::network;
 use crate::popups::PopUpEvent;
 use crate::styles::*;
-use crate::AppState;
 
 use super::components::*;
 
 use bevy::prelude::*;
 use bevy_simple_text_input::{TextInputBundle, TextInputSubmitEvent, TextInputValue};
 
-pub fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>, config: Res<Config>) {
+pub fn setup_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    leaderboard: Option<Res<Leaderboard>>,
+) {
     let root = commands
         .spawn((
             MainMenuUIRoot,
@@ -126,47 +132,122 @@ pub fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>, config
                         },
                     ));
                 });
+            parent
+                .spawn((MainMenuUI, FindMatchButton, default_button()))
+                .with_children(|button| {
+                    button.spawn((
+                        MainMenuUI,
+                        TextBundle {
+                            text: default_text("Find Match", &asset_server),
+                            ..default()
+                        },
+                    ));
+                });
         })
         .set_parent(root);
+
+    // Filled in as servers on the local network announce themselves
+    commands
+        .spawn((
+            MainMenuUI,
+            LanServerList,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Column,
+                    width: Val::Px(LAN_SERVER_LIST_WIDTH),
+                    left: Val::Px(20.0),
+                    top: Val::Percent(30.0),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .set_parent(root);
+
+    // Ratings as of the last visit to the lobby
+    if let Some(leaderboard) = leaderboard {
+        commands
+            .spawn((
+                MainMenuUI,
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        width: Val::Px(LEADERBOARD_WIDTH),
+                        right: Val::Px(20.0),
+                        top: Val::Percent(30.0),
+                        border: UiRect::all(Val::Px(2.0)),
+                        padding: UiRect::all(Val::Px(4.0)),
+                        ..default()
+                    },
+                    border_color: INACTIVE_UI.into(),
+                    ..default()
+                },
+            ))
+            .with_children(|parent| {
+                parent.spawn((
+                    MainMenuUI,
+                    TextBundle {
+                        text: default_text(&leaderboard.text(), &asset_server),
+                        ..default()
+                    },
+                ));
+            })
+            .set_parent(root);
+    }
 }
 
 pub fn connect_button(
     interactions: Query<&Interaction, (Changed<Interaction>, With<ConnectButton>)>,
     text_input_q: Query<&TextInputValue, With<MainMenuUI>>,
-    mut app_state: ResMut<NextState<AppState>>,
+    mut config: ResMut<Config>,
+    mut commands: Commands,
     mut popup_events: EventWriter<PopUpEvent>,
 ) {
     if let Ok(Interaction::Pressed) = interactions.get_single() {
-        app_state.set(AppState::InGame);
+        if let Ok(input) = text_input_q.get_single() {
+            if let Err(e) = connect(input.0.as_str(), &mut config, &mut commands) {
+                popup_events.send(error_popup(e.to_string()));
+            }
+        }
+    }
+}
+
+pub fn find_match_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<FindMatchButton>)>,
+    text_input_q: Query<&TextInputValue, With<MainMenuUI>>,
+    mut config: ResMut<Config>,
+    mut commands: Commands,
+    mut popup_events: EventWriter<PopUpEvent>,
+) {
+    if let Ok(Interaction::Pressed) = interactions.get_single() {
+        if let Ok(input) = text_input_q.get_single() {
+            match connect(input.0.as_str(), &mut config, &mut commands) {
+                Ok(()) => commands.insert_resource(FindMatchOnConnect),
+                Err(e) => {
+                    popup_events.send(error_popup(e.to_string()));
+                }
+            }
+        }
     }
-    // if let Ok(input) = text_input_q.get_single() {
-    //     match connect(input.0.as_str()) {
-    //         Ok(_) => {
-    //             todo!("Connected successfully, so change state to AppState::InGame");
-    //         }
-    //         Err(e) => {
-    //             popup_events.send(error_popup(e.to_string()));
-    //         }
-    //     }
-    // }
 }
 
 pub fn handle_connection_input(
     mut text_input_events: EventReader<TextInputSubmitEvent>,
+    mut config: ResMut<Config>,
+    mut commands: Commands,
     mut popup_events: EventWriter<PopUpEvent>,
 ) {
     for input in text_input_events.read() {
-        match connect(input.value.as_str()) {
-            Ok(_) => {
-                todo!("Connected successfully, so change state to AppState::InGame");
-            }
-            Err(e) => {
-                popup_events.send(error_popup(e.to_string()));
-            }
+        if let Err(e) = connect(input.value.as_str(), &mut config, &mut commands) {
+            popup_events.send(error_popup(e.to_string()));
         }
     }
 }
 
-fn connect(connection_string: &str) -> Result<()> {
-    todo!("Try to connect to server");
+// Lobby opens once the server welcomes us, see the network plugin
+fn connect(connection_string: &str, config: &mut Config, commands: &mut Commands) -> Result<()> {
+    let addr = network::connect(connection_string, config, commands)?;
+    config.set_connection(addr);
+    config.save()
 }
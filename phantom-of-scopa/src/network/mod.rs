@@ -0,0 +1,87 @@
+mod systems;
+mod token;
+
+use crate::AppState;
+pub use systems::connect;
+use systems::*;
+
+use bevy::prelude::*;
+use bevy::tasks::Task;
+use bevy_renet::renet::transport::ConnectToken;
+use bevy_renet::renet::RenetClient;
+use bevy_renet::transport::NetcodeClientPlugin;
+use bevy_renet::RenetClientPlugin;
+use scopa_lib::protocol::{ClientMessage, ServerMessage, SessionToken};
+use scopa_lib::rules::RuleSet;
+use scopa_lib::PlayerId;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+// Enough to ride out a server restart
+const MAX_RECONNECT_ATTEMPTS: u32 = 15;
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+// Game messages from the server. Handshake messages are handled by the network plugin itself.
+#[derive(Event, Debug)]
+pub struct FromServer(pub ServerMessage);
+
+#[derive(Event, Debug)]
+pub struct ToServer(pub ClientMessage);
+
+// Id the server gave to this client
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct LocalPlayer(pub PlayerId);
+
+// Given by the server on taking a seat, lets us get it back after the connection drops
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct Session(pub SessionToken);
+
+// Present while watching a room instead of playing in it
+#[derive(Resource, Debug)]
+pub struct Spectator;
+
+// Rules of the room we are in, shuffles of the server are checked against them
+#[derive(Resource, Debug, Clone)]
+pub struct RoomRules(pub RuleSet);
+
+// Present while the connection is lost in the middle of a game
+#[derive(Resource, Debug)]
+pub struct Reconnecting {
+    attempts: u32,
+    timer: Timer,
+}
+
+impl Reconnecting {
+    fn new(attempts: u32) -> Self {
+        Self {
+            attempts,
+            timer: Timer::new(RECONNECT_DELAY, TimerMode::Once),
+        }
+    }
+}
+
+// Present while the server is asked for a connect token, the connection opens once it answers
+#[derive(Resource)]
+struct FetchingToken {
+    server_addr: SocketAddr,
+    first_message: ClientMessage,
+    task: Task<crate::error::Result<Option<ConnectToken>>>,
+}
+
+pub fn network_plugin(app: &mut App) {
+    app.add_plugins((RenetClientPlugin, NetcodeClientPlugin))
+        .add_event::<FromServer>()
+        .add_event::<ToServer>()
+        .add_systems(
+            Update,
+            (receive_messages, send_messages, handle_disconnect)
+                .chain()
+                .run_if(resource_exists::<RenetClient>),
+        )
+        .add_systems(Update, reconnect.run_if(resource_exists::<Reconnecting>))
+        .add_systems(
+            Update,
+            finish_connecting.run_if(resource_exists::<FetchingToken>),
+        )
+        .add_systems(OnEnter(AppState::MainMenu), disconnect);
+}
@@ -0,0 +1,279 @@
+use super::token::fetch_token;
+use super::{
+    FetchingToken, FromServer, LocalPlayer, Reconnecting, Session, Spectator, ToServer,
+    MAX_RECONNECT_ATTEMPTS,
+};
+use crate::config::Config;
+use crate::error::Result;
+use crate::popups::{PopUpEvent, PopUpLocation};
+use crate::styles::*;
+use crate::AppState;
+
+use bevy::prelude::*;
+use bevy::tasks::{block_on, futures_lite::future, IoTaskPool};
+use bevy_renet::renet::transport::{ClientAuthentication, ConnectToken, NetcodeClientTransport};
+use bevy_renet::renet::{ConnectionConfig, DefaultChannel, RenetClient};
+use scopa_lib::protocol::*;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::SystemTime;
+
+// Starts connecting to the server and says hello right away. Renet holds the message until the
+// connection is established.
+pub fn connect(
+    connection_string: &str,
+    config: &Config,
+    commands: &mut Commands,
+) -> Result<SocketAddr> {
+    let server_addr: SocketAddr = connection_string.trim().parse()?;
+    let hello = ClientMessage::Hello {
+        version: PROTOCOL_VERSION,
+        name: config.player_name().into(),
+    };
+    open_connection(server_addr, config, hello, commands);
+    Ok(server_addr)
+}
+
+// The token is fetched off the main thread, the frames go on while the server takes its time
+fn open_connection(
+    server_addr: SocketAddr,
+    config: &Config,
+    first_message: ClientMessage,
+    commands: &mut Commands,
+) {
+    let name = config.player_name().to_string();
+    let secret = config.player_secret().to_string();
+    let task = IoTaskPool::get().spawn(async move { fetch_token(server_addr, &name, &secret) });
+    commands.insert_resource(FetchingToken {
+        server_addr,
+        first_message,
+        task,
+    });
+}
+
+// Opens the connection once the server has answered the token request
+pub fn finish_connecting(
+    mut fetching: ResMut<FetchingToken>,
+    reconnecting: Option<Res<Reconnecting>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut commands: Commands,
+    mut popup_events: EventWriter<PopUpEvent>,
+) {
+    let Some(token) = block_on(future::poll_once(&mut fetching.task)) else {
+        return;
+    };
+    commands.remove_resource::<FetchingToken>();
+    let result = token.and_then(|token| {
+        start_client(
+            fetching.server_addr,
+            token,
+            &fetching.first_message,
+            &mut commands,
+        )
+    });
+    if let Err(e) = result {
+        popup_events.send(error_popup(e.to_string()));
+        if reconnecting.is_some() {
+            end_session(&mut commands);
+            next_state.set(AppState::MainMenu);
+        }
+    }
+}
+
+fn start_client(
+    server_addr: SocketAddr,
+    token: Option<ConnectToken>,
+    first_message: &ClientMessage,
+    commands: &mut Commands,
+) -> Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    // Server picks our client id when it signs the token
+    let authentication = match token {
+        Some(connect_token) => ClientAuthentication::Secure { connect_token },
+        None => ClientAuthentication::Unsecure {
+            protocol_id: PROTOCOL_ID,
+            // Top half of the ids belongs to bots and WebSocket connections
+            client_id: rand::random::<u64>() >> 1,
+            server_addr,
+            user_data: None,
+        },
+    };
+    let transport = NetcodeClientTransport::new(current_time, authentication, socket)?;
+    let mut client = RenetClient::new(ConnectionConfig::default());
+    client.send_message(DefaultChannel::ReliableOrdered, encode(first_message)?);
+    commands.insert_resource(client);
+    commands.insert_resource(transport);
+    Ok(())
+}
+
+// Sessions survive dropped connections, but not leaving the game
+fn end_session(commands: &mut Commands) {
+    commands.remove_resource::<Session>();
+    commands.remove_resource::<Reconnecting>();
+    commands.remove_resource::<Spectator>();
+}
+
+fn drop_connection(commands: &mut Commands) {
+    commands.remove_resource::<RenetClient>();
+    commands.remove_resource::<NetcodeClientTransport>();
+    commands.remove_resource::<LocalPlayer>();
+}
+
+pub fn receive_messages(
+    mut client: ResMut<RenetClient>,
+    reconnecting: Option<Res<Reconnecting>>,
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut from_server: EventWriter<FromServer>,
+    mut popup_events: EventWriter<PopUpEvent>,
+) {
+    while let Some(message) = client.receive_message(DefaultChannel::ReliableOrdered) {
+        match decode::<ServerMessage>(&message) {
+            Ok(ServerMessage::Welcome { id, .. }) => {
+                commands.insert_resource(LocalPlayer(id));
+                if reconnecting.is_some() {
+                    commands.remove_resource::<Reconnecting>();
+                    popup_events.send(PopUpEvent {
+                        text: "Reconnected".into(),
+                        location: PopUpLocation::Top,
+                        ..default()
+                    });
+                } else {
+                    app_state.set(AppState::Lobby);
+                }
+            }
+            // Refused handshake, or the admin sent us away in the middle of things
+            Ok(ServerMessage::Rejected { reason }) => {
+                popup_events.send(error_popup(reason.to_string()));
+                drop_connection(&mut commands);
+                if reconnecting.is_some()
+                    || matches!(state.get(), AppState::Lobby | AppState::InGame)
+                {
+                    end_session(&mut commands);
+                    app_state.set(AppState::MainMenu);
+                }
+                return;
+            }
+            Ok(ServerMessage::Session { token }) => {
+                commands.insert_resource(Session(token));
+            }
+            Ok(ServerMessage::Announcement { text }) => {
+                popup_events.send(PopUpEvent {
+                    text: format!("Server: {}", text),
+                    duration: 8.0,
+                    location: PopUpLocation::Top,
+                    ..default()
+                });
+            }
+            Ok(ServerMessage::ShuttingDown { after }) => {
+                popup_events.send(PopUpEvent {
+                    text: format!("Server is shutting down in {} seconds", after.as_secs()),
+                    duration: 8.0,
+                    location: PopUpLocation::Top,
+                    ..default()
+                });
+            }
+            Ok(message) => {
+                from_server.send(FromServer(message));
+            }
+            Err(e) => warn!("{}", e),
+        }
+    }
+}
+
+pub fn send_messages(mut client: ResMut<RenetClient>, mut to_server: EventReader<ToServer>) {
+    for ToServer(message) in to_server.read() {
+        match encode(message) {
+            Ok(encoded) => client.send_message(DefaultChannel::ReliableOrdered, encoded),
+            Err(e) => warn!("{:?} can't be sent: {}", message, e),
+        }
+    }
+}
+
+// Connection lost in the middle of a game is retried a few times to resume our seat
+pub fn handle_disconnect(
+    client: Res<RenetClient>,
+    session: Option<Res<Session>>,
+    reconnecting: Option<Res<Reconnecting>>,
+    app_state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut commands: Commands,
+    mut popup_events: EventWriter<PopUpEvent>,
+) {
+    if !client.is_disconnected() {
+        return;
+    }
+    drop_connection(&mut commands);
+    let attempts = reconnecting.map_or(0, |r| r.attempts);
+    let in_game = *app_state.get() == AppState::InGame;
+    if in_game && session.is_some() && attempts < MAX_RECONNECT_ATTEMPTS {
+        commands.insert_resource(Reconnecting::new(attempts + 1));
+        popup_events.send(PopUpEvent {
+            text: format!(
+                "Connection lost, reconnecting ({}/{})...",
+                attempts + 1,
+                MAX_RECONNECT_ATTEMPTS
+            ),
+            duration: 5.0,
+            location: PopUpLocation::Top,
+            ..default()
+        });
+        return;
+    }
+    popup_events.send(error_popup("Connection to the server was lost".into()));
+    end_session(&mut commands);
+    if matches!(app_state.get(), AppState::Lobby | AppState::InGame) {
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+// Connects to the server stored in the config and asks for our seat back
+pub fn reconnect(
+    mut reconnecting: ResMut<Reconnecting>,
+    session: Option<Res<Session>>,
+    config: Res<Config>,
+    time: Res<Time>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut commands: Commands,
+    mut popup_events: EventWriter<PopUpEvent>,
+) {
+    if !reconnecting.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let Some(session) = session else {
+        commands.remove_resource::<Reconnecting>();
+        return;
+    };
+    let resume = ClientMessage::Resume {
+        version: PROTOCOL_VERSION,
+        token: session.0,
+    };
+    match config.connection_str().parse::<SocketAddr>() {
+        Ok(server_addr) => open_connection(server_addr, &config, resume, &mut commands),
+        Err(e) => {
+            popup_events.send(error_popup(e.to_string()));
+            end_session(&mut commands);
+            next_state.set(AppState::MainMenu);
+        }
+    }
+}
+
+pub fn disconnect(
+    client: Option<ResMut<RenetClient>>,
+    transport: Option<ResMut<NetcodeClientTransport>>,
+    mut commands: Commands,
+) {
+    if let Some(mut client) = client {
+        client.disconnect();
+    }
+    if let Some(mut transport) = transport {
+        transport.disconnect();
+    }
+    // Whatever the server says about a token now, we no longer want to connect
+    commands.remove_resource::<FetchingToken>();
+    drop_connection(&mut commands);
+    end_session(&mut commands);
+}
@@ -0,0 +1,70 @@
+use crate::error::{BaseError, Result};
+
+use bevy_renet::renet::transport::ConnectToken;
+use scopa_lib::protocol::{encode, TokenRequest, PROTOCOL_VERSION, TOKEN_PATH};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+// A firewalled token port doesn't refuse the connection, it never answers
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Asks the server for a token tied to our name. Servers that let anybody in don't hand out tokens,
+// None is returned when nobody answers on the token port.
+// The request is plain HTTP, the secret and the token can be read by anybody on the way, so the
+// secret shouldn't be a password used anywhere else.
+// Blocks until the server answers, it's run on the IO task pool.
+pub fn fetch_token(
+    server_addr: SocketAddr,
+    name: &str,
+    secret: &str,
+) -> Result<Option<ConnectToken>> {
+    let mut stream = match TcpStream::connect_timeout(&server_addr, TOKEN_TIMEOUT) {
+        Ok(stream) => stream,
+        Err(e)
+            if matches!(
+                e.kind(),
+                ErrorKind::ConnectionRefused | ErrorKind::TimedOut | ErrorKind::WouldBlock
+            ) =>
+        {
+            return Ok(None)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    stream.set_read_timeout(Some(TOKEN_TIMEOUT))?;
+    stream.set_write_timeout(Some(TOKEN_TIMEOUT))?;
+    let body = encode(&TokenRequest {
+        version: PROTOCOL_VERSION,
+        name: name.into(),
+        secret: secret.into(),
+    })?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/octet-stream\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        TOKEN_PATH,
+        server_addr,
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+
+    // Server closes the connection once it has answered
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    let malformed = || BaseError::Gameplay("Server sent a malformed token".into());
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(malformed)?;
+    let (head, body) = (&response[..split], &response[split + 4..]);
+    let status = String::from_utf8_lossy(head)
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(malformed)?;
+    if status != 200 {
+        let reason = String::from_utf8_lossy(body).trim().to_string();
+        return Err(BaseError::Gameplay(reason));
+    }
+    Ok(Some(ConnectToken::read(&mut &body[..])?))
+}
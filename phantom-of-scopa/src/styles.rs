@@ -20,6 +20,29 @@ pub const TABLE_SLOT_WIDTH: f32 = 77.0;
 pub const TABLE_SLOT_HEIGHT: f32 = 111.0;
 pub const BUTTON_WIDTH: f32 = 120.0;
 pub const BUTTON_HEIGHT: f32 = 51.0;
+pub const BOT_BUTTON_WIDTH: f32 = 80.0;
+pub const ROOM_LIST_WIDTH: f32 = 500.0;
+pub const LEADERBOARD_WIDTH: f32 = 240.0;
+pub const LAN_SERVER_LIST_WIDTH: f32 = 240.0;
+pub const PILE_CARD_WIDTH: f32 = 17.0;
+pub const PILE_CARD_HEIGHT: f32 = 26.0;
+pub const PILES_X: f32 = 8.0;
+pub const PILES_Y: f32 = 362.0;
+pub const PILES_WIDTH: f32 = 784.0;
+pub const PILES_HEIGHT: f32 = 115.0;
+// Left of the hand for players, in place of the buttons for spectators
+pub const TURN_TIMER_X: f32 = 150.0;
+pub const TURN_TIMER_Y: f32 = 410.0;
+pub const SPECTATOR_TURN_TIMER_X: f32 = 669.0;
+pub const SPECTATOR_TURN_TIMER_Y: f32 = 187.0;
+pub const CHAT_X: f32 = 8.0;
+pub const CHAT_Y: f32 = 8.0;
+pub const CHAT_WIDTH: f32 = 180.0;
+pub const CHAT_LOG_HEIGHT: f32 = 170.0;
+pub const CHAT_BUTTON_HEIGHT: f32 = 26.0;
+// Top right corner, clear of the table
+pub const FAIRNESS_BADGE_X: f32 = 620.0;
+pub const FAIRNESS_BADGE_Y: f32 = 8.0;
 
 pub const DEFAULT_BG: Color = Color::rgba(0.11, 0.13, 0.13, 1.0);
 pub const TEXT_COLOR: Color = Color::rgba(0.85, 0.82, 0.16, 1.0);
@@ -29,11 +52,14 @@ pub const HOVERED_UI: Color = Color::rgba(0.85, 0.82, 0.16, 1.0);
 pub const SELECTED_UI: Color = Color::rgba(0.85, 0.82, 0.16, 1.0);
 // pub const SELECTED_UI: Color = Color::rgba(0.38, 0.02, 0.03, 1.0);
 pub const HOVERED_SELECTED_UI: Color = Color::rgba(0.77, 0.74, 0.10, 1.0);
+pub const FAIR_COLOR: Color = Color::rgba(0.35, 0.75, 0.35, 1.0);
+pub const UNFAIR_COLOR: Color = Color::rgba(0.85, 0.25, 0.20, 1.0);
 
 pub const DEFAULT_FONT: &str = "fonts/DroidSerif-Regular.ttf";
 pub const DEFAULT_FONT_SIZE: f32 = 17.0;
 pub const INPUT_FONT_SIZE: f32 = 20.0;
 pub const TITLE_FONT_SIZE: f32 = 24.0;
+pub const CHAT_FONT_SIZE: f32 = 13.0;
 
 pub const DEFAULT_VOLUME: f32 = 0.1;
 
@@ -0,0 +1,342 @@
+use crate::http::{self, Request, Response};
+use crate::server::Server;
+
+use scopa_lib::card::Card;
+use scopa_lib::snapshot::GameSnapshot;
+use scopa_lib::PlayerId;
+use serde::{Deserialize, Serialize};
+use std::io::BufRead;
+use std::net::SocketAddr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+// Warning players get when no other delay is given
+pub const DEFAULT_SHUTDOWN_DELAY: Duration = Duration::from_secs(30);
+
+const USAGE: &str = "\
+rooms                list open rooms
+players              list connected players and those holding a seat
+room CODE            show the game in a room, hands included
+kick ID              disconnect a player, a bot takes their seat
+ban NAME             kick everybody with the name and keep them out
+unban NAME           let the name back in
+say TEXT             send an announcement to everybody
+end CODE             end the game in a room without a winner
+shutdown [SECONDS]   warn everybody and stop the server after a while";
+
+// Commands for whoever operates the server, typed on the console or sent as JSON to the admin
+// endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum AdminCommand {
+    Help,
+    Rooms,
+    Players,
+    Room { code: String },
+    Kick { id: PlayerId },
+    Ban { name: String },
+    Unban { name: String },
+    Say { text: String },
+    End { code: String },
+    Shutdown { seconds: Option<u64> },
+}
+
+impl AdminCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let argument = argument.trim();
+        let required = |what: &str| match argument {
+            "" => Err(format!("{} needs a {}", command, what)),
+            argument => Ok(argument.to_string()),
+        };
+        Ok(match command {
+            "help" => AdminCommand::Help,
+            "rooms" => AdminCommand::Rooms,
+            "players" => AdminCommand::Players,
+            "room" => AdminCommand::Room {
+                code: required("room code")?.to_uppercase(),
+            },
+            "kick" => AdminCommand::Kick {
+                id: required("player id")?
+                    .parse()
+                    .map_err(|_| format!("{} is not a player id", argument))?,
+            },
+            "ban" => AdminCommand::Ban {
+                name: required("player name")?,
+            },
+            "unban" => AdminCommand::Unban {
+                name: required("player name")?,
+            },
+            "say" => AdminCommand::Say {
+                text: required("text")?,
+            },
+            "end" => AdminCommand::End {
+                code: required("room code")?.to_uppercase(),
+            },
+            "shutdown" if argument.is_empty() => AdminCommand::Shutdown { seconds: None },
+            "shutdown" => AdminCommand::Shutdown {
+                seconds: Some(
+                    argument
+                        .parse()
+                        .map_err(|_| format!("{} is not a number of seconds", argument))?,
+                ),
+            },
+            _ => return Err(format!("Unknown command {}, try help", command)),
+        })
+    }
+
+    pub fn run(self, server: &mut Server) -> AdminReply {
+        let done = |message: String| AdminReply::Done { message };
+        let error = |message: String| AdminReply::Error { message };
+        match self {
+            AdminCommand::Help => done(USAGE.into()),
+            AdminCommand::Rooms => AdminReply::Rooms {
+                rooms: server.room_statuses(),
+            },
+            AdminCommand::Players => AdminReply::Players {
+                players: server.player_statuses(),
+            },
+            AdminCommand::Room { code } => match server.room_detail(&code) {
+                Some(room) => AdminReply::Room { room },
+                None => error(format!("There is no room {}", code)),
+            },
+            AdminCommand::Kick { id } if server.kick(id) => done(format!("Player {} kicked", id)),
+            AdminCommand::Kick { id } => error(format!("There is no player {}", id)),
+            AdminCommand::Ban { name } => {
+                let kicked = server.ban(&name);
+                done(format!("{} banned, {} connection(s) kicked", name, kicked))
+            }
+            AdminCommand::Unban { name } if server.unban(&name) => {
+                done(format!("{} is no longer banned", name))
+            }
+            AdminCommand::Unban { name } => error(format!("{} is not banned", name)),
+            AdminCommand::Say { text } => {
+                server.announce(&text);
+                done("Announcement sent".into())
+            }
+            AdminCommand::End { code } if server.end_room(&code) => {
+                done(format!("Game in room {} ended", code))
+            }
+            AdminCommand::End { code } => error(format!("There is no room {}", code)),
+            AdminCommand::Shutdown { seconds } => {
+                let after = seconds.map_or(DEFAULT_SHUTDOWN_DELAY, Duration::from_secs);
+                server.shut_down(after);
+                done(format!("Shutting down in {} seconds", after.as_secs()))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerStatus {
+    pub id: PlayerId,
+    pub name: String,
+    // Room the player is seated in
+    pub room: Option<String>,
+    // Room the player is watching
+    pub watching: Option<String>,
+    pub connected: bool,
+    pub bot: bool,
+    // Waiting for a quick match
+    pub queued: bool,
+}
+
+impl std::fmt::Display for PlayerStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:>6} {}", self.id, self.name)?;
+        if let Some(room) = &self.room {
+            write!(f, ", seated in {}", room)?;
+        }
+        if let Some(room) = &self.watching {
+            write!(f, ", watching {}", room)?;
+        }
+        if self.queued {
+            write!(f, ", looking for a match")?;
+        }
+        if !self.bot && !self.connected {
+            write!(f, ", away")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomStatus {
+    pub code: String,
+    pub players: Vec<String>,
+    pub seats: u8,
+    pub spectators: usize,
+    pub started: bool,
+}
+
+impl std::fmt::Display for RoomStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}/{} {} [{}], {} watching",
+            self.code,
+            self.players.len(),
+            self.seats,
+            if self.started { "playing" } else { "waiting" },
+            self.players.join(", "),
+            self.spectators
+        )
+    }
+}
+
+// Everything about a room, hands and deck included, so keep it away from the players
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomDetail {
+    pub code: String,
+    pub started: bool,
+    pub seats: Vec<PlayerStatus>,
+    pub spectators: Vec<PlayerStatus>,
+    pub game: GameSnapshot,
+}
+
+impl std::fmt::Display for RoomDetail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cards = |cards: &[Card]| {
+            cards
+                .iter()
+                .map(Card::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let game = &self.game;
+        writeln!(
+            f,
+            "Room {}, {}, round {}, {} to win, seed {}",
+            self.code,
+            if self.started { "playing" } else { "waiting" },
+            game.round,
+            game.rules.target_score,
+            game.seed
+        )?;
+        for player in &game.players {
+            let status = self.seats.iter().find(|seat| seat.id == player.id);
+            writeln!(
+                f,
+                "  {}{} {}: {} points, {} scopas, {} taken, hand [{}]",
+                if player.id == game.active_player {
+                    "*"
+                } else {
+                    " "
+                },
+                player.id,
+                status.map_or(player.name.as_str(), |status| status.name.as_str()),
+                player.points,
+                player.scopas,
+                player.taken.len(),
+                cards(&player.hand)
+            )?;
+        }
+        writeln!(f, "  Table [{}]", cards(&game.table))?;
+        write!(
+            f,
+            "  {} cards in the deck, {} watching",
+            game.deck.len(),
+            self.spectators.len()
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+pub enum AdminReply {
+    Rooms { rooms: Vec<RoomStatus> },
+    Players { players: Vec<PlayerStatus> },
+    Room { room: RoomDetail },
+    Done { message: String },
+    Error { message: String },
+}
+
+impl std::fmt::Display for AdminReply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdminReply::Rooms { rooms } if rooms.is_empty() => write!(f, "No rooms are open"),
+            AdminReply::Rooms { rooms } => {
+                let lines: Vec<String> = rooms.iter().map(RoomStatus::to_string).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            AdminReply::Players { players } if players.is_empty() => {
+                write!(f, "Nobody is connected")
+            }
+            AdminReply::Players { players } => {
+                let lines: Vec<String> = players.iter().map(PlayerStatus::to_string).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            AdminReply::Room { room } => room.fmt(f),
+            AdminReply::Done { message } => write!(f, "{}", message),
+            AdminReply::Error { message } => write!(f, "Error: {}", message),
+        }
+    }
+}
+
+// Command together with the way back to whoever sent it
+pub type AdminRequest = (AdminCommand, Sender<AdminReply>);
+
+// Console and endpoint run on threads of their own and hand the commands to the server loop,
+// which is the only one touching the server
+pub fn channel() -> (Sender<AdminRequest>, Receiver<AdminRequest>) {
+    mpsc::channel()
+}
+
+fn ask(requests: &Sender<AdminRequest>, command: AdminCommand) -> Option<AdminReply> {
+    let (reply_to, reply) = mpsc::channel();
+    requests.send((command, reply_to)).ok()?;
+    reply.recv().ok()
+}
+
+// Reads commands from standard input until it is closed
+pub fn spawn_console(requests: Sender<AdminRequest>) {
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match AdminCommand::parse(&line) {
+                Ok(command) => match ask(&requests, command) {
+                    Some(reply) => println!("{}", reply),
+                    None => break,
+                },
+                Err(e) => println!("{}", e),
+            }
+        }
+    });
+}
+
+// GET /rooms, /players and /rooms/CODE for a look around, POST /command with a JSON command for
+// everything else
+pub fn serve(addr: SocketAddr, requests: Sender<AdminRequest>) -> std::io::Result<()> {
+    http::serve(addr, "admin API", move |request: Request| {
+        let command = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/rooms") => AdminCommand::Rooms,
+            ("GET", "/players") => AdminCommand::Players,
+            ("GET", path) if path.starts_with("/rooms/") => AdminCommand::Room {
+                code: path["/rooms/".len()..].to_uppercase(),
+            },
+            ("POST", "/command") => match serde_json::from_slice(&request.body) {
+                Ok(command) => command,
+                Err(e) => return Response::text(400, format!("{}\n", e)),
+            },
+            (_, "/command") => return Response::text(405, "Use POST\n"),
+            _ => return Response::not_found(),
+        };
+        let Some(reply) = ask(&requests, command) else {
+            return Response::text(503, "Server is stopping\n");
+        };
+        let status = match reply {
+            AdminReply::Error { .. } => 400,
+            _ => 200,
+        };
+        match serde_json::to_vec(&reply) {
+            Ok(body) => Response::json(status, body),
+            Err(e) => Response::text(500, format!("{}\n", e)),
+        }
+    })
+}
@@ -0,0 +1,330 @@
+use crate::error::Result;
+use crate::http::{self, Request, Response};
+use crate::rate_limit::RateLimit;
+
+use log::{info, warn};
+use rand::Rng;
+use renet::transport::{ConnectToken, NETCODE_KEY_BYTES, NETCODE_USER_DATA_BYTES};
+use scopa_lib::protocol::{
+    decode, RejectReason, TokenRequest, MAX_NAME_LENGTH, PROTOCOL_ID, PROTOCOL_VERSION, TOKEN_PATH,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+
+// Client has this long to connect with a token before asking for another
+const TOKEN_EXPIRE_SECONDS: u64 = 60;
+// Same as renet uses for clients without a token
+const CONNECTION_TIMEOUT_SECONDS: i32 = 15;
+
+pub type PrivateKey = [u8; NETCODE_KEY_BYTES];
+
+// Names one address can claim at once, and how fast it can claim more after that
+const CLAIM_BURST: u32 = 5;
+const CLAIMS_PER_SECOND: f64 = 1.0 / 60.0;
+// Addresses whose claims are remembered before the ones that can claim again are forgotten
+const MAX_CLAIMERS: usize = 1024;
+// Names claimed on this server before it refuses new ones
+const MAX_ACCOUNTS: usize = 100_000;
+
+// What came of a sign-in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignIn {
+    Accepted,
+    // Name was claimed with another secret
+    WrongSecret,
+    // Address claimed too many names lately
+    TooManyClaims,
+    // Server has as many names as it takes
+    Full,
+}
+
+// One line of the accounts file
+#[derive(Serialize, Deserialize)]
+struct Claim {
+    name: String,
+    hash: String,
+}
+
+// Names claimed on this server, each with a hash of the secret of the client that claimed it.
+// Claims are appended to a file with a JSON claim on every line, oldest first.
+#[derive(Debug)]
+pub struct Accounts {
+    path: PathBuf,
+    secrets: HashMap<String, String>,
+    claimers: HashMap<IpAddr, RateLimit>,
+    opened: Instant,
+}
+
+impl Accounts {
+    // Missing file only means nobody has claimed a name yet. Lines which can't be read, e.g. one
+    // cut short by a crash, are skipped.
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let mut secrets = HashMap::new();
+        match fs::File::open(&path) {
+            Ok(file) => {
+                for (number, line) in BufReader::new(file).lines().enumerate() {
+                    match serde_json::from_str::<Claim>(&line?) {
+                        Ok(claim) => {
+                            secrets.entry(claim.name).or_insert(claim.hash);
+                        }
+                        Err(e) => warn!("Skipping line {} of the accounts: {}", number + 1, e),
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(Self {
+            path,
+            secrets,
+            claimers: HashMap::new(),
+            opened: Instant::now(),
+        })
+    }
+
+    // First secret given for a name claims it, later ones have to be the same
+    pub fn sign_in(&mut self, name: &str, secret: &str, peer: IpAddr) -> Result<SignIn> {
+        let hash = hash_secret(name, secret);
+        if let Some(known) = self.secrets.get(name) {
+            return Ok(match *known == hash {
+                true => SignIn::Accepted,
+                false => SignIn::WrongSecret,
+            });
+        }
+        if self.secrets.len() >= MAX_ACCOUNTS {
+            return Ok(SignIn::Full);
+        }
+        let now = self.opened.elapsed();
+        if self.claimers.len() >= MAX_CLAIMERS {
+            self.claimers.retain(|_, claims| !claims.is_full(now));
+        }
+        let allowed = self
+            .claimers
+            .entry(peer)
+            .or_insert_with(|| RateLimit::new(CLAIM_BURST, CLAIMS_PER_SECOND))
+            .allow(now);
+        if !allowed {
+            return Ok(SignIn::TooManyClaims);
+        }
+        let claim = Claim {
+            name: name.into(),
+            hash,
+        };
+        let mut line = serde_json::to_vec(&claim)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        self.secrets.insert(claim.name, claim.hash);
+        info!("{} claimed their name", name);
+        Ok(SignIn::Accepted)
+    }
+}
+
+fn hash_secret(name: &str, secret: &str) -> String {
+    Sha256::new()
+        .chain_update(name.as_bytes())
+        .chain_update([0])
+        .chain_update(secret.as_bytes())
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Token carries the name as its user data, prefixed with its length in bytes
+fn user_data(name: &str) -> [u8; NETCODE_USER_DATA_BYTES] {
+    let mut data = [0; NETCODE_USER_DATA_BYTES];
+    data[0] = name.len() as u8;
+    data[1..=name.len()].copy_from_slice(name.as_bytes());
+    data
+}
+
+pub fn name_from_user_data(data: &[u8; NETCODE_USER_DATA_BYTES]) -> Option<String> {
+    let bytes = data.get(1..=data[0] as usize)?;
+    String::from_utf8(bytes.to_vec()).ok()
+}
+
+// Why a token or a sign-in was refused, with the HTTP status that goes with it
+#[derive(Debug)]
+pub struct Refusal {
+    pub status: u16,
+    pub reason: String,
+}
+
+impl Refusal {
+    fn new(status: u16, reason: impl ToString) -> Self {
+        Self {
+            status,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+// Checks the name and the secret of a client, returns the name as hello has to give it
+pub fn sign_in(
+    accounts: &Mutex<Accounts>,
+    request: &TokenRequest,
+    peer: IpAddr,
+) -> std::result::Result<String, Refusal> {
+    if request.version != PROTOCOL_VERSION {
+        let reason = RejectReason::VersionMismatch {
+            server: PROTOCOL_VERSION,
+        };
+        return Err(Refusal::new(400, reason));
+    }
+    // Names are checked the same way hello checks them, so the two always agree
+    let name = request.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(Refusal::new(400, RejectReason::InvalidName));
+    }
+    let Ok(mut accounts) = accounts.lock() else {
+        return Err(Refusal::new(500, "Your name can't be checked right now"));
+    };
+    match accounts.sign_in(name, &request.secret, peer) {
+        Ok(SignIn::Accepted) => Ok(name.into()),
+        Ok(SignIn::WrongSecret) => Err(Refusal::new(
+            403,
+            "Somebody else plays under that name here",
+        )),
+        Ok(SignIn::TooManyClaims) => Err(Refusal::new(
+            429,
+            "Too many names were claimed from your address, try again later",
+        )),
+        Ok(SignIn::Full) => Err(Refusal::new(503, "No more names can be claimed here")),
+        Err(e) => {
+            warn!("{} can't claim their name: {}", name, e);
+            Err(Refusal::new(500, "Your name can't be saved right now"))
+        }
+    }
+}
+
+// Signs connect tokens for the names their owners sign in with
+pub struct TokenIssuer {
+    accounts: Arc<Mutex<Accounts>>,
+    private_key: PrivateKey,
+    server_addr: SocketAddr,
+}
+
+impl TokenIssuer {
+    pub fn new(
+        accounts: Arc<Mutex<Accounts>>,
+        private_key: PrivateKey,
+        server_addr: SocketAddr,
+    ) -> Self {
+        Self {
+            accounts,
+            private_key,
+            server_addr,
+        }
+    }
+
+    fn issue(&self, request: &TokenRequest, peer: IpAddr) -> Response {
+        let name = match sign_in(&self.accounts, request, peer) {
+            Ok(name) => name,
+            Err(refusal) => {
+                return Response::text(refusal.status, format!("{}\n", refusal.reason));
+            }
+        };
+        // Top half is left to bots and WebSocket connections
+        let client_id = rand::thread_rng().gen_range(1..u64::MAX / 2);
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let token = ConnectToken::generate(
+            now,
+            PROTOCOL_ID,
+            TOKEN_EXPIRE_SECONDS,
+            client_id,
+            CONNECTION_TIMEOUT_SECONDS,
+            vec![self.server_addr],
+            Some(&user_data(&name)),
+            &self.private_key,
+        );
+        let mut bytes = Vec::new();
+        let written = token
+            .map_err(|e| e.to_string())
+            .and_then(|token| token.write(&mut bytes).map_err(|e| e.to_string()));
+        match written {
+            Ok(()) => Response::new(200, "application/octet-stream", bytes),
+            Err(e) => {
+                warn!("Token for {} can't be issued: {}", name, e);
+                Response::text(500, "Token can't be issued\n")
+            }
+        }
+    }
+}
+
+// Served on the game port over TCP, clients ask here before connecting
+// Plain HTTP: names, secrets and tokens aren't encrypted, put a TLS proxy in front if that matters
+pub fn serve(addr: SocketAddr, issuer: TokenIssuer) -> std::io::Result<()> {
+    http::serve(addr, "connect tokens", move |request: Request| {
+        if request.path != TOKEN_PATH {
+            return Response::not_found();
+        }
+        if request.method != "POST" {
+            return Response::text(405, "Use POST\n");
+        }
+        match decode::<TokenRequest>(&request.body) {
+            Ok(token_request) => issuer.issue(&token_request, request.peer),
+            Err(e) => Response::text(400, format!("{}\n", e)),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn claims_are_limited_and_kept() {
+        let path = std::env::temp_dir().join(format!("phantom-accounts-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut accounts = Accounts::open(path.clone()).unwrap();
+        let (peer, other) = (
+            Ipv4Addr::new(10, 0, 0, 1).into(),
+            Ipv4Addr::LOCALHOST.into(),
+        );
+        for i in 0..CLAIM_BURST {
+            let name = format!("Player {}", i);
+            assert_eq!(
+                accounts.sign_in(&name, "secret", peer).unwrap(),
+                SignIn::Accepted
+            );
+        }
+        let result = accounts.sign_in("One more", "secret", peer).unwrap();
+        assert_eq!(result, SignIn::TooManyClaims);
+        assert_eq!(
+            accounts.sign_in("One more", "secret", other).unwrap(),
+            SignIn::Accepted
+        );
+        // Names already claimed can still sign in from anywhere
+        let result = accounts.sign_in("Player 0", "secret", peer).unwrap();
+        assert_eq!(result, SignIn::Accepted);
+        let result = accounts.sign_in("Player 0", "guess", other).unwrap();
+        assert_eq!(result, SignIn::WrongSecret);
+
+        // Every claim is a line of its own, one cut short is skipped
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"name\":\"Cut")
+            .unwrap();
+        let mut accounts = Accounts::open(path.clone()).unwrap();
+        assert_eq!(accounts.secrets.len(), CLAIM_BURST as usize + 1);
+        let result = accounts.sign_in("One more", "guess", peer).unwrap();
+        assert_eq!(result, SignIn::WrongSecret);
+        fs::remove_file(path).unwrap();
+    }
+}
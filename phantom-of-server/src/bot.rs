@@ -0,0 +1,104 @@
+use rand::seq::SliceRandom;
+use scopa_lib::card::{Card, CardValue, Suite};
+use scopa_lib::protocol::{ClientMessage, Difficulty};
+use scopa_lib::{GameEvent, ScopaGame};
+use std::time::Duration;
+
+// Bots wait a moment before moving, so people can follow what happens on the table
+pub const BOT_THINKING_TIME: Duration = Duration::from_millis(1500);
+
+// Computer player living in the server. It only reads the game and sends the same messages a
+// client would, so its moves are checked like anybody else's.
+#[derive(Debug)]
+pub struct Bot {
+    difficulty: Difficulty,
+    // When the bot noticed it is its turn
+    thinking_since: Option<Duration>,
+}
+
+impl Bot {
+    pub fn new(difficulty: Difficulty) -> Self {
+        Self {
+            difficulty,
+            thinking_since: None,
+        }
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    // Turn is over without the bot, its next one starts afresh
+    pub fn stop_thinking(&mut self) {
+        self.thinking_since = None;
+    }
+
+    // Move of the bot once it thought long enough. Only asked while it's the bot's turn.
+    pub fn think(&mut self, game: &ScopaGame, now: Duration) -> Option<ClientMessage> {
+        let since = *self.thinking_since.get_or_insert(now);
+        if now < since + BOT_THINKING_TIME {
+            return None;
+        }
+        self.thinking_since = None;
+        match choose_move(game, self.difficulty)? {
+            GameEvent::PutCard { card, .. } => Some(ClientMessage::PutCard { card }),
+            GameEvent::TakeCards { take, with, .. } => {
+                Some(ClientMessage::TakeCards { take, with })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn choose_move(game: &ScopaGame, difficulty: Difficulty) -> Option<GameEvent> {
+    let mut moves = game.legal_moves();
+    match difficulty {
+        Difficulty::Easy => moves.choose(&mut rand::thread_rng()).cloned(),
+        Difficulty::Normal => moves.drain(..).max_by_key(|event| match event {
+            GameEvent::TakeCards { take, .. } => take.len() as i32,
+            GameEvent::PutCard { card, .. } => -(card.value() as i32),
+            _ => i32::MIN,
+        }),
+        Difficulty::Hard => {
+            let table: Vec<Card> = game.table().copied().collect();
+            moves.drain(..).max_by_key(|event| weigh(event, &table))
+        }
+    }
+}
+
+// Cards worth the most points are the ones to go after and the ones not to give away
+fn worth(card: &Card) -> i32 {
+    let mut worth = 1;
+    if card.suite == Suite::Coins {
+        worth += 1;
+    }
+    match card.value {
+        CardValue::Seven if card.suite == Suite::Coins => worth += 6,
+        CardValue::Seven => worth += 3,
+        CardValue::Six | CardValue::One => worth += 1,
+        _ => {}
+    }
+    worth
+}
+
+fn weigh(event: &GameEvent, table: &[Card]) -> i32 {
+    let (taken, left): (i32, Vec<&Card>) = match event {
+        GameEvent::TakeCards { take, with, .. } => (
+            take.iter().chain([with]).map(worth).sum(),
+            table.iter().filter(|card| !take.contains(card)).collect(),
+        ),
+        GameEvent::PutCard { card, .. } => (-worth(card), table.iter().chain([card]).collect()),
+        _ => return i32::MIN,
+    };
+    let sum: u8 = left.iter().map(|card| card.value()).sum();
+    let scopa = match (
+        left.is_empty(),
+        matches!(event, GameEvent::TakeCards { .. }),
+    ) {
+        (true, true) => 20,
+        // Next player may sweep the table with a single card
+        _ if sum <= 10 => -8,
+        _ => 0,
+    };
+    taken + scopa
+}
@@ -0,0 +1,88 @@
+use scopa_lib::PlayerId;
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Every move has to be made within the turn time. Whatever goes over it is taken from the
+// player's bank, which lasts for the whole game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControl {
+    pub turn: Duration,
+    pub bank: Duration,
+    // Timeouts in a row after which the player forfeits
+    pub max_timeouts: u8,
+}
+
+#[derive(Debug)]
+pub struct TurnClock {
+    control: TimeControl,
+    banks: HashMap<PlayerId, Duration>,
+    timeouts: HashMap<PlayerId, u8>,
+    // Player to move and when their turn started
+    turn: Option<(PlayerId, Duration)>,
+}
+
+impl TurnClock {
+    pub fn new(control: TimeControl) -> Self {
+        Self {
+            control,
+            banks: HashMap::new(),
+            timeouts: HashMap::new(),
+            turn: None,
+        }
+    }
+
+    pub fn start_turn(&mut self, id: PlayerId, now: Duration) {
+        self.turn = Some((id, now));
+    }
+
+    // Charges the player to move for the time they took. Moving in time clears their timeouts.
+    pub fn end_turn(&mut self, now: Duration) {
+        let Some((id, started)) = self.turn.take() else {
+            return;
+        };
+        let overtime = now
+            .saturating_sub(started)
+            .saturating_sub(self.control.turn);
+        let bank = self.bank(id);
+        self.banks.insert(id, bank.saturating_sub(overtime));
+        self.timeouts.remove(&id);
+    }
+
+    // Clock stops until the next game starts and everybody gets a full bank then
+    pub fn reset(&mut self) {
+        self.banks.clear();
+        self.timeouts.clear();
+        self.turn = None;
+    }
+
+    // Player who ran out of time and how many times in a row it happened. Their turn is over and
+    // their bank is empty.
+    pub fn timed_out(&mut self, now: Duration) -> Option<(PlayerId, u8)> {
+        let (id, started) = self.turn?;
+        if now < started + self.control.turn + self.bank(id) {
+            return None;
+        }
+        self.turn = None;
+        self.banks.insert(id, Duration::ZERO);
+        let timeouts = self.timeouts.entry(id).or_default();
+        *timeouts += 1;
+        Some((id, *timeouts))
+    }
+
+    pub fn is_forfeit(&self, timeouts: u8) -> bool {
+        timeouts >= self.control.max_timeouts
+    }
+
+    // Player to move with the turn time and the bank they have left
+    pub fn remaining(&self, now: Duration) -> Option<(PlayerId, Duration, Duration)> {
+        let (id, started) = self.turn?;
+        let elapsed = now.saturating_sub(started);
+        let turn = self.control.turn.saturating_sub(elapsed);
+        let overtime = elapsed.saturating_sub(self.control.turn);
+        Some((id, turn, self.bank(id).saturating_sub(overtime)))
+    }
+
+    fn bank(&self, id: PlayerId) -> Duration {
+        self.banks.get(&id).copied().unwrap_or(self.control.bank)
+    }
+}
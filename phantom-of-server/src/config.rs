@@ -0,0 +1,536 @@
+use crate::bot::BOT_THINKING_TIME;
+use crate::clock::TimeControl;
+use crate::error::{Result, ServerError};
+
+use clap::Parser;
+use log::LevelFilter;
+use scopa_lib::protocol::MAX_NAME_LENGTH;
+use scopa_lib::rules::RuleSet;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Same address the client connects to out of the box
+pub const DEFAULT_IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+pub const DEFAULT_PORT: u16 = 6969;
+pub const DEFAULT_MAX_ROOMS: usize = 32;
+pub const DEFAULT_MAX_CLIENTS: usize = 64;
+pub const DEFAULT_MAX_TIMEOUTS: u8 = 3;
+// Relative to the working directory
+pub const DEFAULT_SNAPSHOT_DIR: &str = "snapshots";
+pub const DEFAULT_HISTORY_FILE: &str = "history.jsonl";
+pub const DEFAULT_ACCOUNTS_FILE: &str = "accounts.jsonl";
+// Shown to players looking for servers on the local network
+pub const DEFAULT_SERVER_NAME: &str = "Scopa server";
+// Read from the working directory when no other file is given
+pub const DEFAULT_CONFIG_PATH: &str = "phantom-of-server.toml";
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Server for Phantom of Scopa")]
+pub struct Args {
+    /// Config file, phantom-of-server.toml in the working directory is used if it exists
+    #[arg(short, long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Address to listen on
+    #[arg(long)]
+    pub ip: Option<IpAddr>,
+    /// Port to listen on
+    #[arg(short, long)]
+    pub port: Option<u16>,
+    /// Address clients reach the server at, needed with connect tokens when listening on 0.0.0.0
+    #[arg(long, value_name = "IP:PORT")]
+    pub public_address: Option<SocketAddr>,
+    /// Name shown to players who find the server on the local network
+    #[arg(long)]
+    pub server_name: Option<String>,
+    /// Don't announce the server on the local network
+    #[arg(long)]
+    pub no_announce: bool,
+    /// Most rooms open at once
+    #[arg(long)]
+    pub max_rooms: Option<usize>,
+    /// Most clients connected at once
+    #[arg(long)]
+    pub max_clients: Option<usize>,
+    /// Points needed to win in rooms created without rules of their own
+    #[arg(long)]
+    pub target_score: Option<u8>,
+    /// Seconds a player has to make a move, 0 turns the limit off
+    #[arg(long, value_name = "SECONDS")]
+    pub turn_time_limit: Option<u64>,
+    /// Seconds each player can go over the turn time limit during a game
+    #[arg(long, value_name = "SECONDS")]
+    pub time_bank: Option<u64>,
+    /// Timeouts in a row after which a player forfeits
+    #[arg(long)]
+    pub max_timeouts: Option<u8>,
+    /// Directory running games are saved to, so they survive a restart
+    #[arg(long, value_name = "DIR")]
+    pub snapshot_dir: Option<PathBuf>,
+    /// Don't save running games, they are lost when the server stops
+    #[arg(long)]
+    pub no_persist: bool,
+    /// File finished matches are recorded to
+    #[arg(long, value_name = "FILE")]
+    pub history_file: Option<PathBuf>,
+    /// File the names claimed by players are kept in
+    #[arg(long, value_name = "FILE")]
+    pub accounts_file: Option<PathBuf>,
+    /// Let clients in without a connect token, anybody can then play under any name
+    #[arg(long)]
+    pub insecure: bool,
+    /// Let wins by more points move the ratings further
+    #[arg(long)]
+    pub rating_by_margin: bool,
+    /// Accept WebSocket clients speaking the protocol in JSON on this port
+    #[arg(long, value_name = "PORT")]
+    pub websocket_port: Option<u16>,
+    /// Serve the admin API on this port of localhost
+    #[arg(long, value_name = "PORT")]
+    pub admin_port: Option<u16>,
+    /// Serve Prometheus metrics and a health check on this port of localhost
+    #[arg(long, value_name = "PORT")]
+    pub metrics_port: Option<u16>,
+    /// One of off, error, warn, info, debug, trace
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<LevelFilter>,
+    /// Print the resulting config and exit without starting the server
+    #[arg(long)]
+    pub check_config: bool,
+    /// Print the matches of a player and exit without starting the server
+    #[arg(long, value_name = "NAME")]
+    pub history: Option<String>,
+    /// Print the full record of a match as JSON and exit without starting the server
+    #[arg(long, value_name = "ID")]
+    pub match_record: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub ip: IpAddr,
+    pub port: u16,
+    // Address put into connect tokens, clients can't reach the server at an unspecified IP such as
+    // 0.0.0.0. The one listened on when not set.
+    pub public_address: Option<SocketAddr>,
+    pub server_name: String,
+    // Whether the server is announced on the local network. Servers on localhost or IPv6 never
+    // are, broadcasts don't get anywhere from there.
+    pub announce: bool,
+    pub max_rooms: usize,
+    pub max_clients: usize,
+    // Seconds a player has to make a move, none for no limit
+    pub turn_time_limit: Option<u64>,
+    // Seconds each player can go over the turn time limit during a game
+    pub time_bank: u64,
+    pub max_timeouts: u8,
+    // Seconds spectators lag behind the players
+    pub spectator_delay: u64,
+    // Whether running games are saved and picked up again after a restart
+    pub persist: bool,
+    pub snapshot_dir: PathBuf,
+    pub history_file: PathBuf,
+    // Whether clients need a connect token, which ties them to the name they claimed
+    pub secure: bool,
+    pub accounts_file: PathBuf,
+    // Whether winning by more points moves the ratings further
+    pub rating_by_margin: bool,
+    // TCP port of WebSocket clients, none to leave them out
+    pub websocket_port: Option<u16>,
+    // Port of the admin API on localhost, none to leave it off
+    pub admin_port: Option<u16>,
+    // Port of /metrics and /health on localhost, none to leave them off
+    pub metrics_port: Option<u16>,
+    // Player names kept out of the server
+    pub banned: Vec<String>,
+    pub log_level: LevelFilter,
+    // Used for rooms created without rules of their own
+    pub rules: RuleSet,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            ip: DEFAULT_IP,
+            port: DEFAULT_PORT,
+            public_address: None,
+            server_name: DEFAULT_SERVER_NAME.into(),
+            announce: true,
+            max_rooms: DEFAULT_MAX_ROOMS,
+            max_clients: DEFAULT_MAX_CLIENTS,
+            turn_time_limit: None,
+            time_bank: 0,
+            max_timeouts: DEFAULT_MAX_TIMEOUTS,
+            spectator_delay: 0,
+            persist: true,
+            snapshot_dir: DEFAULT_SNAPSHOT_DIR.into(),
+            history_file: DEFAULT_HISTORY_FILE.into(),
+            secure: true,
+            accounts_file: DEFAULT_ACCOUNTS_FILE.into(),
+            rating_by_margin: false,
+            websocket_port: None,
+            admin_port: None,
+            metrics_port: None,
+            banned: Vec::new(),
+            log_level: LevelFilter::Info,
+            rules: RuleSet::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    // Config file overridden by the command line. A missing default file is fine, a missing file
+    // that was asked for is not.
+    pub fn load(args: &Args) -> Result<Self> {
+        let mut config = match &args.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => Self::default(),
+        };
+        config.apply(args);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(toml: &str) -> Result<Self> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    fn apply(&mut self, args: &Args) {
+        if let Some(ip) = args.ip {
+            self.ip = ip;
+        }
+        if let Some(port) = args.port {
+            self.port = port;
+        }
+        if let Some(addr) = args.public_address {
+            self.public_address = Some(addr);
+        }
+        if let Some(name) = &args.server_name {
+            self.server_name = name.clone();
+        }
+        if args.no_announce {
+            self.announce = false;
+        }
+        if let Some(max_rooms) = args.max_rooms {
+            self.max_rooms = max_rooms;
+        }
+        if let Some(max_clients) = args.max_clients {
+            self.max_clients = max_clients;
+        }
+        if let Some(target_score) = args.target_score {
+            self.rules.target_score = target_score;
+        }
+        if let Some(seconds) = args.turn_time_limit {
+            self.turn_time_limit = (seconds > 0).then_some(seconds);
+        }
+        if let Some(time_bank) = args.time_bank {
+            self.time_bank = time_bank;
+        }
+        if let Some(max_timeouts) = args.max_timeouts {
+            self.max_timeouts = max_timeouts;
+        }
+        if let Some(dir) = &args.snapshot_dir {
+            self.snapshot_dir = dir.clone();
+        }
+        if args.no_persist {
+            self.persist = false;
+        }
+        if let Some(file) = &args.history_file {
+            self.history_file = file.clone();
+        }
+        if let Some(file) = &args.accounts_file {
+            self.accounts_file = file.clone();
+        }
+        if args.insecure {
+            self.secure = false;
+        }
+        if args.rating_by_margin {
+            self.rating_by_margin = true;
+        }
+        if let Some(port) = args.websocket_port {
+            self.websocket_port = Some(port);
+        }
+        if let Some(port) = args.admin_port {
+            self.admin_port = Some(port);
+        }
+        if let Some(port) = args.metrics_port {
+            self.metrics_port = Some(port);
+        }
+        if let Some(level) = args.log_level {
+            self.log_level = level;
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |reason: &str| Err(ServerError::Config(reason.into()));
+        if self.admin_port.is_some() && self.admin_port == self.metrics_port {
+            return invalid("admin_port and metrics_port must differ");
+        }
+        if self.secure && [self.admin_port, self.metrics_port].contains(&Some(self.port)) {
+            return invalid(
+                "admin_port and metrics_port must differ from port while it serves connect tokens",
+            );
+        }
+        if self.secure && self.public_address().ip().is_unspecified() {
+            return invalid(
+                "public_address must be set to an address clients can reach while ip is unspecified",
+            );
+        }
+        let token_port = self.token_address().map(|addr| addr.port());
+        if self.websocket_port.is_some()
+            && [self.admin_port, self.metrics_port, token_port].contains(&self.websocket_port)
+        {
+            return invalid("websocket_port must differ from the other TCP ports");
+        }
+        if self.server_name.trim().is_empty() || self.server_name.chars().count() > MAX_NAME_LENGTH
+        {
+            return Err(ServerError::Config(format!(
+                "server_name must have from 1 to {} characters",
+                MAX_NAME_LENGTH
+            )));
+        }
+        if self.max_rooms == 0 {
+            return invalid("max_rooms must be at least 1");
+        }
+        if self.max_clients == 0 {
+            return invalid("max_clients must be at least 1");
+        }
+        // Bots would run out of time on every turn and forfeit the game for everybody
+        if let Some(seconds) = self.turn_time_limit {
+            if Duration::from_secs(seconds) <= BOT_THINKING_TIME {
+                return Err(ServerError::Config(format!(
+                    "turn_time_limit must be longer than the {:.1} seconds bots think, leave it \
+                     out for no limit",
+                    BOT_THINKING_TIME.as_secs_f64()
+                )));
+            }
+        }
+        if self.max_timeouts == 0 {
+            return invalid("max_timeouts must be at least 1");
+        }
+        if !self.rules.is_playable() {
+            return invalid("rules can't be played, check players, target_score and prime_table");
+        }
+        Ok(())
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::new(self.ip, self.port)
+    }
+
+    // Address clients are sent to by their connect tokens
+    pub fn public_address(&self) -> SocketAddr {
+        self.public_address.unwrap_or_else(|| self.address())
+    }
+
+    // Connect tokens are handed out on the TCP port with the number of the game port
+    pub fn token_address(&self) -> Option<SocketAddr> {
+        self.secure.then(|| self.address())
+    }
+
+    // Address announcements are sent from
+    pub fn announce_ip(&self) -> Option<IpAddr> {
+        (self.announce && self.ip.is_ipv4() && !self.ip.is_loopback()).then_some(self.ip)
+    }
+
+    pub fn websocket_address(&self) -> Option<SocketAddr> {
+        self.websocket_port
+            .map(|port| SocketAddr::new(self.ip, port))
+    }
+
+    pub fn time_control(&self) -> Option<TimeControl> {
+        self.turn_time_limit.map(|seconds| TimeControl {
+            turn: Duration::from_secs(seconds),
+            bank: Duration::from_secs(self.time_bank),
+            max_timeouts: self.max_timeouts,
+        })
+    }
+
+    pub fn spectator_delay(&self) -> Duration {
+        Duration::from_secs(self.spectator_delay)
+    }
+
+    pub fn snapshot_dir(&self) -> Option<&Path> {
+        self.persist.then_some(self.snapshot_dir.as_path())
+    }
+
+    // Only reachable from the machine the server runs on
+    pub fn admin_address(&self) -> Option<SocketAddr> {
+        self.admin_port
+            .map(|port| SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port))
+    }
+
+    pub fn metrics_address(&self) -> Option<SocketAddr> {
+        self.metrics_port
+            .map(|port| SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Args {
+        Args::parse_from(std::iter::once("phantom-of-server").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn missing_fields_fall_back_to_defaults() {
+        let config = ServerConfig::parse("port = 7000\n[rules]\ntarget_score = 21\n").unwrap();
+        assert_eq!(config.port, 7000);
+        assert_eq!(config.ip, DEFAULT_IP);
+        assert_eq!(config.rules.target_score, 21);
+        assert_eq!(config.rules.players, RuleSet::default().players);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn default_config_round_trips() {
+        let config = ServerConfig::default();
+        assert_eq!(
+            ServerConfig::parse(&config.to_toml().unwrap()).unwrap(),
+            config
+        );
+        assert_eq!(config.address().to_string(), "127.0.0.1:6969");
+        assert_eq!(config.announce_ip(), None);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert!(ServerConfig::parse("max_room = 3").is_err());
+    }
+
+    #[test]
+    fn command_line_overrides_file() {
+        let mut config = ServerConfig::parse("port = 7000\nmax_rooms = 4\n").unwrap();
+        config.apply(&args(&[
+            "--port",
+            "7001",
+            "--target-score",
+            "16",
+            "--log-level",
+            "debug",
+        ]));
+        assert_eq!(config.port, 7001);
+        assert_eq!(config.max_rooms, 4);
+        assert_eq!(config.rules.target_score, 16);
+        assert_eq!(config.log_level, LevelFilter::Debug);
+        assert_eq!(config.time_control(), None);
+
+        config.apply(&args(&["--turn-time-limit", "20", "--time-bank", "60"]));
+        let time_control = config.time_control().unwrap();
+        assert_eq!(time_control.turn, Duration::from_secs(20));
+        assert_eq!(time_control.bank, Duration::from_secs(60));
+        config.apply(&args(&["--turn-time-limit", "0"]));
+        assert_eq!(config.time_control(), None);
+
+        assert_eq!(config.snapshot_dir(), Some(Path::new(DEFAULT_SNAPSHOT_DIR)));
+        config.apply(&args(&["--snapshot-dir", "/tmp/games", "--no-persist"]));
+        assert_eq!(config.snapshot_dir, PathBuf::from("/tmp/games"));
+        assert_eq!(config.snapshot_dir(), None);
+        assert_eq!(config.history_file, PathBuf::from(DEFAULT_HISTORY_FILE));
+        config.apply(&args(&["--history-file", "/tmp/history.jsonl"]));
+        assert_eq!(config.history_file, PathBuf::from("/tmp/history.jsonl"));
+        config.apply(&args(&[
+            "--ip",
+            "192.168.1.20",
+            "--server-name",
+            "Game night",
+        ]));
+        assert_eq!(config.announce_ip(), Some(config.ip));
+        assert_eq!(config.server_name, "Game night");
+        config.apply(&args(&["--no-announce", "--ip", "127.0.0.1"]));
+        assert_eq!(config.announce_ip(), None);
+        assert_eq!(config.token_address(), Some(config.address()));
+        config.apply(&args(&[
+            "--insecure",
+            "--accounts-file",
+            "/tmp/accounts.jsonl",
+        ]));
+        assert_eq!(config.token_address(), None);
+        assert_eq!(config.accounts_file, PathBuf::from("/tmp/accounts.jsonl"));
+        assert_eq!(config.websocket_address(), None);
+        config.apply(&args(&["--websocket-port", "7003"]));
+        assert_eq!(
+            config.websocket_address().unwrap().to_string(),
+            "127.0.0.1:7003"
+        );
+        assert_eq!(config.admin_address(), None);
+        config.apply(&args(&["--admin-port", "7002"]));
+        assert_eq!(
+            config.admin_address().unwrap().to_string(),
+            "127.0.0.1:7002"
+        );
+    }
+
+    #[test]
+    fn nonsense_is_caught() {
+        let mut config = ServerConfig::default();
+        config.apply(&args(&["--target-score", "0"]));
+        assert!(config.validate().is_err());
+        assert!(ServerConfig::parse("max_clients = 0")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(ServerConfig::parse("metrics_port = 6969")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(ServerConfig::parse("metrics_port = 6969\nsecure = false")
+            .unwrap()
+            .validate()
+            .is_ok());
+        assert!(ServerConfig::parse("server_name = \"  \"")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(ServerConfig::parse("websocket_port = 6969")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(ServerConfig::parse("[rules]\nplayers = 5")
+            .unwrap()
+            .validate()
+            .is_err());
+        // Bots need more time than that for their moves
+        assert!(ServerConfig::parse("turn_time_limit = 1")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(ServerConfig::parse("turn_time_limit = 2")
+            .unwrap()
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn tokens_name_a_reachable_address() {
+        let mut config = ServerConfig::parse("ip = \"0.0.0.0\"").unwrap();
+        assert!(config.validate().is_err());
+        config.apply(&args(&["--public-address", "203.0.113.5:6969"]));
+        assert!(config.validate().is_ok());
+        assert_eq!(config.public_address().to_string(), "203.0.113.5:6969");
+        assert_eq!(config.address().to_string(), "0.0.0.0:6969");
+        assert_eq!(config.token_address(), Some(config.address()));
+        assert!(ServerConfig::parse("ip = \"0.0.0.0\"\nsecure = false")
+            .unwrap()
+            .validate()
+            .is_ok());
+        assert_eq!(
+            ServerConfig::default().public_address(),
+            ServerConfig::default().address()
+        );
+    }
+}
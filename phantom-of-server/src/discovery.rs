@@ -0,0 +1,78 @@
+use crate::error::Result;
+
+use log::{debug, warn};
+use scopa_lib::protocol::{
+    encode, ServerAnnouncement, ANNOUNCE_INTERVAL, DISCOVERY_PORT, PROTOCOL_VERSION,
+};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Instant;
+
+// Tells clients on the local network where the server is
+pub struct Announcer {
+    socket: UdpSocket,
+    name: String,
+    port: u16,
+    last: Option<Instant>,
+}
+
+impl Announcer {
+    // Broadcasts go out from the address the server listens on, so they reach the same network
+    pub fn new(ip: IpAddr, name: String, port: u16) -> Result<Self> {
+        let socket = UdpSocket::bind(SocketAddr::new(ip, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            name,
+            port,
+            last: None,
+        })
+    }
+
+    // Does nothing until the interval since the last announcement is over
+    pub fn announce(&mut self, now: Instant, rooms: usize) {
+        if self.last.is_some_and(|last| now - last < ANNOUNCE_INTERVAL) {
+            return;
+        }
+        self.last = Some(now);
+        let announcement = self.announcement(rooms);
+        let broadcast = SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), DISCOVERY_PORT);
+        let encoded = match encode(&announcement) {
+            Ok(encoded) => encoded,
+            Err(e) => return warn!("Announcement can't be encoded: {}", e),
+        };
+        // Networks without broadcast are common enough, and nothing else depends on it
+        if let Err(e) = self.socket.send_to(&encoded, broadcast) {
+            debug!("Announcement not sent: {}", e);
+        }
+    }
+
+    fn announcement(&self, rooms: usize) -> ServerAnnouncement {
+        ServerAnnouncement {
+            version: PROTOCOL_VERSION,
+            name: self.name.clone(),
+            port: self.port,
+            rooms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scopa_lib::protocol::{decode, MAX_ANNOUNCEMENT_SIZE, MAX_NAME_LENGTH};
+
+    #[test]
+    fn announcements_fit_what_clients_read() {
+        // Longest name there can be, in the widest characters
+        let name = "🃏".repeat(MAX_NAME_LENGTH);
+        let announcer = Announcer::new(Ipv4Addr::LOCALHOST.into(), name, u16::MAX).unwrap();
+        let announcement = announcer.announcement(usize::MAX);
+        let encoded = encode(&announcement).unwrap();
+        assert!(encoded.len() <= MAX_ANNOUNCEMENT_SIZE);
+        assert_eq!(
+            decode::<ServerAnnouncement>(&encoded).unwrap(),
+            announcement
+        );
+    }
+}
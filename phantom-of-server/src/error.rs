@@ -0,0 +1,82 @@
+use renet::transport::NetcodeTransportError;
+use std::error::Error;
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, ServerError>;
+
+#[derive(Debug)]
+pub enum ServerError {
+    Io(std::io::Error),
+    Netcode(NetcodeTransportError),
+    Time(std::time::SystemTimeError),
+    TomlDeserialize(toml::de::Error),
+    TomlSerialize(toml::ser::Error),
+    Json(serde_json::Error),
+    // Config that parsed but makes no sense
+    Config(String),
+}
+
+impl std::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ServerError::*;
+        match self {
+            Io(e) => e.fmt(f),
+            Netcode(e) => e.fmt(f),
+            Time(e) => e.fmt(f),
+            TomlDeserialize(e) => e.fmt(f),
+            TomlSerialize(e) => e.fmt(f),
+            Json(e) => e.fmt(f),
+            Config(reason) => write!(f, "Invalid config: {}", reason),
+        }
+    }
+}
+
+impl Error for ServerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ServerError::Io(e) => Some(e),
+            ServerError::Netcode(e) => Some(e),
+            ServerError::Time(e) => Some(e),
+            ServerError::TomlDeserialize(e) => Some(e),
+            ServerError::TomlSerialize(e) => Some(e),
+            ServerError::Json(e) => Some(e),
+            ServerError::Config(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ServerError {
+    fn from(value: std::io::Error) -> Self {
+        ServerError::Io(value)
+    }
+}
+
+impl From<NetcodeTransportError> for ServerError {
+    fn from(value: NetcodeTransportError) -> Self {
+        ServerError::Netcode(value)
+    }
+}
+
+impl From<std::time::SystemTimeError> for ServerError {
+    fn from(value: std::time::SystemTimeError) -> Self {
+        ServerError::Time(value)
+    }
+}
+
+impl From<toml::de::Error> for ServerError {
+    fn from(value: toml::de::Error) -> Self {
+        ServerError::TomlDeserialize(value)
+    }
+}
+
+impl From<toml::ser::Error> for ServerError {
+    fn from(value: toml::ser::Error) -> Self {
+        ServerError::TomlSerialize(value)
+    }
+}
+
+impl From<serde_json::Error> for ServerError {
+    fn from(value: serde_json::Error) -> Self {
+        ServerError::Json(value)
+    }
+}
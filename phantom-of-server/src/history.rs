@@ -0,0 +1,237 @@
+use crate::error::Result;
+
+use log::warn;
+use scopa_lib::rules::{RuleSet, TEAM_PLAYERS};
+use scopa_lib::{GameEvent, PlayerId, Points};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchPlayer {
+    pub id: PlayerId,
+    pub name: String,
+    pub bot: bool,
+    // Left or was taken over by a bot before the end, which loses the match whoever wins it
+    #[serde(default)]
+    pub forfeited: bool,
+    // Points at the end of the match
+    pub points: u8,
+}
+
+// Everything about a finished match. Replaying the events with the rules and the seed gives the
+// very same match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchRecord {
+    // Given by the history once recorded
+    pub id: u64,
+    // Seconds since the Unix epoch
+    pub finished_at: u64,
+    pub room: String,
+    // In seat order
+    pub players: Vec<MatchPlayer>,
+    pub rules: RuleSet,
+    pub seed: u64,
+    pub events: Vec<GameEvent>,
+    // Points and results of every player, round by round
+    pub rounds: Vec<Vec<Points>>,
+    pub winner: PlayerId,
+}
+
+impl MatchRecord {
+    pub fn has_player(&self, name: &str) -> bool {
+        self.players
+            .iter()
+            .any(|player| !player.bot && player.name == name)
+    }
+
+    pub fn winner(&self) -> Option<&MatchPlayer> {
+        self.players.iter().find(|player| player.id == self.winner)
+    }
+
+    // Player sitting opposite in a team game
+    pub fn partner(&self, id: PlayerId) -> Option<&MatchPlayer> {
+        if !self.rules.teams() {
+            return None;
+        }
+        let seat = self.players.iter().position(|player| player.id == id)?;
+        self.players.get((seat + 2) % TEAM_PLAYERS)
+    }
+
+    // Winner and, in a team game, their partner
+    pub fn winners(&self) -> impl Iterator<Item = &MatchPlayer> {
+        let partner = self.partner(self.winner);
+        self.winner().into_iter().chain(partner)
+    }
+
+    pub fn is_winner(&self, id: PlayerId) -> bool {
+        self.winners().any(|player| player.id == id)
+    }
+}
+
+#[cfg(test)]
+impl MatchRecord {
+    // Match won by the first player, with the names and final points given. Names ending in "bot"
+    // are bots.
+    pub(crate) fn won_by_first(players: &[(&str, u8)]) -> Self {
+        Self {
+            id: 0,
+            finished_at: 0,
+            room: "ABCD".into(),
+            players: players
+                .iter()
+                .enumerate()
+                .map(|(i, (name, points))| MatchPlayer {
+                    id: i as PlayerId + 1,
+                    name: name.to_string(),
+                    bot: name.ends_with("bot"),
+                    forfeited: false,
+                    points: *points,
+                })
+                .collect(),
+            rules: RuleSet::default(),
+            seed: 0,
+            events: Vec::new(),
+            rounds: Vec::new(),
+            winner: 1,
+        }
+    }
+}
+
+impl std::fmt::Display for MatchRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let players: Vec<String> = self
+            .players
+            .iter()
+            .map(|player| match player.forfeited {
+                true => format!("{} {} (forfeited)", player.name, player.points),
+                false => format!("{} {}", player.name, player.points),
+            })
+            .collect();
+        let winners: Vec<&str> = self.winners().map(|player| player.name.as_str()).collect();
+        write!(
+            f,
+            "#{} in room {}: {}, won by {} after {} rounds",
+            self.id,
+            self.room,
+            players.join(" - "),
+            match winners.is_empty() {
+                true => "nobody".into(),
+                false => winners.join(" and "),
+            },
+            self.rounds.len()
+        )
+    }
+}
+
+// Append-only file with a JSON record on every line, oldest first
+#[derive(Debug)]
+pub struct MatchHistory {
+    path: PathBuf,
+    next_id: u64,
+}
+
+impl MatchHistory {
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let mut history = Self { path, next_id: 1 };
+        history.next_id = history
+            .records()?
+            .iter()
+            .map(|record| record.id + 1)
+            .max()
+            .unwrap_or(1);
+        Ok(history)
+    }
+
+    // Gives the record its id and writes it down
+    pub fn append(&mut self, mut record: MatchRecord) -> Result<u64> {
+        record.id = self.next_id;
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        self.next_id += 1;
+        Ok(record.id)
+    }
+
+    // Lines which can't be read, e.g. one cut short by a crash, are skipped
+    pub fn records(&self) -> Result<Vec<MatchRecord>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut records = Vec::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            match serde_json::from_str(&line?) {
+                Ok(record) => records.push(record),
+                Err(e) => warn!("Skipping line {} of the match history: {}", number + 1, e),
+            }
+        }
+        Ok(records)
+    }
+
+    pub fn of_player(&self, name: &str) -> Result<Vec<MatchRecord>> {
+        let mut records = self.records()?;
+        records.retain(|record| record.has_player(name));
+        Ok(records)
+    }
+
+    pub fn find(&self, id: u64) -> Result<Option<MatchRecord>> {
+        Ok(self.records()?.into_iter().find(|record| record.id == id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_keeps_every_match() {
+        let path = std::env::temp_dir().join(format!("phantom-history-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut record = MatchRecord::won_by_first(&[("Player 1", 11), ("Hard bot", 4)]);
+        record.seed = 7;
+        let mut history = MatchHistory::open(path.clone()).unwrap();
+        assert_eq!(history.append(record.clone()).unwrap(), 1);
+        assert_eq!(history.append(record.clone()).unwrap(), 2);
+
+        // Ids go on after the last one, a line cut short by a crash is skipped
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"id\":3,")
+            .unwrap();
+        let mut history = MatchHistory::open(path.clone()).unwrap();
+        assert_eq!(history.records().unwrap().len(), 2);
+        assert_eq!(history.of_player("Player 1").unwrap().len(), 2);
+        // Bots have no history
+        assert!(history.of_player("Hard bot").unwrap().is_empty());
+        assert!(history.of_player("Player 2").unwrap().is_empty());
+        assert_eq!(history.find(2).unwrap().unwrap().seed, 7);
+        assert!(history.find(3).unwrap().is_none());
+        fs::remove_file(&path).unwrap();
+        assert!(history.records().unwrap().is_empty());
+        assert_eq!(history.append(record).unwrap(), 3);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn partners_win_together() {
+        let mut record =
+            MatchRecord::won_by_first(&[("A", 11), ("B", 5), ("C", 11), ("Normal bot", 5)]);
+        record.rules.players = TEAM_PLAYERS;
+        assert_eq!(record.partner(1).map(|player| player.id), Some(3));
+        assert_eq!(record.partner(4).map(|player| player.id), Some(2));
+        assert!(record.is_winner(3) && !record.is_winner(2));
+        assert!(record.to_string().contains("won by A and C"));
+        record.rules.players = 2;
+        assert!(record.partner(1).is_none());
+        assert!(!record.is_winner(3));
+    }
+}
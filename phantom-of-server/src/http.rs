@@ -0,0 +1,251 @@
+use log::{info, warn};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Nothing served here needs more, anything bigger is refused
+const MAX_BODY_SIZE: usize = 64 * 1024;
+const MAX_HEADER_LINES: usize = 64;
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+// Whole request has to come in this long, however slowly the caller sends it
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+// Requests handled at the same time, more connections are closed right away
+const MAX_CONNECTIONS: usize = 16;
+
+#[derive(Debug)]
+pub struct Request {
+    pub peer: IpAddr,
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    pub fn json(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self::new(status, "application/json", body)
+    }
+
+    pub fn text(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self::new(status, "text/plain; charset=utf-8", body)
+    }
+
+    pub fn not_found() -> Self {
+        Self::text(404, "Not found\n")
+    }
+}
+
+// Bare HTTP/1.1 for local tools like curl and Prometheus, and for clients asking for connect
+// tokens: one request per connection, each on a thread of its own
+pub fn serve<F>(addr: SocketAddr, name: &'static str, handler: F) -> std::io::Result<()>
+where
+    F: Fn(Request) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr)?;
+    info!("Serving {} on http://{}", name, addr);
+    let handler = Arc::new(handler);
+    let open = Arc::new(AtomicUsize::new(0));
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            if open.load(Ordering::Relaxed) >= MAX_CONNECTIONS {
+                continue;
+            }
+            open.fetch_add(1, Ordering::Relaxed);
+            let (open, handler) = (open.clone(), handler.clone());
+            std::thread::spawn(move || {
+                if let Err(e) = handle(stream, handler.as_ref()) {
+                    warn!("{} request failed: {}", name, e);
+                }
+                open.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+    });
+    Ok(())
+}
+
+fn handle<F>(mut stream: TcpStream, handler: &F) -> std::io::Result<()>
+where
+    F: Fn(Request) -> Response,
+{
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let peer = stream.peer_addr()?.ip();
+    let deadline = Deadline {
+        stream: &stream,
+        until: Instant::now() + REQUEST_TIMEOUT,
+    };
+    let response = match read_request(deadline, peer) {
+        Ok(request) => handler(request),
+        Err(e) => Response::text(400, format!("{}\n", e)),
+    };
+    write_response(&mut stream, &response)
+}
+
+// Reads that fail once the request has taken too long, instead of waiting for each byte anew
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    until: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(std::io::Error::new(
+                ErrorKind::TimedOut,
+                "Request took too long",
+            ));
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+
+fn read_request(stream: impl Read, peer: IpAddr) -> std::io::Result<Request> {
+    let invalid = |reason: &str| std::io::Error::new(ErrorKind::InvalidData, reason);
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    read_line(&mut reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(invalid("Malformed request line"));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+    let mut length = 0;
+    for _ in 0..MAX_HEADER_LINES {
+        line.clear();
+        read_line(&mut reader, &mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            return Ok(Request {
+                peer,
+                method,
+                path,
+                body,
+            });
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("Malformed content length"))?;
+                if length > MAX_BODY_SIZE {
+                    return Err(invalid("Request body is too big"));
+                }
+            }
+        }
+    }
+    Err(invalid("Too many headers"))
+}
+
+// Lines longer than any request needs are refused rather than read on
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> std::io::Result<()> {
+    reader.take(MAX_LINE_LENGTH).read_line(line)?;
+    if !line.ends_with('\n') {
+        let reason = match line.len() as u64 {
+            MAX_LINE_LENGTH => "Line is too long",
+            _ => "Request ended early",
+        };
+        return Err(std::io::Error::new(ErrorKind::InvalidData, reason));
+    }
+    Ok(())
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> std::io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason,
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn requests_are_bounded() {
+        let peer = Ipv4Addr::LOCALHOST.into();
+        let request = b"POST /token HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi";
+        let request = read_request(&request[..], peer).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/token");
+        assert_eq!(request.body, b"hi");
+
+        // Line without an end isn't read further than any request needs
+        let endless = std::io::repeat(b'a');
+        let e = read_request(endless, peer).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        let e = read_request(&b"GET / HTTP/1.1\r\nHost"[..], peer).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        let big = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        assert!(read_request(big.as_bytes(), peer).is_err());
+    }
+
+    #[test]
+    fn slow_requests_run_out_of_time() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        let deadline = Deadline {
+            stream: &stream,
+            until: Instant::now() + Duration::from_millis(200),
+        };
+        let started = Instant::now();
+        // Each byte comes in time for a timeout per read, but not for the whole request
+        std::thread::spawn(move || {
+            while client.write_all(b"X-Slow: yes\r\n").is_ok() {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        });
+        let e = read_request(deadline, Ipv4Addr::LOCALHOST.into()).unwrap_err();
+        assert!(matches!(
+            e.kind(),
+            ErrorKind::TimedOut | ErrorKind::WouldBlock
+        ));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,19 @@
+// The server is a library so the fuzzer and the integration tests can drive it too
+pub mod admin;
+pub mod auth;
+pub mod bot;
+pub mod clock;
+pub mod config;
+pub mod discovery;
+pub mod error;
+pub mod history;
+pub mod http;
+pub mod matchmaking;
+pub mod metrics;
+pub mod network;
+pub mod rate_limit;
+pub mod rating;
+pub mod room;
+pub mod server;
+pub mod snapshot;
+pub mod websocket;
@@ -1,3 +1,182 @@
+use clap::Parser;
+use phantom_of_server::auth::{self, Accounts, PrivateKey, TokenIssuer};
+use phantom_of_server::config::{Args, ServerConfig};
+use phantom_of_server::discovery::Announcer;
+use phantom_of_server::history::MatchHistory;
+use phantom_of_server::rating::Ratings;
+use phantom_of_server::server::Server;
+use phantom_of_server::snapshot::SnapshotStore;
+use phantom_of_server::websocket::WebSocketTransport;
+use phantom_of_server::{admin, error, metrics, network};
+use std::sync::{Arc, Mutex};
+
 fn main() {
-    println!("Hello, world!");
+    let args = Args::parse();
+    let config = match ServerConfig::load(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if args.check_config {
+        match config.to_toml() {
+            Ok(toml) => print!("{}", toml),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let history = match MatchHistory::open(config.history_file.clone()) {
+        Ok(history) => history,
+        Err(e) => {
+            eprintln!("Can't read {}: {}", config.history_file.display(), e);
+            std::process::exit(1);
+        }
+    };
+    if args.history.is_some() || args.match_record.is_some() {
+        if let Err(e) = print_history(&args, &history) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // RUST_LOG still wins over the config, handy for a quick look at one module
+    env_logger::Builder::new()
+        .filter_level(config.log_level)
+        .parse_env(env_logger::Env::default())
+        .init();
+    let ratings = match history.records() {
+        Ok(records) => Ratings::from_history(&records, config.rating_by_margin),
+        Err(e) => {
+            log::error!("Can't read {}: {}", config.history_file.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let mut server = Server::new(config.rules.clone())
+        .with_max_rooms(config.max_rooms)
+        .with_time_control(config.time_control())
+        .with_spectator_delay(config.spectator_delay())
+        .with_ratings(ratings)
+        .with_banned(config.banned.iter().cloned());
+    let store = match config
+        .snapshot_dir()
+        .map(|dir| SnapshotStore::open(dir.into()))
+    {
+        Some(Ok(store)) => Some(store),
+        Some(Err(e)) => {
+            log::error!("Can't open {}: {}", config.snapshot_dir.display(), e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+    if let Some(store) = &store {
+        match store.load_all() {
+            Ok(snapshots) => server.restore(snapshots),
+            Err(e) => log::warn!("Saved games can't be read: {}", e),
+        }
+    }
+    let (admin_requests, admin_commands) = admin::channel();
+    if let Some(addr) = config.admin_address() {
+        if let Err(e) = admin::serve(addr, admin_requests.clone()) {
+            log::error!("Can't serve the admin API on {}: {}", addr, e);
+            std::process::exit(1);
+        }
+    }
+    admin::spawn_console(admin_requests);
+    let (scrape_requests, scrapes) = metrics::channel();
+    if let Some(addr) = config.metrics_address() {
+        if let Err(e) = metrics::serve(addr, scrape_requests) {
+            log::error!("Can't serve metrics on {}: {}", addr, e);
+            std::process::exit(1);
+        }
+    }
+    let accounts = match config
+        .secure
+        .then(|| Accounts::open(config.accounts_file.clone()))
+    {
+        Some(Ok(accounts)) => Some(Arc::new(Mutex::new(accounts))),
+        Some(Err(e)) => {
+            log::error!("Can't read {}: {}", config.accounts_file.display(), e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+    // New key on every start, tokens handed out before a restart are worthless after it
+    let private_key = config.secure.then(rand::random::<PrivateKey>);
+    if let (Some(addr), Some(private_key), Some(accounts)) =
+        (config.token_address(), private_key, &accounts)
+    {
+        let issuer = TokenIssuer::new(accounts.clone(), private_key, config.public_address());
+        if let Err(e) = auth::serve(addr, issuer) {
+            log::error!("Can't serve connect tokens on {}: {}", addr, e);
+            std::process::exit(1);
+        }
+    }
+    let websocket = match config.websocket_address() {
+        Some(addr) => match WebSocketTransport::listen(addr, config.max_clients, accounts) {
+            Ok(websocket) => {
+                log::info!(
+                    "Accepting WebSocket clients on ws://{}",
+                    websocket.local_addr()
+                );
+                Some(websocket)
+            }
+            Err(e) => {
+                log::error!("Can't accept WebSocket connections on {}: {}", addr, e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let announcer = config.announce_ip().and_then(|ip| {
+        Announcer::new(ip, config.server_name.clone(), config.port)
+            .map_err(|e| log::warn!("Server can't be announced on the local network: {}", e))
+            .ok()
+    });
+    let network = network::Network {
+        addr: config.address(),
+        public_addr: config.public_address(),
+        max_clients: config.max_clients,
+        private_key,
+        websocket,
+        announcer,
+        store,
+        history,
+        admin: admin_commands,
+        metrics: scrapes,
+    };
+    if let Err(e) = network::run(network, server) {
+        log::error!("Server stopped: {}", e);
+        std::process::exit(1);
+    }
+}
+
+// Answers the questions about past matches asked on the command line
+fn print_history(args: &Args, history: &MatchHistory) -> error::Result<()> {
+    if let Some(name) = &args.history {
+        let records = history.of_player(name)?;
+        for record in &records {
+            println!("{}", record);
+        }
+        let won = records
+            .iter()
+            .filter(|record| {
+                record
+                    .winners()
+                    .any(|winner| !winner.bot && !winner.forfeited && winner.name == *name)
+            })
+            .count();
+        println!("{} won {} of {} matches", name, won, records.len());
+    }
+    if let Some(id) = args.match_record {
+        match history.find(id)? {
+            Some(record) => println!("{}", serde_json::to_string_pretty(&record)?),
+            None => eprintln!("There is no match #{}", id),
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,115 @@
+use scopa_lib::PlayerId;
+use std::time::Duration;
+
+// Rating difference players accept right away
+const INITIAL_WINDOW: f64 = 100.0;
+// How much the accepted difference grows for every second of waiting
+const WINDOW_GROWTH: f64 = 10.0;
+// Waiting this long gets the player an offer to play a bot instead
+pub const BOT_OFFER_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+struct Ticket {
+    id: PlayerId,
+    rating: f64,
+    since: Duration,
+    bot_offered: bool,
+}
+
+impl Ticket {
+    fn window(&self, now: Duration) -> f64 {
+        INITIAL_WINDOW + WINDOW_GROWTH * now.saturating_sub(self.since).as_secs_f64()
+    }
+
+    // Both players have to accept the difference between them
+    fn accepts(&self, other: &Ticket, now: Duration) -> bool {
+        let difference = (self.rating - other.rating).abs();
+        difference <= self.window(now) && difference <= other.window(now)
+    }
+}
+
+// Players waiting for a quick match, longest waiting first
+#[derive(Debug, Default)]
+pub struct MatchQueue {
+    tickets: Vec<Ticket>,
+}
+
+impl MatchQueue {
+    pub fn push(&mut self, id: PlayerId, rating: f64, now: Duration) {
+        if !self.contains(id) {
+            self.tickets.push(Ticket {
+                id,
+                rating,
+                since: now,
+                bot_offered: false,
+            });
+        }
+    }
+
+    pub fn remove(&mut self, id: PlayerId) -> bool {
+        let len = self.tickets.len();
+        self.tickets.retain(|ticket| ticket.id != id);
+        self.tickets.len() != len
+    }
+
+    pub fn contains(&self, id: PlayerId) -> bool {
+        self.tickets.iter().any(|ticket| ticket.id == id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tickets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tickets.is_empty()
+    }
+
+    pub fn players(&self) -> Vec<PlayerId> {
+        self.tickets.iter().map(|ticket| ticket.id).collect()
+    }
+
+    pub fn bot_offered(&self, id: PlayerId) -> bool {
+        self.tickets
+            .iter()
+            .any(|ticket| ticket.id == id && ticket.bot_offered)
+    }
+
+    // Takes a group of the given size out of the queue, built around the player who has been
+    // waiting the longest and whose rating everybody in the group accepts
+    pub fn next_group(&mut self, size: usize, now: Duration) -> Option<Vec<PlayerId>> {
+        for first in 0..self.tickets.len() {
+            let mut group = vec![first];
+            for candidate in first + 1..self.tickets.len() {
+                if group.len() == size {
+                    break;
+                }
+                let accepted = group
+                    .iter()
+                    .all(|i| self.tickets[*i].accepts(&self.tickets[candidate], now));
+                if accepted {
+                    group.push(candidate);
+                }
+            }
+            if group.len() == size {
+                let ids = group.iter().map(|i| self.tickets[*i].id).collect();
+                for i in group.into_iter().rev() {
+                    self.tickets.remove(i);
+                }
+                return Some(ids);
+            }
+        }
+        None
+    }
+
+    // Players who waited long enough to be offered a bot, each of them only once
+    pub fn bot_offers(&mut self, now: Duration) -> Vec<PlayerId> {
+        self.tickets
+            .iter_mut()
+            .filter(|ticket| !ticket.bot_offered && now >= ticket.since + BOT_OFFER_AFTER)
+            .map(|ticket| {
+                ticket.bot_offered = true;
+                ticket.id
+            })
+            .collect()
+    }
+}
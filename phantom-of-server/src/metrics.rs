@@ -0,0 +1,329 @@
+use crate::http::{self, Request, Response};
+
+use scopa_lib::ScopaError;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+// Moves per second are averaged over this much time
+const MOVE_RATE_WINDOW: Duration = Duration::from_secs(60);
+// Server loop that doesn't answer within this time is considered stuck
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(2);
+// Every kind is reported from the start, so a rate of rejections works before the first one
+const ERROR_KINDS: [&str; 5] = [
+    "player",
+    "logic",
+    "card",
+    "out_of_turn",
+    "putting_on_full_table",
+];
+
+fn error_kind(error: &ScopaError) -> &'static str {
+    match error {
+        ScopaError::Player(_) => "player",
+        ScopaError::Logic(_) => "logic",
+        ScopaError::Card(_) => "card",
+        ScopaError::OutOfTurn => "out_of_turn",
+        ScopaError::PuttingOnFullTable => "putting_on_full_table",
+    }
+}
+
+// Counters kept by the server as it goes
+#[derive(Debug)]
+pub struct Metrics {
+    games_completed: u64,
+    moves: u64,
+    // Times of the moves made within the rate window
+    recent_moves: VecDeque<Duration>,
+    rejections: BTreeMap<&'static str, u64>,
+    // Protocol messages as they are handed to and taken from the transport
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            games_completed: 0,
+            moves: 0,
+            recent_moves: VecDeque::new(),
+            rejections: ERROR_KINDS.iter().map(|kind| (*kind, 0)).collect(),
+            bytes_in: 0,
+            bytes_out: 0,
+        }
+    }
+}
+
+impl Metrics {
+    pub fn game_completed(&mut self) {
+        self.games_completed += 1;
+    }
+
+    pub fn moved(&mut self, now: Duration) {
+        self.moves += 1;
+        self.recent_moves.push_back(now);
+        self.forget_moves(now);
+    }
+
+    pub fn rejected(&mut self, error: &ScopaError) {
+        *self.rejections.entry(error_kind(error)).or_default() += 1;
+    }
+
+    pub fn received(&mut self, bytes: usize) {
+        self.bytes_in += bytes as u64;
+    }
+
+    pub fn sent(&mut self, bytes: usize) {
+        self.bytes_out += bytes as u64;
+    }
+
+    pub fn moves_per_second(&mut self, now: Duration) -> f64 {
+        self.forget_moves(now);
+        self.recent_moves.len() as f64 / MOVE_RATE_WINDOW.as_secs_f64()
+    }
+
+    fn forget_moves(&mut self, now: Duration) {
+        while self
+            .recent_moves
+            .front()
+            .is_some_and(|at| *at + MOVE_RATE_WINDOW < now)
+        {
+            self.recent_moves.pop_front();
+        }
+    }
+
+    // Prometheus text format, together with the state of the server at the moment
+    pub fn render(&mut self, state: &ServerState, now: Duration) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, f64)]| {
+            let _ = writeln!(text, "# HELP phantom_{} {}", name, help);
+            let _ = writeln!(text, "# TYPE phantom_{} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(text, "phantom_{}{} {}", name, labels, value);
+            }
+        };
+        let one = |value: f64| [(String::new(), value)];
+        metric(
+            "connected_clients",
+            "gauge",
+            "Open connections",
+            &one(state.connected_clients as f64),
+        );
+        metric(
+            "players",
+            "gauge",
+            "Players who said hello, away ones included",
+            &one(state.players as f64),
+        );
+        metric(
+            "active_rooms",
+            "gauge",
+            "Open rooms",
+            &one(state.active_rooms as f64),
+        );
+        metric(
+            "running_games",
+            "gauge",
+            "Rooms with a game going on",
+            &one(state.running_games as f64),
+        );
+        metric(
+            "games_completed_total",
+            "counter",
+            "Games played to the end",
+            &one(self.games_completed as f64),
+        );
+        metric(
+            "moves_total",
+            "counter",
+            "Moves made by players and bots",
+            &one(self.moves as f64),
+        );
+        let moves_per_second = self.moves_per_second(now);
+        metric(
+            "moves_per_second",
+            "gauge",
+            "Moves per second over the last minute",
+            &one(moves_per_second),
+        );
+        let rejections: Vec<(String, f64)> = self
+            .rejections
+            .iter()
+            .map(|(kind, count)| (format!("{{kind=\"{}\"}}", kind), *count as f64))
+            .collect();
+        metric(
+            "rejected_moves_total",
+            "counter",
+            "Moves the rules didn't allow, by kind of error",
+            &rejections,
+        );
+        metric(
+            "received_bytes_total",
+            "counter",
+            "Bytes of messages received from clients",
+            &one(self.bytes_in as f64),
+        );
+        metric(
+            "sent_bytes_total",
+            "counter",
+            "Bytes of messages sent to clients",
+            &one(self.bytes_out as f64),
+        );
+        text
+    }
+}
+
+// Gauges taken from the server when the metrics are asked for
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ServerState {
+    pub connected_clients: usize,
+    pub players: usize,
+    pub active_rooms: usize,
+    pub running_games: usize,
+}
+
+// Metrics are rendered by the server loop, which sends them back on the given channel
+pub type MetricsRequest = Sender<String>;
+
+pub fn channel() -> (Sender<MetricsRequest>, Receiver<MetricsRequest>) {
+    mpsc::channel()
+}
+
+// GET /metrics for Prometheus, GET /health answers whether the server loop is still going
+pub fn serve(addr: SocketAddr, requests: Sender<MetricsRequest>) -> std::io::Result<()> {
+    http::serve(addr, "metrics", move |request: Request| {
+        answer(&request, &requests)
+    })
+}
+
+fn answer(request: &Request, requests: &Sender<MetricsRequest>) -> Response {
+    if !matches!(request.path.as_str(), "/metrics" | "/health") {
+        return Response::not_found();
+    }
+    if request.method != "GET" {
+        return Response::text(405, "Use GET\n");
+    }
+    let (reply_to, reply) = mpsc::channel();
+    let Some(metrics) = requests
+        .send(reply_to)
+        .ok()
+        .and_then(|_| reply.recv_timeout(HEALTH_TIMEOUT).ok())
+    else {
+        return Response::text(503, "Server loop is not responding\n");
+    };
+    match request.path.as_str() {
+        "/metrics" => Response::new(200, "text/plain; version=0.0.4; charset=utf-8", metrics),
+        _ => Response::text(200, "ok\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn get(path: &str) -> Request {
+        Request {
+            peer: Ipv4Addr::LOCALHOST.into(),
+            method: "GET".into(),
+            path: path.into(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn every_metric_is_rendered() {
+        let mut metrics = Metrics::default();
+        metrics.rejected(&ScopaError::OutOfTurn);
+        metrics.rejected(&ScopaError::Logic("Nope".into()));
+        metrics.rejected(&ScopaError::Logic("Nope".into()));
+        metrics.received(10);
+        metrics.sent(25);
+        metrics.moved(Duration::from_secs(1));
+        metrics.game_completed();
+        let state = ServerState {
+            connected_clients: 3,
+            players: 2,
+            active_rooms: 1,
+            running_games: 1,
+        };
+        let text = metrics.render(&state, Duration::from_secs(1));
+        for (name, kind) in [
+            ("connected_clients", "gauge"),
+            ("players", "gauge"),
+            ("active_rooms", "gauge"),
+            ("running_games", "gauge"),
+            ("games_completed_total", "counter"),
+            ("moves_total", "counter"),
+            ("moves_per_second", "gauge"),
+            ("rejected_moves_total", "counter"),
+            ("received_bytes_total", "counter"),
+            ("sent_bytes_total", "counter"),
+        ] {
+            let line = format!("# TYPE phantom_{} {}\n", name, kind);
+            assert!(text.contains(&line), "{} is missing", name);
+        }
+        assert!(text.contains("phantom_connected_clients 3\n"));
+        assert!(text.contains("phantom_games_completed_total 1\n"));
+        assert!(text.contains("phantom_received_bytes_total 10\n"));
+        assert!(text.contains("phantom_sent_bytes_total 25\n"));
+
+        // Every kind of error has a label, whether it happened or not
+        let errors = [
+            ScopaError::Player(String::new()),
+            ScopaError::Logic(String::new()),
+            ScopaError::Card(String::new()),
+            ScopaError::OutOfTurn,
+            ScopaError::PuttingOnFullTable,
+        ];
+        for error in &errors {
+            assert!(ERROR_KINDS.contains(&error_kind(error)));
+        }
+        for (kind, count) in [
+            ("player", 0),
+            ("logic", 2),
+            ("card", 0),
+            ("out_of_turn", 1),
+            ("putting_on_full_table", 0),
+        ] {
+            let line = format!(
+                "phantom_rejected_moves_total{{kind=\"{}\"}} {}\n",
+                kind, count
+            );
+            assert!(text.contains(&line), "{}", line);
+        }
+    }
+
+    #[test]
+    fn move_rate_forgets_old_moves() {
+        let mut metrics = Metrics::default();
+        for second in 0..30 {
+            metrics.moved(Duration::from_secs(second));
+        }
+        assert_eq!(metrics.moves_per_second(Duration::from_secs(30)), 0.5);
+        // Moves made more than a minute ago don't count any more
+        assert_eq!(metrics.moves_per_second(Duration::from_secs(75)), 0.25);
+        assert_eq!(metrics.moves_per_second(Duration::from_secs(90)), 0.0);
+        assert_eq!(metrics.moves, 30);
+    }
+
+    #[test]
+    fn health_needs_the_server_loop() {
+        let (requests, incoming) = channel();
+        let server_loop = std::thread::spawn(move || {
+            let reply: MetricsRequest = incoming.recv().unwrap();
+            reply.send("phantom_players 0\n".into()).unwrap();
+            // Stuck from here on
+            let _stuck = incoming.recv();
+            std::thread::sleep(HEALTH_TIMEOUT * 2);
+        });
+        assert_eq!(answer(&get("/health"), &requests).status, 200);
+        assert_eq!(answer(&get("/health"), &requests).status, 503);
+        server_loop.join().unwrap();
+        // Stopped altogether
+        assert_eq!(answer(&get("/metrics"), &requests).status, 503);
+        assert_eq!(answer(&get("/nothing"), &requests).status, 404);
+    }
+}
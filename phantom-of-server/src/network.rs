@@ -0,0 +1,183 @@
+use crate::admin::AdminRequest;
+use crate::auth::{self, PrivateKey};
+use crate::discovery::Announcer;
+use crate::error::Result;
+use crate::history::MatchHistory;
+use crate::metrics::MetricsRequest;
+use crate::server::Server;
+use crate::snapshot::SnapshotStore;
+use crate::websocket::{WebSocketTransport, FIRST_CONNECTION_ID};
+
+use log::{info, warn};
+use renet::transport::{NetcodeServerTransport, ServerAuthentication, ServerConfig};
+use renet::{ClientId, ConnectionConfig, DefaultChannel, RenetServer, ServerEvent};
+use scopa_lib::protocol::PROTOCOL_ID;
+use std::collections::HashSet;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant, SystemTime};
+
+const TICK: Duration = Duration::from_millis(16);
+
+// Everything the server loop talks to besides the server itself
+pub struct Network {
+    pub addr: SocketAddr,
+    // Where clients reach the server, connect tokens must name it
+    pub public_addr: SocketAddr,
+    pub max_clients: usize,
+    // Clients need a token signed with this key, without one anybody gets in
+    pub private_key: Option<PrivateKey>,
+    // Clients that can't or won't use renet
+    pub websocket: Option<WebSocketTransport>,
+    // Broadcasts where the server is to the local network
+    pub announcer: Option<Announcer>,
+    pub store: Option<SnapshotStore>,
+    pub history: MatchHistory,
+    // Commands from the admin console and API
+    pub admin: Receiver<AdminRequest>,
+    // Scrapes of the metrics endpoint
+    pub metrics: Receiver<MetricsRequest>,
+}
+
+// Running games are saved to the store, if any, after every change. Finished matches go to the
+// history. Returns once the admin shut the server down.
+pub fn run(network: Network, mut server: Server) -> Result<()> {
+    let Network {
+        addr,
+        public_addr,
+        max_clients,
+        private_key,
+        mut websocket,
+        mut announcer,
+        store,
+        mut history,
+        admin,
+        metrics,
+    } = network;
+    let mut renet = RenetServer::new(ConnectionConfig::default());
+    let socket = UdpSocket::bind(addr)?;
+    let server_config = ServerConfig {
+        current_time: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?,
+        max_clients,
+        protocol_id: PROTOCOL_ID,
+        public_addresses: vec![public_addr],
+        authentication: match private_key {
+            Some(private_key) => ServerAuthentication::Secure { private_key },
+            None => ServerAuthentication::Unsecure,
+        },
+    };
+    let mut transport = NetcodeServerTransport::new(server_config, socket)?;
+    info!("Listening on {}", addr);
+
+    // Refused connections whose disconnect the server mustn't hear about, their ids are somebody
+    // else's
+    let mut refused = HashSet::new();
+    let started = Instant::now();
+    let mut last_update = started;
+    loop {
+        let now = Instant::now();
+        let delta = now - last_update;
+        last_update = now;
+        renet.update(delta);
+        transport.update(delta, &mut renet)?;
+        server.update(now - started);
+
+        while let Some(event) = renet.get_event() {
+            match event {
+                ServerEvent::ClientConnected { client_id } => {
+                    // Insecure clients choose their own ids, the top half belongs to WebSocket
+                    // connections and bots
+                    if client_id.raw() >= FIRST_CONNECTION_ID
+                        || !server.client_connected(client_id.raw())
+                    {
+                        warn!("Client {} refused, its id is taken", client_id);
+                        refused.insert(client_id);
+                        renet.disconnect(client_id);
+                        continue;
+                    }
+                    info!("Client {} connected", client_id);
+                    if let Some(name) = transport
+                        .user_data(client_id)
+                        .and_then(|data| auth::name_from_user_data(&data))
+                    {
+                        server.client_authenticated(client_id.raw(), name);
+                    }
+                }
+                ServerEvent::ClientDisconnected { client_id, reason } => {
+                    if refused.remove(&client_id) {
+                        continue;
+                    }
+                    info!("Client {} disconnected: {}", client_id, reason);
+                    server.client_disconnected(client_id.raw());
+                }
+            }
+        }
+        for client_id in renet.clients_id() {
+            if refused.contains(&client_id) {
+                continue;
+            }
+            while let Some(message) =
+                renet.receive_message(client_id, DefaultChannel::ReliableOrdered)
+            {
+                server.handle_message(client_id.raw(), &message);
+            }
+        }
+        if let Some(websocket) = &mut websocket {
+            websocket.receive(&mut server);
+        }
+        while let Ok((command, reply)) = admin.try_recv() {
+            // Whoever asked may be gone already
+            let _ = reply.send(command.run(&mut server));
+        }
+        while let Ok(reply) = metrics.try_recv() {
+            let _ = reply.send(server.metrics());
+        }
+        for (id, message) in server.drain_outbox() {
+            match &mut websocket {
+                Some(websocket) if websocket.is_connected(id) => websocket.send(id, message),
+                _ => renet.send_message(
+                    ClientId::from_raw(id),
+                    DefaultChannel::ReliableOrdered,
+                    message,
+                ),
+            }
+        }
+
+        for (code, snapshot) in server.drain_snapshots() {
+            let Some(store) = &store else {
+                continue;
+            };
+            let saved = match snapshot {
+                Some(snapshot) => store.save(&snapshot),
+                None => store.remove(&code),
+            };
+            if let Err(e) = saved {
+                warn!("Room {} can't be saved: {}", code, e);
+            }
+        }
+
+        for record in server.drain_finished_matches() {
+            match history.append(record) {
+                Ok(id) => info!("Match #{} recorded", id),
+                Err(e) => warn!("Match can't be recorded: {}", e),
+            }
+        }
+
+        if let Some(announcer) = &mut announcer {
+            announcer.announce(now, server.room_count());
+        }
+        transport.send_packets(&mut renet);
+        for id in server.drain_disconnects() {
+            match &mut websocket {
+                Some(websocket) if websocket.is_connected(id) => websocket.disconnect(id),
+                _ => renet.disconnect(ClientId::from_raw(id)),
+            }
+        }
+        if server.is_shut_down() {
+            info!("Server shut down");
+            transport.disconnect_all(&mut renet);
+            return Ok(());
+        }
+        std::thread::sleep(TICK);
+    }
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+// Token bucket: allows bursts of up to `capacity` actions and refills at a steady rate after them
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    capacity: f64,
+    per_second: f64,
+    tokens: f64,
+    // Time of the last refill, as reported by the transport
+    refilled: Duration,
+}
+
+impl RateLimit {
+    pub fn new(capacity: u32, per_second: f64) -> Self {
+        Self {
+            capacity: f64::from(capacity),
+            per_second,
+            tokens: f64::from(capacity),
+            refilled: Duration::ZERO,
+        }
+    }
+
+    // Whether it has refilled completely, so forgetting it changes nothing
+    pub fn is_full(&self, now: Duration) -> bool {
+        let elapsed = now.saturating_sub(self.refilled).as_secs_f64();
+        self.tokens + elapsed * self.per_second >= self.capacity
+    }
+
+    // Takes a token if there is one left
+    pub fn allow(&mut self, now: Duration) -> bool {
+        let elapsed = now.saturating_sub(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.refilled = self.refilled.max(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use crate::history::MatchRecord;
+
+use scopa_lib::protocol::PlayerRating;
+use std::collections::HashMap;
+
+pub const INITIAL_RATING: f64 = 1500.0;
+// Most rating a player can win or lose in a single match against one opponent
+const K_FACTOR: f64 = 32.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub matches: u32,
+    pub wins: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            rating: INITIAL_RATING,
+            matches: 0,
+            wins: 0,
+        }
+    }
+}
+
+// Elo ratings of players by name. Every pair of people in a match counts as a game of its own
+// between them, bots are left out. Whoever forfeited loses to everybody who didn't.
+#[derive(Debug, Default)]
+pub struct Ratings {
+    ratings: HashMap<String, Rating>,
+    // Whether winning by more points moves the ratings further
+    by_margin: bool,
+}
+
+impl Ratings {
+    pub fn new(by_margin: bool) -> Self {
+        Self {
+            ratings: HashMap::new(),
+            by_margin,
+        }
+    }
+
+    // Ratings follow from the recorded matches, so they need no storage of their own
+    pub fn from_history(records: &[MatchRecord], by_margin: bool) -> Self {
+        let mut ratings = Self::new(by_margin);
+        for record in records {
+            ratings.record(record);
+        }
+        ratings
+    }
+
+    pub fn record(&mut self, record: &MatchRecord) {
+        let people: Vec<_> = record.players.iter().filter(|p| !p.bot).collect();
+        if people.len() < 2 {
+            return;
+        }
+        let target = f64::from(record.rules.target_score.max(1));
+        let mut changes = vec![0.0; people.len()];
+        // Change of each player is the average over the people they played against
+        let mut opponents = vec![0; people.len()];
+        for (i, a) in people.iter().enumerate() {
+            for (j, b) in people.iter().enumerate().skip(i + 1) {
+                // Partners don't play against each other
+                if record
+                    .partner(a.id)
+                    .is_some_and(|partner| partner.id == b.id)
+                {
+                    continue;
+                }
+                opponents[i] += 1;
+                opponents[j] += 1;
+                let score = match (a.forfeited, b.forfeited) {
+                    (false, true) => 1.0,
+                    (true, false) => 0.0,
+                    (false, false) if record.is_winner(a.id) => 1.0,
+                    (false, false) if record.is_winner(b.id) => 0.0,
+                    // Both lost to somebody else, or both gave up
+                    _ => 0.5,
+                };
+                let (rating_a, rating_b) = (self.rating(&a.name), self.rating(&b.name));
+                let expected = 1.0 / (1.0 + 10f64.powf((rating_b - rating_a) / 400.0));
+                // Winning by the whole target score counts twice as much as a narrow win
+                let margin = if self.by_margin {
+                    1.0 + f64::from(a.points.abs_diff(b.points)) / target
+                } else {
+                    1.0
+                };
+                let change = K_FACTOR * margin * (score - expected);
+                changes[i] += change;
+                changes[j] -= change;
+            }
+        }
+        for ((player, change), opponents) in people.iter().zip(changes).zip(opponents) {
+            let rating = self.ratings.entry(player.name.clone()).or_default();
+            rating.rating += change / f64::from(opponents.max(1));
+            rating.matches += 1;
+            if record.is_winner(player.id) && !player.forfeited {
+                rating.wins += 1;
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Rating> {
+        self.ratings.get(name)
+    }
+
+    // Best rated first, ties go by name
+    pub fn leaderboard(&self) -> Vec<PlayerRating> {
+        let mut ranked: Vec<(&String, &Rating)> = self.ratings.iter().collect();
+        ranked.sort_by(|(a_name, a), (b_name, b)| {
+            b.rating
+                .total_cmp(&a.rating)
+                .then_with(|| a_name.cmp(b_name))
+        });
+        ranked
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, rating))| PlayerRating {
+                rank: i as u32 + 1,
+                name: name.clone(),
+                rating: rating.rating.round() as i32,
+                matches: rating.matches,
+                wins: rating.wins,
+            })
+            .collect()
+    }
+
+    fn rating(&self, name: &str) -> f64 {
+        self.get(name)
+            .map_or(INITIAL_RATING, |rating| rating.rating)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scopa_lib::rules::TEAM_PLAYERS;
+
+    fn rating(ratings: &Ratings, name: &str) -> f64 {
+        ratings.get(name).unwrap().rating
+    }
+
+    #[test]
+    fn elo_follows_matches() {
+        let mut ratings = Ratings::new(false);
+        ratings.record(&MatchRecord::won_by_first(&[
+            ("Player 1", 11),
+            ("Player 2", 3),
+        ]));
+        assert_eq!(rating(&ratings, "Player 1"), 1516.0);
+        assert_eq!(rating(&ratings, "Player 2"), 1484.0);
+        // Bots aren't rated and don't move anybody's rating
+        ratings.record(&MatchRecord::won_by_first(&[
+            ("Player 2", 11),
+            ("Hard bot", 0),
+        ]));
+        assert_eq!(rating(&ratings, "Player 2"), 1484.0);
+        assert!(ratings.get("Hard bot").is_none());
+        let leaderboard = ratings.leaderboard();
+        assert_eq!(leaderboard[0].name, "Player 1");
+        assert_eq!((leaderboard[0].matches, leaderboard[0].wins), (1, 1));
+        assert_eq!((leaderboard[1].rank, leaderboard[1].rating), (2, 1484));
+
+        let mut by_margin = Ratings::new(true);
+        by_margin.record(&MatchRecord::won_by_first(&[
+            ("Player 1", 11),
+            ("Player 2", 3),
+        ]));
+        assert!(rating(&by_margin, "Player 1") > 1516.0);
+
+        // Everybody plays everybody, and what one wins the others lose
+        let mut three = Ratings::new(false);
+        three.record(&MatchRecord::won_by_first(&[("A", 11), ("B", 5), ("C", 2)]));
+        let total: f64 = ["A", "B", "C"]
+            .iter()
+            .map(|name| rating(&three, name))
+            .sum();
+        assert!((total - 3.0 * 1500.0).abs() < 1e-9);
+        assert_eq!(rating(&three, "B"), 1492.0);
+    }
+
+    #[test]
+    fn forfeits_lose() {
+        // Winning for somebody who left doesn't count, they lose to everybody else
+        let mut forfeit = MatchRecord::won_by_first(&[("A", 11), ("B", 5)]);
+        forfeit.players[0].forfeited = true;
+        let ratings = Ratings::from_history(&[forfeit], false);
+        assert_eq!(rating(&ratings, "A"), 1484.0);
+        assert_eq!(ratings.get("A").unwrap().wins, 0);
+        assert_eq!(rating(&ratings, "B"), 1516.0);
+    }
+
+    #[test]
+    fn teams_play_the_other_team() {
+        let mut record = MatchRecord::won_by_first(&[("A", 11), ("B", 5), ("C", 11), ("D", 5)]);
+        record.rules.players = TEAM_PLAYERS;
+        let ratings = Ratings::from_history(&[record.clone()], false);
+        // Each player only played the two of the other team
+        for (name, expected, wins) in [("A", 1516.0, 1), ("B", 1484.0, 0), ("C", 1516.0, 1)] {
+            assert_eq!(rating(&ratings, name), expected);
+            assert_eq!(ratings.get(name).unwrap().wins, wins);
+        }
+
+        // With a bot in the losing team, its partner is the only one left to play the winners
+        record.players[3].bot = true;
+        let ratings = Ratings::from_history(&[record], false);
+        assert_eq!(rating(&ratings, "A"), 1516.0);
+        assert_eq!(rating(&ratings, "B"), 1484.0);
+        assert!(ratings.get("D").is_none());
+    }
+}
@@ -0,0 +1,234 @@
+use crate::clock::{TimeControl, TurnClock};
+
+use scopa_lib::protocol::{RoomInfo, ServerMessage};
+use scopa_lib::rules::RuleSet;
+use scopa_lib::{GameEvent, PlayerId, ScopaError, ScopaGame};
+use std::time::Duration;
+
+// A single table with its own game
+#[derive(Debug)]
+pub struct Room {
+    game: ScopaGame,
+    started: bool,
+    spectators: Vec<PlayerId>,
+    // None when moves may take as long as they take
+    clock: Option<TurnClock>,
+    // Everything that happened in the current game
+    events: Vec<GameEvent>,
+    // People who gave up the current game, bots may be playing their seats
+    forfeited: Vec<PlayerId>,
+}
+
+impl Room {
+    pub fn new(rules: RuleSet, time_control: Option<TimeControl>) -> Self {
+        Self {
+            game: ScopaGame::new(rules),
+            started: false,
+            spectators: Vec::new(),
+            clock: time_control.map(TurnClock::new),
+            events: Vec::new(),
+            forfeited: Vec::new(),
+        }
+    }
+
+    // Running game as it was saved. Clock of the player to move starts over.
+    pub fn restore(
+        game: ScopaGame,
+        events: Vec<GameEvent>,
+        forfeited: Vec<PlayerId>,
+        time_control: Option<TimeControl>,
+        now: Duration,
+    ) -> Self {
+        let mut clock = time_control.map(TurnClock::new);
+        if let Some(clock) = &mut clock {
+            clock.start_turn(game.active_player(), now);
+        }
+        Self {
+            game,
+            started: true,
+            spectators: Vec::new(),
+            clock,
+            events,
+            forfeited,
+        }
+    }
+
+    pub fn players(&self) -> &[PlayerId] {
+        self.game.seats()
+    }
+
+    pub fn player_name(&self, id: PlayerId) -> Option<&str> {
+        self.game.player_name(id)
+    }
+
+    pub fn rules(&self) -> &RuleSet {
+        self.game.rules()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.game.is_full()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.game.seats().is_empty()
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    // Seats of a game that was won stay taken until their players leave
+    pub fn is_over(&self) -> bool {
+        self.events
+            .iter()
+            .any(|event| matches!(event, GameEvent::PlayerWon { .. }))
+    }
+
+    pub fn game(&self) -> &ScopaGame {
+        &self.game
+    }
+
+    pub fn events(&self) -> &[GameEvent] {
+        &self.events
+    }
+
+    pub fn info(&self, code: &str) -> RoomInfo {
+        RoomInfo {
+            code: code.into(),
+            players: self
+                .players()
+                .iter()
+                .map(|id| self.player_name(*id).unwrap_or_default().into())
+                .collect(),
+            rules: self.rules().clone(),
+            spectators: self.spectators.len(),
+        }
+    }
+
+    pub fn spectators(&self) -> &[PlayerId] {
+        &self.spectators
+    }
+
+    pub fn add_spectator(&mut self, id: PlayerId) {
+        self.spectators.push(id);
+    }
+
+    pub fn remove_spectator(&mut self, id: PlayerId) {
+        self.spectators.retain(|spectator| *spectator != id);
+    }
+
+    // Seats the player and starts the game once the room is full
+    pub fn join(
+        &mut self,
+        id: PlayerId,
+        name: &str,
+        now: Duration,
+    ) -> Result<Vec<GameEvent>, ScopaError> {
+        let mut events = vec![self.game.add_player(id, name)?];
+        if self.game.is_full() {
+            events.extend(self.game.start()?);
+            self.started = true;
+            if let Some(clock) = &mut self.clock {
+                clock.start_turn(self.game.active_player(), now);
+            }
+        }
+        self.events.extend(events.iter().cloned());
+        Ok(events)
+    }
+
+    // Game can't go on with an empty seat, so the rest of the players wait for somebody to join
+    // and start over
+    pub fn leave(&mut self, id: PlayerId) -> Result<Vec<GameEvent>, ScopaError> {
+        let event = self.game.remove_player(id)?;
+        self.events.push(event.clone());
+        if self.started {
+            let mut game = ScopaGame::new(self.game.rules().clone());
+            self.events.clear();
+            for id in self.game.seats() {
+                let name = self.game.player_name(*id).unwrap_or_default();
+                self.events.push(game.add_player(*id, name)?);
+            }
+            self.game = game;
+            self.started = false;
+            self.forfeited.clear();
+            if let Some(clock) = &mut self.clock {
+                clock.reset();
+            }
+        }
+        Ok(vec![event])
+    }
+
+    pub fn play(&mut self, event: GameEvent, now: Duration) -> Result<Vec<GameEvent>, ScopaError> {
+        if self.is_over() {
+            return Err(ScopaError::Logic("The game is over".into()));
+        }
+        let events = self.game.play(event)?;
+        self.events.extend(events.iter().cloned());
+        if let Some(clock) = &mut self.clock {
+            clock.end_turn(now);
+            let won = events
+                .iter()
+                .any(|event| matches!(event, GameEvent::PlayerWon { .. }));
+            if !won {
+                clock.start_turn(self.game.active_player(), now);
+            }
+        }
+        Ok(events)
+    }
+
+    // Game ends right away and the leader among the other players wins it, ties go to whoever
+    // sits first. In a team game the partner loses too.
+    pub fn forfeit(&mut self, id: PlayerId) -> Result<Vec<GameEvent>, ScopaError> {
+        if !self.started || self.is_over() {
+            return Err(ScopaError::Logic("No game to forfeit".into()));
+        }
+        let game = &self.game;
+        let partner = game.partner(id);
+        let winner = game
+            .seats()
+            .iter()
+            .copied()
+            .filter(|seat| *seat != id && Some(*seat) != partner)
+            .rev()
+            .max_by_key(|seat| game.points(*seat).unwrap_or_default())
+            .ok_or_else(|| ScopaError::Logic("Nobody is left to win the game".into()))?;
+        let event = GameEvent::PlayerWon { id: winner };
+        self.events.push(event.clone());
+        self.give_up(id);
+        if let Some(clock) = &mut self.clock {
+            clock.reset();
+        }
+        Ok(vec![event])
+    }
+
+    // Player loses the game however it ends, also when a bot goes on in their place
+    pub fn give_up(&mut self, id: PlayerId) {
+        if !self.forfeited.contains(&id) {
+            self.forfeited.push(id);
+        }
+    }
+
+    pub fn gave_up(&self, id: PlayerId) -> bool {
+        self.forfeited.contains(&id)
+    }
+
+    pub fn timed_out(&mut self, now: Duration) -> Option<(PlayerId, u8)> {
+        self.clock.as_mut()?.timed_out(now)
+    }
+
+    pub fn is_forfeit(&self, timeouts: u8) -> bool {
+        self.clock
+            .as_ref()
+            .is_some_and(|clock| clock.is_forfeit(timeouts))
+    }
+
+    pub fn fallback_move(&self) -> Option<GameEvent> {
+        self.game.fallback_move()
+    }
+
+    // None while nobody's clock is running
+    pub fn turn_timer(&self, now: Duration) -> Option<ServerMessage> {
+        let (id, turn, bank) = self.clock.as_ref()?.remaining(now)?;
+        Some(ServerMessage::TurnTimer { id, turn, bank })
+    }
+}
@@ -0,0 +1,2681 @@
+use crate::admin::{PlayerStatus, RoomDetail, RoomStatus};
+use crate::bot::Bot;
+use crate::clock::TimeControl;
+use crate::history::{MatchPlayer, MatchRecord};
+use crate::matchmaking::MatchQueue;
+use crate::metrics::{Metrics, ServerState};
+use crate::rate_limit::RateLimit;
+use crate::rating::{Ratings, INITIAL_RATING};
+use crate::room::Room;
+use crate::snapshot::{RoomSnapshot, SeatSnapshot};
+
+use log::{info, warn};
+use rand::seq::SliceRandom;
+use scopa_lib::protocol::*;
+use scopa_lib::rules::RuleSet;
+use scopa_lib::{GameEvent, PlayerId, ScopaError, ScopaGame};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, SystemTime};
+
+// Room codes are read aloud across the office, so letters and digits that are easy to mix up are
+// left out
+const ROOM_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+// How long the seat of a player with a dropped connection is held in a running game
+pub const SEAT_GRACE_PERIOD: Duration = Duration::from_secs(60);
+pub const MAX_SPECTATORS: usize = 32;
+// Bot playing for players who left a running game for good
+const TAKEOVER_DIFFICULTY: Difficulty = Difficulty::Normal;
+// Bot offered to players who waited too long for a quick match
+const QUICK_MATCH_DIFFICULTY: Difficulty = Difficulty::Normal;
+// Players may send a few chat messages or emotes at once, then one every two seconds
+const CHAT_BURST: u32 = 5;
+const CHAT_PER_SECOND: f64 = 0.5;
+// Clients must say hello this soon after connecting
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// Far more messages than anybody clicking through a game sends, but not enough to flood the server
+const MESSAGE_BURST: u32 = 50;
+const MESSAGES_PER_SECOND: f64 = 10.0;
+// Malformed, misplaced and excess messages a connection may send before it is dropped
+const MAX_STRIKES: u32 = 10;
+
+// Transport's id of a connection. Players keep the id of the connection they said hello from, so
+// after resuming their player id and connection id differ.
+pub type ConnectionId = u64;
+
+#[derive(Debug)]
+struct Connection {
+    // None until the client says hello
+    player: Option<PlayerId>,
+    // Name the connect token was issued for, none when the transport doesn't check
+    identity: Option<String>,
+    opened: Duration,
+    limit: RateLimit,
+    // Malformed, misplaced and excess messages so far
+    strikes: u32,
+}
+
+impl Connection {
+    fn new(opened: Duration) -> Self {
+        Self {
+            player: None,
+            identity: None,
+            opened,
+            limit: RateLimit::new(MESSAGE_BURST, MESSAGES_PER_SECOND),
+            strikes: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Client {
+    name: String,
+    // None while the player is away
+    connection: Option<ConnectionId>,
+    // Code of the room the client is seated in
+    room: Option<String>,
+    // Code of the room the client is watching
+    watching: Option<String>,
+    session: Option<SessionToken>,
+    // Time at which a held seat is given up
+    away_until: Option<Duration>,
+    chat_limit: RateLimit,
+    // Players whose chat and emotes this client doesn't want to see
+    muted: HashSet<PlayerId>,
+}
+
+// Game logic of the server, independent of the transport. Transport reports connections and
+// messages, sends out whatever ends up in the outbox and drops clients the server wants gone.
+#[derive(Debug)]
+pub struct Server {
+    // Used for rooms created without rules of their own
+    rules: RuleSet,
+    rooms: HashMap<String, Room>,
+    max_rooms: usize,
+    // Applies to every room, None for no time limit
+    time_control: Option<TimeControl>,
+    // Connections and players that said hello through them
+    connections: HashMap<ConnectionId, Connection>,
+    clients: HashMap<PlayerId, Client>,
+    // Seated clients played by the server. Their ids count down from the top, far away from the
+    // ids transport gives to connections.
+    bots: HashMap<PlayerId, Bot>,
+    next_bot: PlayerId,
+    outbox: Vec<(ConnectionId, Vec<u8>)>,
+    disconnects: Vec<ConnectionId>,
+    // Spectators see the game this much later than the players
+    spectator_delay: Duration,
+    // Messages for spectators waiting for their time: when, room, spectators and the message
+    delayed: VecDeque<(Duration, String, Vec<PlayerId>, Vec<u8>)>,
+    // Rooms changed since their snapshots were last taken
+    dirty: HashSet<String>,
+    // Matches finished since they were last handed to the history
+    finished: Vec<MatchRecord>,
+    ratings: Ratings,
+    // Players waiting for a quick match
+    queue: MatchQueue,
+    // Names kept out by the admin
+    banned: HashSet<String>,
+    // Time at which the server stops, once the admin asked it to
+    shutdown_at: Option<Duration>,
+    metrics: Metrics,
+    // Time since the server started, as reported by the transport
+    now: Duration,
+}
+
+impl Server {
+    pub fn new(rules: RuleSet) -> Self {
+        Self {
+            rules,
+            rooms: HashMap::new(),
+            max_rooms: usize::MAX,
+            time_control: None,
+            connections: HashMap::new(),
+            clients: HashMap::new(),
+            bots: HashMap::new(),
+            next_bot: PlayerId::MAX,
+            outbox: Vec::new(),
+            disconnects: Vec::new(),
+            spectator_delay: Duration::ZERO,
+            delayed: VecDeque::new(),
+            dirty: HashSet::new(),
+            finished: Vec::new(),
+            ratings: Ratings::default(),
+            queue: MatchQueue::default(),
+            banned: HashSet::new(),
+            shutdown_at: None,
+            metrics: Metrics::default(),
+            now: Duration::ZERO,
+        }
+    }
+
+    pub fn with_max_rooms(mut self, max_rooms: usize) -> Self {
+        self.max_rooms = max_rooms;
+        self
+    }
+
+    pub fn with_time_control(mut self, time_control: Option<TimeControl>) -> Self {
+        self.time_control = time_control;
+        self
+    }
+
+    pub fn with_spectator_delay(mut self, delay: Duration) -> Self {
+        self.spectator_delay = delay;
+        self
+    }
+
+    pub fn with_ratings(mut self, ratings: Ratings) -> Self {
+        self.ratings = ratings;
+        self
+    }
+
+    pub fn with_banned(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.banned.extend(names);
+        self
+    }
+
+    // Brings back the rooms saved before a restart. Their players get the usual grace period to
+    // resume before bots take over their seats.
+    pub fn restore(&mut self, snapshots: Vec<RoomSnapshot>) {
+        for snapshot in snapshots {
+            let code = snapshot.code;
+            let game = match ScopaGame::restore(snapshot.game) {
+                Ok(game) => game,
+                Err(e) => {
+                    warn!("Room {} can't be restored: {}", code, e);
+                    continue;
+                }
+            };
+            let mut seated: Vec<PlayerId> = snapshot.seats.iter().map(|seat| seat.id).collect();
+            seated.sort_unstable();
+            let mut players = game.seats().to_vec();
+            players.sort_unstable();
+            let taken = seated.iter().any(|id| self.clients.contains_key(id));
+            if seated != players || taken || self.rooms.contains_key(&code) {
+                warn!("Room {} can't be restored: seats don't match", code);
+                continue;
+            }
+            let forfeited = snapshot
+                .seats
+                .iter()
+                .filter(|seat| seat.forfeited)
+                .map(|seat| seat.id)
+                .collect();
+            for seat in snapshot.seats {
+                let name = game.player_name(seat.id).unwrap_or_default().into();
+                let away_until = match seat.bot {
+                    Some(difficulty) => {
+                        self.bots.insert(seat.id, Bot::new(difficulty));
+                        None
+                    }
+                    None => Some(self.now + SEAT_GRACE_PERIOD),
+                };
+                self.clients.insert(
+                    seat.id,
+                    Client {
+                        name,
+                        connection: None,
+                        room: Some(code.clone()),
+                        watching: None,
+                        session: seat.session,
+                        away_until,
+                        chat_limit: RateLimit::new(CHAT_BURST, CHAT_PER_SECOND),
+                        muted: HashSet::new(),
+                    },
+                );
+            }
+            info!("Room {} restored", code);
+            let room = Room::restore(
+                game,
+                snapshot.events,
+                forfeited,
+                self.time_control,
+                self.now,
+            );
+            self.rooms.insert(code, room);
+        }
+    }
+
+    // Rooms changed since the last call. Rooms with no running game, including ones whose game
+    // was won, come with None, their snapshots are to be dropped.
+    pub fn drain_snapshots(&mut self) -> Vec<(String, Option<RoomSnapshot>)> {
+        let dirty: Vec<String> = self.dirty.drain().collect();
+        dirty
+            .into_iter()
+            .map(|code| {
+                let snapshot = self.room_snapshot(&code);
+                (code, snapshot)
+            })
+            .collect()
+    }
+
+    pub fn drain_finished_matches(&mut self) -> std::vec::Drain<'_, MatchRecord> {
+        self.finished.drain(..)
+    }
+
+    fn match_record(&self, code: &str, winner: PlayerId) -> Option<MatchRecord> {
+        let room = self.rooms.get(code)?;
+        let game = room.game();
+        // Bots standing in for people are still those people
+        let players = game
+            .seats()
+            .iter()
+            .map(|id| MatchPlayer {
+                id: *id,
+                name: game.player_name(*id).unwrap_or_default().into(),
+                bot: self.bots.contains_key(id) && !room.gave_up(*id),
+                forfeited: room.gave_up(*id),
+                points: game.points(*id).unwrap_or_default(),
+            })
+            .collect();
+        let rounds = room
+            .events()
+            .iter()
+            .filter_map(|event| match event {
+                GameEvent::EndRound { points } => Some(points.clone()),
+                _ => None,
+            })
+            .collect();
+        let finished_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        Some(MatchRecord {
+            id: 0,
+            finished_at,
+            room: code.into(),
+            players,
+            rules: game.rules().clone(),
+            seed: game.seed(),
+            events: room.events().to_vec(),
+            rounds,
+            winner,
+        })
+    }
+
+    fn room_snapshot(&self, code: &str) -> Option<RoomSnapshot> {
+        let room = self
+            .rooms
+            .get(code)
+            .filter(|room| room.is_started() && !room.is_over())?;
+        let seats = room
+            .players()
+            .iter()
+            .map(|id| SeatSnapshot {
+                id: *id,
+                session: self.clients.get(id).and_then(|client| client.session),
+                bot: self.bots.get(id).map(Bot::difficulty),
+                forfeited: room.gave_up(*id),
+            })
+            .collect();
+        Some(RoomSnapshot {
+            code: code.into(),
+            game: room.game().snapshot(),
+            seats,
+            events: room.events().to_vec(),
+        })
+    }
+
+    // Refuses ids of connections which are still open, the transport drops the new one then
+    pub fn client_connected(&mut self, connection: ConnectionId) -> bool {
+        if self.connections.contains_key(&connection) {
+            warn!("Connection {} is already open", connection);
+            return false;
+        }
+        self.connections
+            .insert(connection, Connection::new(self.now));
+        true
+    }
+
+    // Transport vouches for the name of the player on the connection, hello has to agree with it
+    pub fn client_authenticated(&mut self, connection: ConnectionId, name: String) {
+        if let Some(open) = self.connections.get_mut(&connection) {
+            open.identity = Some(name);
+        }
+    }
+
+    // Players in a running game keep their seat for a grace period, everybody else is gone for good
+    pub fn client_disconnected(&mut self, connection: ConnectionId) {
+        let Some(Connection {
+            player: Some(id), ..
+        }) = self.connections.remove(&connection)
+        else {
+            return;
+        };
+        let Some(client) = self.clients.get_mut(&id) else {
+            return;
+        };
+        if client.connection != Some(connection) {
+            // Player already resumed through another connection
+            return;
+        }
+        client.connection = None;
+        let room = client.room.clone();
+        match room {
+            Some(code) if self.rooms.get(&code).is_some_and(Room::is_started) => {
+                info!(
+                    "{} is away, holding their seat in room {}",
+                    client.name, code
+                );
+                client.away_until = Some(self.now + SEAT_GRACE_PERIOD);
+                self.broadcast(&code, &ServerMessage::PlayerAway { id });
+            }
+            _ => self.remove_client(id),
+        }
+    }
+
+    // Advances the server clock, drops connections that never said hello, sends out delayed
+    // messages, hands seats held for too long over to bots, moves for players who ran out of time
+    // and for bots and starts quick matches
+    pub fn update(&mut self, now: Duration) {
+        self.now = now;
+        let silent: Vec<ConnectionId> = self
+            .connections
+            .iter()
+            .filter(|(_, open)| open.player.is_none() && open.opened + HANDSHAKE_TIMEOUT <= now)
+            .map(|(connection, _)| *connection)
+            .collect();
+        for connection in silent {
+            warn!("Client {} didn't say hello in time", connection);
+            self.reject(connection, RejectReason::HandshakeTimeout);
+        }
+        self.send_delayed();
+        let expired: Vec<PlayerId> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.away_until.is_some_and(|until| until <= now))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            match self.room_of(id) {
+                Some(code) if self.has_other_people(&code, id) => self.take_over(id, &code),
+                _ => {
+                    info!("Seat of player {} is no longer held", id);
+                    self.remove_client(id);
+                }
+            }
+        }
+        self.enforce_time_limits();
+        self.move_bots();
+        self.match_players();
+    }
+
+    pub fn handle_message(&mut self, connection: ConnectionId, message: &[u8]) {
+        // Decoder's limit only catches lengths that claim more than there is
+        let decoded = if message.len() > MAX_MESSAGE_SIZE as usize {
+            Err("Message is too long".to_string())
+        } else {
+            decode(message).map_err(|e| e.to_string())
+        };
+        self.handle_decoded(connection, message.len(), decoded);
+    }
+
+    // For transports with an encoding of their own, size is what came over the wire
+    pub fn handle_decoded(
+        &mut self,
+        connection: ConnectionId,
+        size: usize,
+        message: Result<ClientMessage, String>,
+    ) {
+        self.metrics.received(size);
+        let Some(open) = self.connections.get_mut(&connection) else {
+            return;
+        };
+        if !open.limit.allow(self.now) {
+            return self.strike(connection, "Too many messages");
+        }
+        let player = open.player;
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => return self.strike(connection, &e),
+        };
+        match (player, message) {
+            (None, ClientMessage::Hello { version, name }) => {
+                self.hello(connection, version, &name)
+            }
+            (None, ClientMessage::Resume { version, token }) => {
+                self.resume(connection, version, token)
+            }
+            (None, _) => self.strike(connection, "Message before saying hello"),
+            (Some(_), ClientMessage::Hello { .. } | ClientMessage::Resume { .. }) => {
+                self.strike(connection, "Said hello twice")
+            }
+            (Some(id), ClientMessage::ListRooms) => self.list_rooms(id),
+            (Some(id), ClientMessage::CreateRoom { rules }) => self.create_room(id, rules),
+            (Some(id), ClientMessage::JoinRoom { code }) => self.join_room(id, &code),
+            (Some(id), ClientMessage::Spectate { code }) => self.spectate(id, &code),
+            (Some(id), ClientMessage::AddBot { difficulty }) => self.add_bot(id, difficulty),
+            (Some(id), ClientMessage::Leaderboard) => self.leaderboard(id),
+            (Some(id), ClientMessage::FindMatch) => self.find_match(id),
+            (Some(id), ClientMessage::CancelMatch) => self.cancel_match(id),
+            (Some(id), ClientMessage::PlayBot) => self.play_bot(id),
+            (Some(id), ClientMessage::Chat { text }) => self.chat(id, &text),
+            (Some(id), ClientMessage::Emote { emote }) => {
+                self.talk(id, &ServerMessage::Emote { id, emote })
+            }
+            (Some(id), ClientMessage::Mute { id: other, muted }) => self.mute(id, other, muted),
+            (Some(id), ClientMessage::LeaveRoom) => {
+                if let Some(code) = self.room_of(id) {
+                    self.leave(id, &code);
+                } else if self.watched_by(id).is_some() {
+                    self.stop_watching(id);
+                } else {
+                    return self.room_rejected(id, RoomError::NotInRoom);
+                }
+                self.send(id, &ServerMessage::RoomLeft);
+            }
+            (Some(id), message) => self.play(id, message),
+        }
+    }
+
+    pub fn drain_outbox(&mut self) -> std::vec::Drain<'_, (ConnectionId, Vec<u8>)> {
+        let bytes = self.outbox.iter().map(|(_, message)| message.len()).sum();
+        self.metrics.sent(bytes);
+        self.outbox.drain(..)
+    }
+
+    // Prometheus text format
+    pub fn metrics(&mut self) -> String {
+        let state = ServerState {
+            connected_clients: self.connections.len(),
+            players: self.clients.len() - self.bots.len(),
+            active_rooms: self.rooms.len(),
+            running_games: self.rooms.values().filter(|room| room.is_started()).count(),
+        };
+        self.metrics.render(&state, self.now)
+    }
+
+    pub fn room_count(&self) -> usize {
+        self.rooms.len()
+    }
+
+    pub fn drain_disconnects(&mut self) -> std::vec::Drain<'_, ConnectionId> {
+        self.disconnects.drain(..)
+    }
+
+    pub fn room_statuses(&self) -> Vec<RoomStatus> {
+        let mut rooms: Vec<RoomStatus> = self
+            .rooms
+            .iter()
+            .map(|(code, room)| {
+                let info = room.info(code);
+                RoomStatus {
+                    code: info.code,
+                    players: info.players,
+                    seats: room.rules().players as u8,
+                    spectators: info.spectators,
+                    started: room.is_started(),
+                }
+            })
+            .collect();
+        rooms.sort_by(|a, b| a.code.cmp(&b.code));
+        rooms
+    }
+
+    pub fn player_statuses(&self) -> Vec<PlayerStatus> {
+        let mut ids: Vec<PlayerId> = self.clients.keys().copied().collect();
+        ids.sort_unstable();
+        ids.into_iter()
+            .filter_map(|id| self.player_status(id))
+            .collect()
+    }
+
+    fn player_status(&self, id: PlayerId) -> Option<PlayerStatus> {
+        let client = self.clients.get(&id)?;
+        Some(PlayerStatus {
+            id,
+            name: client.name.clone(),
+            room: client.room.clone(),
+            watching: client.watching.clone(),
+            connected: client.connection.is_some(),
+            bot: self.bots.contains_key(&id),
+            queued: self.queue.contains(id),
+        })
+    }
+
+    pub fn room_detail(&self, code: &str) -> Option<RoomDetail> {
+        let room = self.rooms.get(code)?;
+        let statuses = |ids: &[PlayerId]| {
+            ids.iter()
+                .filter_map(|id| self.player_status(*id))
+                .collect()
+        };
+        Some(RoomDetail {
+            code: code.into(),
+            started: room.is_started(),
+            seats: statuses(room.players()),
+            spectators: statuses(room.spectators()),
+            game: room.game().snapshot(),
+        })
+    }
+
+    // Seat of a kicked player in a running game goes to a bot, the same as when they leave for
+    // good. Bots can't be kicked, the room can be ended instead.
+    pub fn kick(&mut self, id: PlayerId) -> bool {
+        self.send_away(id, RejectReason::Kicked)
+    }
+
+    // Returns how many players with the name were kicked
+    pub fn ban(&mut self, name: &str) -> usize {
+        info!("{} banned", name);
+        self.banned.insert(name.into());
+        let ids: Vec<PlayerId> = self
+            .clients
+            .iter()
+            .filter(|(id, client)| client.name == name && !self.bots.contains_key(id))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.into_iter()
+            .filter(|id| self.send_away(*id, RejectReason::Banned))
+            .count()
+    }
+
+    pub fn unban(&mut self, name: &str) -> bool {
+        self.banned.remove(name)
+    }
+
+    pub fn announce(&mut self, text: &str) {
+        info!("Announcement: {}", text);
+        self.send_everybody(&ServerMessage::Announcement { text: text.into() });
+    }
+
+    // Closes the room whatever the state of its game. Nobody won, so the match is not recorded.
+    pub fn end_room(&mut self, code: &str) -> bool {
+        let Some(room) = self.rooms.remove(code) else {
+            return false;
+        };
+        info!("Game in room {} ended by the admin", code);
+        self.dirty.insert(code.into());
+        for id in room.players().iter().chain(room.spectators()) {
+            if self.bots.remove(id).is_some() {
+                self.clients.remove(id);
+                continue;
+            }
+            let Some(client) = self.clients.get_mut(id) else {
+                continue;
+            };
+            client.room = None;
+            client.watching = None;
+            client.session = None;
+            client.away_until = None;
+            if client.connection.is_some() {
+                self.send(*id, &ServerMessage::RoomClosed);
+            } else {
+                self.clients.remove(id);
+            }
+        }
+        true
+    }
+
+    // Everybody is warned, running games are saved as usual and resumed after a restart
+    pub fn shut_down(&mut self, after: Duration) {
+        info!("Shutting down in {} seconds", after.as_secs());
+        self.shutdown_at = Some(self.now + after);
+        self.send_everybody(&ServerMessage::ShuttingDown { after });
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        self.shutdown_at.is_some_and(|at| at <= self.now)
+    }
+
+    fn hello(&mut self, connection: ConnectionId, version: u16, name: &str) {
+        if version != PROTOCOL_VERSION {
+            return self.version_mismatch(connection, version);
+        }
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return self.reject(connection, RejectReason::InvalidName);
+        }
+        if !self.may_use_name(connection, name) {
+            return self.reject(connection, RejectReason::NameMismatch);
+        }
+        if self.banned.contains(name) {
+            info!("Banned {} tried to connect as client {}", name, connection);
+            return self.reject(connection, RejectReason::Banned);
+        }
+        // Player ids are connection ids, and somebody away or a bot may still play under this one
+        if self.clients.contains_key(&connection) {
+            warn!("Client {} has the id of another player", connection);
+            return self.reject(connection, RejectReason::IdInUse);
+        }
+        info!("{} connected as client {}", name, connection);
+        let id = connection;
+        self.identify(connection, id);
+        self.clients.insert(
+            id,
+            Client {
+                name: name.into(),
+                connection: Some(connection),
+                room: None,
+                watching: None,
+                session: None,
+                away_until: None,
+                chat_limit: RateLimit::new(CHAT_BURST, CHAT_PER_SECOND),
+                muted: HashSet::new(),
+            },
+        );
+        self.send(
+            id,
+            &ServerMessage::Welcome {
+                version: PROTOCOL_VERSION,
+                id,
+            },
+        );
+    }
+
+    // Gives the seat back to its owner and tells them everything they missed
+    fn resume(&mut self, connection: ConnectionId, version: u16, token: SessionToken) {
+        if version != PROTOCOL_VERSION {
+            return self.version_mismatch(connection, version);
+        }
+        let Some((&id, client)) = self
+            .clients
+            .iter()
+            .find(|(_, client)| client.session == Some(token))
+        else {
+            return self.reject(connection, RejectReason::SessionExpired);
+        };
+        // Otherwise a leaked session would get around the connect tokens
+        if !self.may_use_name(connection, &client.name) {
+            return self.reject(connection, RejectReason::NameMismatch);
+        }
+        let Some(client) = self.clients.get_mut(&id) else {
+            return;
+        };
+        info!("{} is back as client {}", client.name, connection);
+        // Transport may not have noticed the old connection is dead yet
+        if let Some(old) = client.connection.replace(connection) {
+            self.connections.remove(&old);
+            self.disconnects.push(old);
+        }
+        client.away_until = None;
+        let room = client.room.clone();
+        self.identify(connection, id);
+        self.send(
+            id,
+            &ServerMessage::Welcome {
+                version: PROTOCOL_VERSION,
+                id,
+            },
+        );
+        if let Some(code) = room {
+            if let Some(state) = self.resumed_state(id, &code) {
+                self.send(id, &state);
+            }
+            self.broadcast(&code, &ServerMessage::PlayerBack { id });
+            if let Some(timer) = self.turn_timer(&code) {
+                self.send(id, &timer);
+            }
+        }
+    }
+
+    fn may_use_name(&self, connection: ConnectionId, name: &str) -> bool {
+        self.connections
+            .get(&connection)
+            .and_then(|open| open.identity.as_deref())
+            .is_none_or(|identity| identity == name)
+    }
+
+    fn identify(&mut self, connection: ConnectionId, id: PlayerId) {
+        if let Some(open) = self.connections.get_mut(&connection) {
+            open.player = Some(id);
+        }
+    }
+
+    fn resumed_state(&self, id: PlayerId, code: &str) -> Option<ServerMessage> {
+        let game = self.rooms.get(code)?.game();
+        let seats = game
+            .seats()
+            .iter()
+            .map(|player| SeatState {
+                id: *player,
+                name: game.player_name(*player).unwrap_or_default().into(),
+                points: game.points(*player).unwrap_or_default(),
+                connected: self.is_connected(*player),
+            })
+            .collect();
+        Some(ServerMessage::Resumed {
+            code: code.into(),
+            rules: game.rules().clone(),
+            seats,
+            hand: game.hand(id).unwrap_or_default().to_vec(),
+            table: game.table().copied().collect(),
+            active_player: game.active_player(),
+        })
+    }
+
+    // Free rooms come before full ones, which can be watched, until a message is full
+    fn list_rooms(&mut self, id: PlayerId) {
+        let mut rooms: Vec<(bool, RoomInfo)> = self
+            .rooms
+            .iter()
+            .map(|(code, room)| (room.is_full(), room.info(code)))
+            .collect();
+        rooms.sort_by(|(a_full, a), (b_full, b)| (a_full, &a.code).cmp(&(b_full, &b.code)));
+        // Room count may take a couple more bytes than the empty list has
+        let mut size = encoded(&ServerMessage::RoomList { rooms: Vec::new() })
+            .map_or(0, |empty| empty.len())
+            + 2;
+        let mut listed = Vec::with_capacity(rooms.len());
+        for (_, info) in rooms {
+            size += encode(&info).map_or(usize::MAX, |info| info.len());
+            if size > MAX_MESSAGE_SIZE as usize {
+                break;
+            }
+            listed.push(info);
+        }
+        self.send(id, &ServerMessage::RoomList { rooms: listed });
+    }
+
+    fn leaderboard(&mut self, id: PlayerId) {
+        let Some(name) = self.clients.get(&id).map(|client| client.name.clone()) else {
+            return;
+        };
+        let mut top = self.ratings.leaderboard();
+        let me = top.iter().find(|rating| rating.name == name).cloned();
+        top.truncate(LEADERBOARD_SIZE);
+        self.send(id, &ServerMessage::Leaderboard { top, me });
+    }
+
+    fn create_room(&mut self, id: PlayerId, rules: Option<RuleSet>) {
+        if let Some(reason) = self.busy(id) {
+            return self.room_rejected(id, reason);
+        }
+        let rules = rules.unwrap_or_else(|| self.rules.clone());
+        if !rules.is_playable() {
+            return self.room_rejected(id, RoomError::InvalidRules);
+        }
+        if self.rooms.len() >= self.max_rooms {
+            return self.room_rejected(id, RoomError::TooManyRooms);
+        }
+        let code = self.open_room(rules);
+        self.seat(id, code);
+    }
+
+    fn open_room(&mut self, rules: RuleSet) -> String {
+        let code = self.new_room_code();
+        info!("Room {} created", code);
+        self.rooms
+            .insert(code.clone(), Room::new(rules, self.time_control));
+        code
+    }
+
+    fn find_match(&mut self, id: PlayerId) {
+        if let Some(reason) = self.busy(id) {
+            return self.room_rejected(id, reason);
+        }
+        let Some(name) = self.clients.get(&id).map(|client| client.name.clone()) else {
+            return;
+        };
+        let rating = self
+            .ratings
+            .get(&name)
+            .map_or(INITIAL_RATING, |rating| rating.rating);
+        info!("{} is looking for a match", name);
+        self.queue.push(id, rating, self.now);
+        self.queue_changed();
+    }
+
+    fn cancel_match(&mut self, id: PlayerId) {
+        if !self.queue.remove(id) {
+            return self.room_rejected(id, RoomError::NotInQueue);
+        }
+        self.send(id, &ServerMessage::QueueLeft);
+        self.queue_changed();
+    }
+
+    fn play_bot(&mut self, id: PlayerId) {
+        if !self.queue.bot_offered(id) {
+            return self.room_rejected(id, RoomError::NotInQueue);
+        }
+        if self.rooms.len() >= self.max_rooms {
+            return self.room_rejected(id, RoomError::TooManyRooms);
+        }
+        self.queue.remove(id);
+        self.queue_changed();
+        let code = self.open_room(self.rules.clone());
+        self.seat(id, code);
+        // Bots take every other seat
+        for _ in 1..self.rules.players {
+            self.add_bot(id, QUICK_MATCH_DIFFICULTY);
+        }
+    }
+
+    // Seats groups of waiting players with a similar rating together and offers a bot to those
+    // who waited too long
+    fn match_players(&mut self) {
+        let rules = self.rules.clone();
+        let mut matched = false;
+        while self.rooms.len() < self.max_rooms {
+            let Some(group) = self.queue.next_group(rules.players, self.now) else {
+                break;
+            };
+            let code = self.open_room(rules.clone());
+            info!("Quick match in room {}", code);
+            for id in group {
+                self.seat(id, code.clone());
+            }
+            matched = true;
+        }
+        if matched {
+            self.queue_changed();
+        }
+        for id in self.queue.bot_offers(self.now) {
+            self.send(id, &ServerMessage::BotOffered);
+        }
+    }
+
+    // Everybody waiting learns how many are waiting
+    fn queue_changed(&mut self) {
+        let players = self.queue.len() as u32;
+        for id in self.queue.players() {
+            self.send(id, &ServerMessage::Queued { players });
+        }
+    }
+
+    fn join_room(&mut self, id: PlayerId, code: &str) {
+        if let Some(reason) = self.busy(id) {
+            return self.room_rejected(id, reason);
+        }
+        let code = code.trim().to_uppercase();
+        match self.rooms.get(&code) {
+            None => self.room_rejected(id, RoomError::NotFound),
+            Some(room) if room.is_full() => self.room_rejected(id, RoomError::Full),
+            Some(_) => self.seat(id, code),
+        }
+    }
+
+    fn spectate(&mut self, id: PlayerId, code: &str) {
+        if let Some(reason) = self.busy(id) {
+            return self.room_rejected(id, reason);
+        }
+        let code = code.trim().to_uppercase();
+        let Some(room) = self.rooms.get_mut(&code) else {
+            return self.room_rejected(id, RoomError::NotFound);
+        };
+        if room.spectators().len() >= MAX_SPECTATORS {
+            return self.room_rejected(id, RoomError::Full);
+        }
+        room.add_spectator(id);
+        if let Some(client) = self.clients.get_mut(&id) {
+            info!("{} watches room {}", client.name, code);
+            client.watching = Some(code.clone());
+        }
+        if let Some(state) = self.spectating_state(&code) {
+            self.delay(&code, vec![id], &state);
+        }
+        if let Some(timer) = self.turn_timer(&code) {
+            self.delay(&code, vec![id], &timer);
+        }
+    }
+
+    fn stop_watching(&mut self, id: PlayerId) {
+        let Some(code) = self
+            .clients
+            .get_mut(&id)
+            .and_then(|client| client.watching.take())
+        else {
+            return;
+        };
+        if let Some(room) = self.rooms.get_mut(&code) {
+            room.remove_spectator(id);
+        }
+    }
+
+    // Everything but the hands and the deck
+    fn spectating_state(&self, code: &str) -> Option<ServerMessage> {
+        let room = self.rooms.get(code)?;
+        let game = room.game();
+        let seats = game
+            .seats()
+            .iter()
+            .map(|player| SeatState {
+                id: *player,
+                name: game.player_name(*player).unwrap_or_default().into(),
+                points: game.points(*player).unwrap_or_default(),
+                connected: self.is_connected(*player),
+            })
+            .collect();
+        let piles = game
+            .seats()
+            .iter()
+            .map(|player| PileState {
+                id: *player,
+                hand: game.hand(*player).map_or(0, |hand| hand.len() as u8),
+                taken: game.taken(*player).unwrap_or_default(),
+            })
+            .collect();
+        Some(ServerMessage::Spectating {
+            code: code.into(),
+            rules: game.rules().clone(),
+            seats,
+            piles,
+            table: game.table().copied().collect(),
+            active_player: room.is_started().then(|| game.active_player()),
+        })
+    }
+
+    // Puts the player in a room known to have a free seat, the game starts once the room fills
+    fn seat(&mut self, id: PlayerId, code: String) {
+        let Some(name) = self.clients.get(&id).map(|c| c.name.clone()) else {
+            return;
+        };
+        let Some(room) = self.rooms.get_mut(&code) else {
+            return;
+        };
+        let joined = ServerMessage::RoomJoined {
+            code: code.clone(),
+            rules: room.rules().clone(),
+        };
+        // Newcomer needs to know who is already seated
+        let seated: Vec<ServerMessage> = room
+            .players()
+            .iter()
+            .map(|player| ServerMessage::PlayerJoined {
+                id: *player,
+                name: room.player_name(*player).unwrap_or_default().into(),
+            })
+            .collect();
+        match room.join(id, &name, self.now) {
+            Ok(events) => {
+                info!("{} joined room {}", name, code);
+                let token: SessionToken = rand::random();
+                self.send(id, &joined);
+                self.send(id, &ServerMessage::Session { token });
+                for message in seated {
+                    self.send(id, &message);
+                }
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.room = Some(code.clone());
+                    client.session = Some(token);
+                }
+                self.dispatch(&code, &events);
+            }
+            Err(e) => {
+                warn!("{} can't join room {}: {}", name, code, e);
+                // Game only says in words why it refused, the room tells which case it was
+                let reason = match e {
+                    ScopaError::Player(_) if room.players().contains(&id) => {
+                        RoomError::AlreadyInRoom
+                    }
+                    ScopaError::Player(_) => RoomError::Full,
+                    // Starting the game is all that is left, which fails only for rules it can't
+                    // play
+                    ScopaError::Logic(_)
+                    | ScopaError::Card(_)
+                    | ScopaError::OutOfTurn
+                    | ScopaError::PuttingOnFullTable => RoomError::InvalidRules,
+                };
+                self.room_rejected(id, reason);
+            }
+        }
+    }
+
+    // Empty rooms are closed right away
+    fn leave(&mut self, id: PlayerId, code: &str) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.room = None;
+            client.session = None;
+        }
+        self.dirty.insert(code.into());
+        // Leaving a game before it's over loses it
+        let running = self
+            .rooms
+            .get(code)
+            .is_some_and(|room| room.is_started() && !room.is_over());
+        if running && !self.bots.contains_key(&id) {
+            self.forfeit(id, code);
+        }
+        let Some(room) = self.rooms.get_mut(code) else {
+            return;
+        };
+        match room.leave(id) {
+            Ok(events) => {
+                info!("Player {} left room {}", id, code);
+                self.dispatch(code, &events);
+            }
+            Err(e) => warn!("Player {} left room {}: {}", id, code, e),
+        }
+        // Bots don't play among themselves
+        if !self.has_other_people(code, id) {
+            let bots = self
+                .rooms
+                .get(code)
+                .map(|room| room.players().to_vec())
+                .unwrap_or_default();
+            for bot in bots {
+                self.bots.remove(&bot);
+                self.clients.remove(&bot);
+                if let Some(room) = self.rooms.get_mut(code) {
+                    let _ = room.leave(bot);
+                }
+            }
+        }
+        if self.rooms.get(code).is_some_and(Room::is_empty) {
+            info!("Room {} closed", code);
+            if let Some(room) = self.rooms.remove(code) {
+                for spectator in room.spectators() {
+                    if let Some(client) = self.clients.get_mut(spectator) {
+                        client.watching = None;
+                    }
+                    self.send(*spectator, &ServerMessage::RoomLeft);
+                }
+            }
+        }
+    }
+
+    fn remove_client(&mut self, id: PlayerId) {
+        if let Some(code) = self.room_of(id) {
+            self.leave(id, &code);
+        }
+        if self.queue.remove(id) {
+            self.queue_changed();
+        }
+        self.stop_watching(id);
+        self.clients.remove(&id);
+    }
+
+    // Control characters are dropped, so nobody can mess up the chat of the others
+    fn chat(&mut self, id: PlayerId, text: &str) {
+        let text: String = text.trim().chars().filter(|c| !c.is_control()).collect();
+        if text.is_empty() {
+            return self.chat_rejected(id, ChatError::Empty);
+        }
+        if text.chars().count() > MAX_CHAT_LENGTH {
+            return self.chat_rejected(id, ChatError::TooLong);
+        }
+        let Some(name) = self.clients.get(&id).map(|client| client.name.clone()) else {
+            return;
+        };
+        self.talk(id, &ServerMessage::Chat { id, name, text });
+    }
+
+    // Chat of players reaches the players right away and spectators after the usual delay. Chat
+    // of spectators stays among the spectators.
+    fn talk(&mut self, id: PlayerId, message: &ServerMessage) {
+        let (code, seated) = match (self.room_of(id), self.watched_by(id)) {
+            (Some(code), _) => (code, true),
+            (None, Some(code)) => (code.to_string(), false),
+            _ => return self.chat_rejected(id, ChatError::NotInRoom),
+        };
+        let now = self.now;
+        if let Some(client) = self.clients.get_mut(&id) {
+            if !client.chat_limit.allow(now) {
+                return self.chat_rejected(id, ChatError::TooFast);
+            }
+        }
+        let Some(room) = self.rooms.get(&code) else {
+            return;
+        };
+        let listening = |other: &PlayerId| {
+            self.clients
+                .get(other)
+                .is_some_and(|client| !client.muted.contains(&id))
+        };
+        let spectators: Vec<PlayerId> = room
+            .spectators()
+            .iter()
+            .copied()
+            .filter(listening)
+            .collect();
+        if !seated {
+            let Some(encoded) = encoded(message) else {
+                return;
+            };
+            for spectator in spectators {
+                self.push(spectator, encoded.clone());
+            }
+            return;
+        }
+        let players: Vec<PlayerId> = room.players().iter().copied().filter(listening).collect();
+        let Some(encoded) = encoded(message) else {
+            return;
+        };
+        for player in players {
+            self.push(player, encoded.clone());
+        }
+        self.delay(&code, spectators, message);
+    }
+
+    fn mute(&mut self, id: PlayerId, other: PlayerId, muted: bool) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            if muted {
+                client.muted.insert(other);
+            } else {
+                client.muted.remove(&other);
+            }
+        }
+    }
+
+    fn play(&mut self, id: PlayerId, message: ClientMessage) {
+        let Some(event) = message.to_move(id) else {
+            return;
+        };
+        let Some(code) = self.room_of(id) else {
+            return self.room_rejected(id, RoomError::NotInRoom);
+        };
+        let Some(room) = self.rooms.get_mut(&code) else {
+            return;
+        };
+        match room.play(event, self.now) {
+            Ok(events) => self.dispatch(&code, &events),
+            Err(e) => {
+                self.metrics.rejected(&e);
+                self.send(
+                    id,
+                    &ServerMessage::MoveRejected {
+                        reason: e.to_string(),
+                    },
+                );
+            }
+        }
+    }
+
+    // Moves for the players who ran out of time. Those who keep running out of time lose their
+    // seat.
+    fn enforce_time_limits(&mut self) {
+        let now = self.now;
+        let timed_out: Vec<(String, PlayerId, u8)> = self
+            .rooms
+            .iter_mut()
+            .filter_map(|(code, room)| {
+                let (id, timeouts) = room.timed_out(now)?;
+                Some((code.clone(), id, timeouts))
+            })
+            .collect();
+        for (code, id, timeouts) in timed_out {
+            info!("Player {} ran out of time in room {}", id, code);
+            if let Some(bot) = self.bots.get_mut(&id) {
+                bot.stop_thinking();
+            }
+            self.broadcast(&code, &ServerMessage::TimedOut { id });
+            let Some(room) = self.rooms.get_mut(&code) else {
+                continue;
+            };
+            let fallback = room.fallback_move().filter(|_| !room.is_forfeit(timeouts));
+            let Some(event) = fallback else {
+                info!("Player {} forfeited in room {}", id, code);
+                self.broadcast(&code, &ServerMessage::Forfeited { id });
+                self.forfeit(id, &code);
+                continue;
+            };
+            match room.play(event, now) {
+                Ok(events) => self.dispatch(&code, &events),
+                Err(e) => warn!("Move for player {} in room {}: {}", id, code, e),
+            }
+        }
+    }
+
+    // Player loses the game and the others win it
+    fn forfeit(&mut self, id: PlayerId, code: &str) {
+        let Some(room) = self.rooms.get_mut(code) else {
+            return;
+        };
+        match room.forfeit(id) {
+            Ok(events) => self.dispatch(code, &events),
+            Err(e) => warn!("Player {} can't forfeit in room {}: {}", id, code, e),
+        }
+    }
+
+    fn add_bot(&mut self, id: PlayerId, difficulty: Difficulty) {
+        let Some(code) = self.room_of(id) else {
+            return self.room_rejected(id, RoomError::NotInRoom);
+        };
+        let Some(room) = self.rooms.get(&code) else {
+            return;
+        };
+        if room.players().first() != Some(&id) {
+            return self.room_rejected(id, RoomError::NotOwner);
+        }
+        if room.is_full() {
+            return self.room_rejected(id, RoomError::Full);
+        }
+        // Restored rooms may already use some of the ids
+        while self.clients.contains_key(&self.next_bot) {
+            self.next_bot -= 1;
+        }
+        let bot = self.next_bot;
+        self.next_bot -= 1;
+        self.clients.insert(
+            bot,
+            Client {
+                name: format!("{} bot", difficulty),
+                connection: None,
+                room: None,
+                watching: None,
+                session: None,
+                away_until: None,
+                chat_limit: RateLimit::new(CHAT_BURST, CHAT_PER_SECOND),
+                muted: HashSet::new(),
+            },
+        );
+        self.bots.insert(bot, Bot::new(difficulty));
+        self.seat(bot, code);
+    }
+
+    // Bot keeps the seat and the points of a player who is gone for good
+    fn take_over(&mut self, id: PlayerId, code: &str) {
+        info!("Bot takes over the seat of player {} in room {}", id, code);
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.session = None;
+            client.away_until = None;
+        }
+        self.bots.insert(id, Bot::new(TAKEOVER_DIFFICULTY));
+        if let Some(room) = self.rooms.get_mut(code) {
+            room.give_up(id);
+        }
+        self.dirty.insert(code.into());
+        self.broadcast(code, &ServerMessage::BotTookOver { id });
+    }
+
+    fn move_bots(&mut self) {
+        let now = self.now;
+        let mut moves = Vec::new();
+        let playing = |room: &&Room| room.is_started() && !room.is_over();
+        for room in self.rooms.values().filter(playing) {
+            let active = room.game().active_player();
+            if let Some(bot) = self.bots.get_mut(&active) {
+                if let Some(message) = bot.think(room.game(), now) {
+                    moves.push((active, message));
+                }
+            }
+        }
+        for (id, message) in moves {
+            self.play(id, message);
+        }
+    }
+
+    // Whether anybody but the player and bots is seated in the room
+    fn has_other_people(&self, code: &str, id: PlayerId) -> bool {
+        self.rooms.get(code).is_some_and(|room| {
+            room.players()
+                .iter()
+                .any(|player| *player != id && !self.bots.contains_key(player))
+        })
+    }
+
+    fn is_connected(&self, id: PlayerId) -> bool {
+        self.bots.contains_key(&id)
+            || self
+                .clients
+                .get(&id)
+                .is_some_and(|client| client.connection.is_some())
+    }
+
+    fn turn_timer(&self, code: &str) -> Option<ServerMessage> {
+        self.rooms.get(code)?.turn_timer(self.now)
+    }
+
+    fn room_of(&self, id: PlayerId) -> Option<String> {
+        self.clients.get(&id).and_then(|client| client.room.clone())
+    }
+
+    fn watched_by(&self, id: PlayerId) -> Option<&str> {
+        self.clients
+            .get(&id)
+            .and_then(|client| client.watching.as_deref())
+    }
+
+    // Seated, watching or waiting for a match
+    fn busy(&self, id: PlayerId) -> Option<RoomError> {
+        if self.queue.contains(id) {
+            Some(RoomError::InQueue)
+        } else if self.room_of(id).is_some() || self.watched_by(id).is_some() {
+            Some(RoomError::AlreadyInRoom)
+        } else {
+            None
+        }
+    }
+
+    fn new_room_code(&self) -> String {
+        let mut rng = rand::thread_rng();
+        loop {
+            let code: String = (0..ROOM_CODE_LENGTH)
+                .map(|_| *ROOM_CODE_ALPHABET.choose(&mut rng).unwrap() as char)
+                .collect();
+            if !self.rooms.contains_key(&code) {
+                return code;
+            }
+        }
+    }
+
+    fn version_mismatch(&mut self, connection: ConnectionId, version: u16) {
+        warn!("Client {} speaks protocol version {}", connection, version);
+        self.reject(
+            connection,
+            RejectReason::VersionMismatch {
+                server: PROTOCOL_VERSION,
+            },
+        );
+    }
+
+    // Offending message is dropped. Connections that keep offending are dropped too, seated
+    // players are taken over by a bot like those the admin kicked.
+    fn strike(&mut self, connection: ConnectionId, offense: &str) {
+        let Some(open) = self.connections.get_mut(&connection) else {
+            return;
+        };
+        warn!("Client {}: {}", connection, offense);
+        open.strikes += 1;
+        if open.strikes < MAX_STRIKES {
+            return;
+        }
+        warn!("Client {} is dropped for misbehaving", connection);
+        let player = open.player;
+        if !player.is_some_and(|id| self.send_away(id, RejectReason::Misbehaving)) {
+            self.reject(connection, RejectReason::Misbehaving);
+        }
+    }
+
+    // Connection is forgotten right away, anything else it sends before the transport drops it is
+    // ignored
+    fn reject(&mut self, connection: ConnectionId, reason: RejectReason) {
+        self.connections.remove(&connection);
+        if let Some(rejected) = encoded(&ServerMessage::Rejected { reason }) {
+            self.outbox.push((connection, rejected));
+        }
+        self.disconnects.push(connection);
+    }
+
+    fn send_away(&mut self, id: PlayerId, reason: RejectReason) -> bool {
+        if self.bots.contains_key(&id) {
+            return false;
+        }
+        let Some(client) = self.clients.get_mut(&id) else {
+            return false;
+        };
+        info!("{} sent away: {}", client.name, reason);
+        let connection = client.connection.take();
+        match self.room_of(id) {
+            Some(code)
+                if self.rooms.get(&code).is_some_and(Room::is_started)
+                    && self.has_other_people(&code, id) =>
+            {
+                self.take_over(id, &code)
+            }
+            _ => self.remove_client(id),
+        }
+        if let Some(connection) = connection {
+            self.reject(connection, reason);
+        }
+        true
+    }
+
+    fn send_everybody(&mut self, message: &ServerMessage) {
+        let Some(encoded) = encoded(message) else {
+            return;
+        };
+        let connections: Vec<ConnectionId> = self
+            .clients
+            .values()
+            .filter_map(|client| client.connection)
+            .collect();
+        for connection in connections {
+            self.outbox.push((connection, encoded.clone()));
+        }
+    }
+
+    fn room_rejected(&mut self, id: PlayerId, reason: RoomError) {
+        self.send(id, &ServerMessage::RoomRejected { reason });
+    }
+
+    fn chat_rejected(&mut self, id: PlayerId, reason: ChatError) {
+        self.send(id, &ServerMessage::ChatRejected { reason });
+    }
+
+    fn send(&mut self, id: PlayerId, message: &ServerMessage) {
+        if let Some(encoded) = encoded(message) {
+            self.push(id, encoded);
+        }
+    }
+
+    // Messages for players who are away are dropped, they get the whole state on resuming
+    fn push(&mut self, id: PlayerId, message: Vec<u8>) {
+        if let Some(connection) = self.clients.get(&id).and_then(|client| client.connection) {
+            self.outbox.push((connection, message));
+        }
+    }
+
+    // Sends the message to the players of the room and, after the delay, to its spectators
+    fn broadcast(&mut self, code: &str, message: &ServerMessage) {
+        let Some(room) = self.rooms.get(code) else {
+            return;
+        };
+        let players = room.players().to_vec();
+        let spectators = room.spectators().to_vec();
+        let Some(encoded) = encoded(message) else {
+            return;
+        };
+        for player in players {
+            self.push(player, encoded.clone());
+        }
+        self.delay(code, spectators, message);
+    }
+
+    fn delay(&mut self, code: &str, spectators: Vec<PlayerId>, message: &ServerMessage) {
+        if spectators.is_empty() {
+            return;
+        }
+        let Some(encoded) = encoded(message) else {
+            return;
+        };
+        let due = self.now + self.spectator_delay;
+        self.delayed
+            .push_back((due, code.into(), spectators, encoded));
+        if self.spectator_delay.is_zero() {
+            self.send_delayed();
+        }
+    }
+
+    // Spectators who left the room in the meantime don't get its messages anymore
+    fn send_delayed(&mut self) {
+        while self
+            .delayed
+            .front()
+            .is_some_and(|(due, ..)| *due <= self.now)
+        {
+            let Some((_, code, spectators, message)) = self.delayed.pop_front() else {
+                break;
+            };
+            for id in spectators {
+                if self.watched_by(id) == Some(code.as_str()) {
+                    self.push(id, message.clone());
+                }
+            }
+        }
+    }
+
+    // Hands are only sent to their owners, spectators just learn that a hand was dealt
+    fn dispatch(&mut self, code: &str, events: &[GameEvent]) {
+        self.dirty.insert(code.into());
+        for event in events {
+            if matches!(
+                event,
+                GameEvent::PutCard { .. } | GameEvent::TakeCards { .. }
+            ) {
+                self.metrics.moved(self.now);
+            }
+            let message = ServerMessage::from(event);
+            match event {
+                GameEvent::DealHand { id, .. } => {
+                    self.send(*id, &message);
+                    let spectators = self
+                        .rooms
+                        .get(code)
+                        .map(|room| room.spectators().to_vec())
+                        .unwrap_or_default();
+                    self.delay(code, spectators, &ServerMessage::HandDealtTo { id: *id });
+                }
+                GameEvent::PlayerWon { id } => {
+                    self.metrics.game_completed();
+                    self.broadcast(code, &message);
+                    if let Some(record) = self.match_record(code, *id) {
+                        info!("Match in room {} is over", code);
+                        self.ratings.record(&record);
+                        self.finished.push(record);
+                    }
+                }
+                _ => self.broadcast(code, &message),
+            }
+        }
+        if let Some(timer) = self.turn_timer(code) {
+            self.broadcast(code, &timer);
+        }
+    }
+}
+
+// Whatever can't be encoded is dropped, one lost message is no reason to stop the server
+fn encoded(message: &ServerMessage) -> Option<Vec<u8>> {
+    match encode(message) {
+        Ok(encoded) => Some(encoded),
+        Err(e) => {
+            warn!("{:?} can't be sent: {}", message, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::{AdminCommand, AdminReply};
+    use crate::bot::BOT_THINKING_TIME;
+    use crate::matchmaking::BOT_OFFER_AFTER;
+    use crate::snapshot::SnapshotStore;
+    use scopa_lib::card::{Card, CardValue, Deck, Suite};
+    use scopa_lib::rules::TEAM_PLAYERS;
+
+    fn received(server: &mut Server) -> Vec<(PlayerId, ServerMessage)> {
+        server
+            .drain_outbox()
+            .map(|(id, message)| (id, decode(&message).unwrap()))
+            .collect()
+    }
+
+    fn send(server: &mut Server, id: PlayerId, message: ClientMessage) {
+        server.handle_message(id, &encode(&message).unwrap());
+    }
+
+    fn hello(server: &mut Server, id: PlayerId, version: u16) {
+        server.client_connected(id);
+        let name = format!("Player {}", id);
+        send(server, id, ClientMessage::Hello { version, name });
+    }
+
+    // Creates a room for the first player and seats the rest in it
+    fn room_with(server: &mut Server, players: &[PlayerId]) -> String {
+        for id in players {
+            hello(server, *id, PROTOCOL_VERSION);
+        }
+        send(
+            server,
+            players[0],
+            ClientMessage::CreateRoom { rules: None },
+        );
+        let code = received(server)
+            .into_iter()
+            .find_map(|(_, message)| match message {
+                ServerMessage::RoomJoined { code, .. } => Some(code),
+                _ => None,
+            })
+            .unwrap();
+        for id in &players[1..] {
+            let code = code.clone();
+            send(server, *id, ClientMessage::JoinRoom { code });
+        }
+        code
+    }
+
+    #[test]
+    fn room_list_fits_in_a_message() {
+        let mut server = Server::new(RuleSet {
+            players: 3,
+            ..RuleSet::default()
+        });
+        let mut id = 0;
+        let mut join = |server: &mut Server, message: ClientMessage| {
+            id += 1;
+            server.client_connected(id);
+            let name = format!("{}{:02}", "🃏".repeat(MAX_NAME_LENGTH - 2), id);
+            let version = PROTOCOL_VERSION;
+            send(server, id, ClientMessage::Hello { version, name });
+            send(server, id, message);
+            received(server)
+                .into_iter()
+                .find_map(|(_, message)| match message {
+                    ServerMessage::RoomJoined { code, .. } => Some(code),
+                    _ => None,
+                })
+        };
+        for _ in 0..16 {
+            let code = join(&mut server, ClientMessage::CreateRoom { rules: None }).unwrap();
+            for _ in 0..2 {
+                let code = code.clone();
+                join(&mut server, ClientMessage::JoinRoom { code });
+            }
+        }
+        let waiting = join(&mut server, ClientMessage::CreateRoom { rules: None }).unwrap();
+
+        hello(&mut server, 100, PROTOCOL_VERSION);
+        received(&mut server);
+        send(&mut server, 100, ClientMessage::ListRooms);
+        let outbox: Vec<_> = server.drain_outbox().collect();
+        let [(100, message)] = &outbox[..] else {
+            panic!("No room list");
+        };
+        assert!(message.len() <= MAX_MESSAGE_SIZE as usize);
+        let Ok(ServerMessage::RoomList { rooms }) = decode(message) else {
+            panic!("Room list can't be decoded");
+        };
+        assert!(rooms.len() > 1 && rooms.len() < 17);
+        assert_eq!(rooms[0].code, waiting);
+    }
+
+    #[test]
+    fn handshake() {
+        let mut server = Server::new(RuleSet::default());
+        hello(&mut server, 1, PROTOCOL_VERSION + 1);
+        let rejected = ServerMessage::Rejected {
+            reason: RejectReason::VersionMismatch {
+                server: PROTOCOL_VERSION,
+            },
+        };
+        assert_eq!(received(&mut server), vec![(1, rejected)]);
+        assert_eq!(server.drain_disconnects().collect::<Vec<_>>(), vec![1]);
+
+        hello(&mut server, 2, PROTOCOL_VERSION);
+        let welcome = ServerMessage::Welcome {
+            version: PROTOCOL_VERSION,
+            id: 2,
+        };
+        assert_eq!(received(&mut server), vec![(2, welcome)]);
+        assert!(server.drain_disconnects().next().is_none());
+
+        server.client_connected(3);
+        send(&mut server, 3, ClientMessage::ListRooms);
+        assert!(received(&mut server).is_empty());
+    }
+
+    #[test]
+    fn names_follow_connect_tokens() {
+        let mut server = Server::new(RuleSet::default());
+        let hello_as = |server: &mut Server, id: PlayerId, name: &str| {
+            let name = name.into();
+            let version = PROTOCOL_VERSION;
+            send(server, id, ClientMessage::Hello { version, name });
+        };
+        let mismatch = ServerMessage::Rejected {
+            reason: RejectReason::NameMismatch,
+        };
+        server.client_connected(1);
+        server.client_authenticated(1, "Alice".into());
+        hello_as(&mut server, 1, "Bob");
+        assert_eq!(received(&mut server), vec![(1, mismatch.clone())]);
+
+        server.client_connected(2);
+        server.client_authenticated(2, "Alice".into());
+        hello_as(&mut server, 2, " Alice ");
+        send(&mut server, 2, ClientMessage::CreateRoom { rules: None });
+        let token = received(&mut server)
+            .into_iter()
+            .find_map(|(_, message)| match message {
+                ServerMessage::Session { token } => Some(token),
+                _ => None,
+            })
+            .unwrap();
+        hello(&mut server, 5, PROTOCOL_VERSION);
+        let code = server.room_of(2).unwrap();
+        send(&mut server, 5, ClientMessage::JoinRoom { code });
+        server.client_disconnected(2);
+        received(&mut server);
+
+        // Session token alone is not enough to come back under somebody else's name
+        let resume = ClientMessage::Resume {
+            version: PROTOCOL_VERSION,
+            token,
+        };
+        server.client_connected(3);
+        server.client_authenticated(3, "Mallory".into());
+        send(&mut server, 3, resume.clone());
+        assert_eq!(received(&mut server), vec![(3, mismatch)]);
+        server.client_connected(4);
+        server.client_authenticated(4, "Alice".into());
+        send(&mut server, 4, resume);
+        let welcome = ServerMessage::Welcome {
+            version: PROTOCOL_VERSION,
+            id: 2,
+        };
+        assert_eq!(received(&mut server)[0], (4, welcome));
+    }
+
+    #[test]
+    fn hands_are_private() {
+        let mut server = Server::new(RuleSet::default());
+        let code = room_with(&mut server, &[1, 2]);
+        let messages = received(&mut server);
+        let hands: Vec<PlayerId> = messages
+            .iter()
+            .filter(|(_, message)| matches!(message, ServerMessage::HandDealt { .. }))
+            .map(|(to, _)| *to)
+            .collect();
+        assert_eq!(hands, vec![1, 2]);
+
+        hello(&mut server, 3, PROTOCOL_VERSION);
+        send(&mut server, 3, ClientMessage::JoinRoom { code });
+        let full = ServerMessage::RoomRejected {
+            reason: RoomError::Full,
+        };
+        assert_eq!(received(&mut server).last(), Some(&(3, full)));
+        assert!(server.drain_disconnects().next().is_none());
+    }
+
+    #[test]
+    fn moves_out_of_turn_are_rejected() {
+        let mut server = Server::new(RuleSet::default());
+        room_with(&mut server, &[1, 2]);
+        let messages = received(&mut server);
+        let first = messages
+            .iter()
+            .find_map(|(_, message)| match message {
+                ServerMessage::RoundStarted { active_player } => Some(*active_player),
+                _ => None,
+            })
+            .unwrap();
+        let other = if first == 1 { 2 } else { 1 };
+        let hand = messages
+            .iter()
+            .find_map(|(to, message)| match message {
+                ServerMessage::HandDealt { hand } if *to == other => Some(*hand),
+                _ => None,
+            })
+            .unwrap();
+        send(&mut server, other, ClientMessage::PutCard { card: hand[0] });
+        let messages = received(&mut server);
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(
+            messages[0],
+            (id, ServerMessage::MoveRejected { .. }) if id == other
+        ));
+        server.handle_message(other, b"garbage");
+        assert!(received(&mut server).is_empty());
+
+        // Rejections show in the metrics by kind, only moves that were made count as moves
+        let metrics = server.metrics();
+        assert!(metrics.contains("phantom_rejected_moves_total{kind=\"out_of_turn\"} 1\n"));
+        assert!(metrics.contains("phantom_rejected_moves_total{kind=\"card\"} 0\n"));
+        assert!(metrics.contains("phantom_running_games 1\n"));
+        assert!(metrics.contains("phantom_moves_total 0\n"));
+        let game = server.rooms.values().next().unwrap().game();
+        let legal = match game.legal_moves().remove(0) {
+            GameEvent::PutCard { card, .. } => ClientMessage::PutCard { card },
+            GameEvent::TakeCards { take, with, .. } => ClientMessage::TakeCards { take, with },
+            event => panic!("{:?} is not a move", event),
+        };
+        send(&mut server, first, legal);
+        assert!(server.metrics().contains("phantom_moves_total 1\n"));
+    }
+
+    #[test]
+    fn cards_are_taken_only_once() {
+        use scopa_lib::scenario::ScenarioBuilder;
+        use CardValue::*;
+        use Suite::*;
+        let mut server = Server::new(RuleSet::default());
+        let code = room_with(&mut server, &[1, 2]);
+        received(&mut server);
+        let two = Card::new(Coins, Two);
+        let four = Card::new(Clubs, Four);
+        let used = [two, four, Card::new(Cups, Five)];
+        let rest: Vec<Card> = Deck::default()
+            .cards()
+            .iter()
+            .filter(|card| !used.contains(card))
+            .copied()
+            .collect();
+        let game = ScenarioBuilder::new(RuleSet::default())
+            .player(1, "Player 1")
+            .player(2, "Player 2")
+            .table(&[two, used[2]])
+            .hand(1, &[four])
+            .taken(2, &rest)
+            .build()
+            .unwrap();
+        let room = Room::restore(game, Vec::new(), Vec::new(), None, Duration::ZERO);
+        server.rooms.insert(code.clone(), room);
+
+        let (take, with) = (vec![two, two], four);
+        send(&mut server, 1, ClientMessage::TakeCards { take, with });
+        let messages = received(&mut server);
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(
+            messages[0],
+            (1, ServerMessage::MoveRejected { .. })
+        ));
+        assert_eq!(server.rooms[&code].game().table().count(), 2);
+        assert_eq!(server.rooms[&code].game().hand(1), Some(&[four][..]));
+    }
+
+    #[test]
+    fn rooms_are_independent() {
+        let mut server = Server::new(RuleSet::default());
+        let first = room_with(&mut server, &[1, 2]);
+        let three_players = RuleSet {
+            players: 3,
+            ..RuleSet::default()
+        };
+        hello(&mut server, 3, PROTOCOL_VERSION);
+        send(
+            &mut server,
+            3,
+            ClientMessage::CreateRoom {
+                rules: Some(three_players.clone()),
+            },
+        );
+        received(&mut server);
+
+        // Full rooms are listed as well, so they can be watched
+        send(&mut server, 3, ClientMessage::ListRooms);
+        let Some((3, ServerMessage::RoomList { mut rooms })) = received(&mut server).pop() else {
+            panic!("No room list");
+        };
+        assert_eq!(rooms.len(), 2);
+        rooms.retain(|room| room.code != first);
+        assert_eq!(rooms[0].players, vec!["Player 3".to_string()]);
+        assert_eq!(rooms[0].rules, three_players);
+
+        // Codes are case insensitive
+        hello(&mut server, 4, PROTOCOL_VERSION);
+        let code = rooms[0].code.to_lowercase();
+        send(&mut server, 4, ClientMessage::JoinRoom { code });
+        let messages = received(&mut server);
+        assert!(messages.contains(&(
+            3,
+            ServerMessage::PlayerJoined {
+                id: 4,
+                name: "Player 4".into()
+            }
+        )));
+        assert!(messages.iter().all(|(to, _)| *to == 3 || *to == 4));
+
+        // Moves outside of a room go nowhere
+        hello(&mut server, 5, PROTOCOL_VERSION);
+        received(&mut server);
+        let card = Card::new(Suite::Coins, CardValue::Seven);
+        send(&mut server, 5, ClientMessage::PutCard { card });
+        let not_in_room = ServerMessage::RoomRejected {
+            reason: RoomError::NotInRoom,
+        };
+        assert_eq!(received(&mut server), vec![(5, not_in_room)]);
+
+        let invalid = RuleSet {
+            players: 5,
+            ..RuleSet::default()
+        };
+        send(
+            &mut server,
+            5,
+            ClientMessage::CreateRoom {
+                rules: Some(invalid),
+            },
+        );
+        let invalid_rules = ServerMessage::RoomRejected {
+            reason: RoomError::InvalidRules,
+        };
+        assert_eq!(received(&mut server), vec![(5, invalid_rules)]);
+
+        // Empty rooms are closed
+        send(&mut server, 3, ClientMessage::LeaveRoom);
+        server.client_disconnected(4);
+        received(&mut server);
+        assert_eq!(server.rooms.len(), 1);
+        assert!(server.rooms.contains_key(&first));
+    }
+
+    #[test]
+    fn games_survive_restart() {
+        let mut server = Server::new(RuleSet::default());
+        let code = room_with(&mut server, &[1, 2]);
+        let token = server.clients[&1].session.unwrap();
+        let hand = server.rooms[&code].game().hand(1).unwrap().to_vec();
+        let snapshots = server.drain_snapshots();
+        assert_eq!(snapshots.len(), 1);
+        assert!(server.drain_snapshots().is_empty());
+
+        let dir = std::env::temp_dir().join(format!("phantom-of-server-{}", std::process::id()));
+        let store = SnapshotStore::open(dir.clone()).unwrap();
+        for (_, snapshot) in &snapshots {
+            store.save(snapshot.as_ref().unwrap()).unwrap();
+        }
+        let json = std::fs::read_to_string(dir.join(format!("{}.json", code))).unwrap();
+        assert!(json.starts_with(r#"{"V1":"#));
+        let loaded = store.load_all().unwrap();
+        store.remove(&code).unwrap();
+        assert!(store.load_all().unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let mut server = Server::new(RuleSet::default());
+        server.restore(loaded);
+        server.client_connected(10);
+        let resume = ClientMessage::Resume {
+            version: PROTOCOL_VERSION,
+            token,
+        };
+        send(&mut server, 10, resume);
+        let messages = received(&mut server);
+        let welcome = ServerMessage::Welcome {
+            version: PROTOCOL_VERSION,
+            id: 1,
+        };
+        assert_eq!(messages[0], (10, welcome));
+        let ServerMessage::Resumed {
+            hand: resumed,
+            seats,
+            ..
+        } = &messages[1].1
+        else {
+            panic!("No state after resuming");
+        };
+        assert_eq!(resumed, &hand);
+        assert_eq!(seats.len(), 2);
+        assert!(!seats[1].connected);
+
+        // Room is gone for good once nobody comes back
+        server.update(SEAT_GRACE_PERIOD);
+        server.client_disconnected(10);
+        server.update(SEAT_GRACE_PERIOD * 3);
+        assert!(server.rooms.is_empty());
+        let dropped = server.drain_snapshots();
+        assert!(dropped.iter().all(|(_, snapshot)| snapshot.is_none()));
+    }
+
+    #[test]
+    fn leaderboard_is_sent_on_request() {
+        let mut ratings = Ratings::new(false);
+        ratings.record(&MatchRecord::won_by_first(&[
+            ("Player 1", 11),
+            ("Player 2", 3),
+        ]));
+        let mut server = Server::new(RuleSet::default()).with_ratings(ratings);
+        hello(&mut server, 1, PROTOCOL_VERSION);
+        hello(&mut server, 3, PROTOCOL_VERSION);
+        received(&mut server);
+        send(&mut server, 1, ClientMessage::Leaderboard);
+        send(&mut server, 3, ClientMessage::Leaderboard);
+        let first = PlayerRating {
+            rank: 1,
+            name: "Player 1".into(),
+            rating: 1516,
+            matches: 1,
+            wins: 1,
+        };
+        let second = PlayerRating {
+            rank: 2,
+            name: "Player 2".into(),
+            rating: 1484,
+            matches: 1,
+            wins: 0,
+        };
+        let top = vec![first.clone(), second];
+        assert_eq!(
+            received(&mut server),
+            vec![
+                (
+                    1,
+                    ServerMessage::Leaderboard {
+                        top: top.clone(),
+                        me: Some(first),
+                    }
+                ),
+                (3, ServerMessage::Leaderboard { top, me: None }),
+            ]
+        );
+    }
+
+    #[test]
+    fn quick_match_pairs_similar_ratings() {
+        let mut queue = MatchQueue::default();
+        queue.push(1, 1500.0, Duration::ZERO);
+        queue.push(2, 1800.0, Duration::ZERO);
+        queue.push(3, 1550.0, Duration::ZERO);
+        assert_eq!(queue.next_group(2, Duration::ZERO), Some(vec![1, 3]));
+        queue.push(4, 1500.0, Duration::ZERO);
+        assert_eq!(queue.next_group(2, Duration::from_secs(10)), None);
+        assert_eq!(
+            queue.next_group(2, Duration::from_secs(20)),
+            Some(vec![2, 4])
+        );
+
+        // Quick matches are played by the rules of the server
+        let rules = RuleSet {
+            target_score: 21,
+            ..RuleSet::default()
+        };
+        let mut server = Server::new(rules.clone());
+        for id in 1..=3 {
+            hello(&mut server, id, PROTOCOL_VERSION);
+        }
+        received(&mut server);
+        send(&mut server, 1, ClientMessage::FindMatch);
+        send(&mut server, 1, ClientMessage::CreateRoom { rules: None });
+        let in_queue = ServerMessage::RoomRejected {
+            reason: RoomError::InQueue,
+        };
+        let queued = |players| ServerMessage::Queued { players };
+        assert_eq!(received(&mut server), vec![(1, queued(1)), (1, in_queue)]);
+        send(&mut server, 2, ClientMessage::FindMatch);
+        received(&mut server);
+        server.update(Duration::from_secs(1));
+        let joined = |id| {
+            move |(to, message): &(PlayerId, ServerMessage)| {
+                *to == id && matches!(message, ServerMessage::RoomJoined { .. })
+            }
+        };
+        let messages = received(&mut server);
+        assert!(messages.iter().any(joined(1)) && messages.iter().any(joined(2)));
+        assert_eq!(server.room_of(1), server.room_of(2));
+        assert_eq!(server.rooms[&server.room_of(1).unwrap()].rules(), &rules);
+
+        // Nobody else turns up, so a bot is offered
+        send(&mut server, 3, ClientMessage::FindMatch);
+        send(&mut server, 3, ClientMessage::PlayBot);
+        let not_in_queue = ServerMessage::RoomRejected {
+            reason: RoomError::NotInQueue,
+        };
+        assert_eq!(
+            received(&mut server),
+            vec![(3, queued(1)), (3, not_in_queue.clone())]
+        );
+        server.update(Duration::from_secs(1) + BOT_OFFER_AFTER);
+        assert_eq!(received(&mut server), vec![(3, ServerMessage::BotOffered)]);
+        send(&mut server, 3, ClientMessage::PlayBot);
+        let messages = received(&mut server);
+        assert!(messages.iter().any(joined(3)));
+        let room = &server.rooms[&server.room_of(3).unwrap()];
+        assert!(room.is_started());
+        assert_eq!(room.rules(), &rules);
+        assert!(room.players().iter().any(|id| server.bots.contains_key(id)));
+        send(&mut server, 3, ClientMessage::CancelMatch);
+        assert_eq!(received(&mut server), vec![(3, not_in_queue)]);
+    }
+
+    #[test]
+    fn chat_is_limited_and_muted() {
+        let mut server = Server::new(RuleSet::default());
+        let code = room_with(&mut server, &[1, 2]);
+        hello(&mut server, 3, PROTOCOL_VERSION);
+        send(&mut server, 3, ClientMessage::Spectate { code });
+        received(&mut server);
+
+        // Players talk to everybody, spectators only to each other
+        let text = "  Buona\u{7} fortuna ".into();
+        send(&mut server, 1, ClientMessage::Chat { text });
+        let chat = ServerMessage::Chat {
+            id: 1,
+            name: "Player 1".into(),
+            text: "Buona fortuna".into(),
+        };
+        let expected: Vec<_> = [1, 2, 3].map(|to| (to, chat.clone())).into();
+        assert_eq!(received(&mut server), expected);
+        let emote = Emote::WellPlayed;
+        send(&mut server, 3, ClientMessage::Emote { emote });
+        let emoted = ServerMessage::Emote { id: 3, emote };
+        assert_eq!(received(&mut server), vec![(3, emoted)]);
+
+        let text = "a".repeat(MAX_CHAT_LENGTH + 1);
+        send(&mut server, 2, ClientMessage::Chat { text });
+        let too_long = ServerMessage::ChatRejected {
+            reason: ChatError::TooLong,
+        };
+        assert_eq!(received(&mut server), vec![(2, too_long)]);
+
+        // Muted players are not heard
+        send(&mut server, 2, ClientMessage::Mute { id: 1, muted: true });
+        let emote = Emote::Scopa;
+        send(&mut server, 1, ClientMessage::Emote { emote });
+        let messages = received(&mut server);
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|(to, _)| *to != 2));
+
+        // Burst is used up, the next message has to wait
+        for _ in 2..CHAT_BURST {
+            send(&mut server, 1, ClientMessage::Emote { emote });
+        }
+        received(&mut server);
+        send(&mut server, 1, ClientMessage::Emote { emote });
+        let too_fast = ServerMessage::ChatRejected {
+            reason: ChatError::TooFast,
+        };
+        assert_eq!(received(&mut server), vec![(1, too_fast)]);
+        server.update(Duration::from_secs(2));
+        received(&mut server);
+        send(&mut server, 1, ClientMessage::Emote { emote });
+        assert_eq!(received(&mut server).len(), 2);
+    }
+
+    #[test]
+    fn admin_keeps_order() {
+        let mut server = Server::new(RuleSet::default());
+        let code = room_with(&mut server, &[1, 2]);
+        received(&mut server);
+        let rooms = server.room_statuses();
+        assert_eq!(rooms.len(), 1);
+        assert!(rooms[0].started);
+        let room = server.room_detail(&code).unwrap();
+        assert_eq!(room.seats.len(), 2);
+        assert!(room.game.players.iter().all(|p| p.hand.len() == 3));
+
+        // Kicked player's seat goes to a bot
+        assert!(server.kick(2));
+        assert!(!server.kick(2));
+        let messages = received(&mut server);
+        let kicked = ServerMessage::Rejected {
+            reason: RejectReason::Kicked,
+        };
+        assert!(messages.contains(&(2, kicked)));
+        assert!(messages.contains(&(1, ServerMessage::BotTookOver { id: 2 })));
+        assert_eq!(server.drain_disconnects().collect::<Vec<_>>(), vec![2]);
+
+        // Banned player is sent away and can't come back, the bot doesn't play alone
+        assert_eq!(server.ban("Player 1"), 1);
+        assert!(server.rooms.is_empty());
+        assert!(server.bots.is_empty());
+        received(&mut server);
+        hello(&mut server, 1, PROTOCOL_VERSION);
+        let banned = ServerMessage::Rejected {
+            reason: RejectReason::Banned,
+        };
+        assert_eq!(received(&mut server), vec![(1, banned)]);
+        assert!(server.unban("Player 1"));
+
+        // Ended game is gone with its snapshot, players and spectators go back to the lobby
+        server.drain_snapshots();
+        let code = room_with(&mut server, &[3, 4]);
+        hello(&mut server, 5, PROTOCOL_VERSION);
+        send(
+            &mut server,
+            5,
+            ClientMessage::Spectate { code: code.clone() },
+        );
+        received(&mut server);
+        let end = AdminCommand::parse(&format!("end {}", code.to_lowercase())).unwrap();
+        assert!(matches!(end.run(&mut server), AdminReply::Done { .. }));
+        let closed: Vec<_> = [3, 4, 5].map(|to| (to, ServerMessage::RoomClosed)).into();
+        assert_eq!(received(&mut server), closed);
+        assert!(server
+            .drain_snapshots()
+            .iter()
+            .any(|(saved, snapshot)| *saved == code && snapshot.is_none()));
+        let end = AdminCommand::End { code };
+        assert!(matches!(end.run(&mut server), AdminReply::Error { .. }));
+        assert!(server.player_statuses().iter().all(|p| p.room.is_none()));
+
+        // Everybody is warned before the server stops
+        let shutdown = AdminCommand::parse("shutdown 10").unwrap();
+        shutdown.run(&mut server);
+        let after = Duration::from_secs(10);
+        assert_eq!(received(&mut server).len(), 3);
+        assert!(!server.is_shut_down());
+        server.update(after);
+        assert!(server.is_shut_down());
+        assert!(AdminCommand::parse("kick me").is_err());
+    }
+
+    #[test]
+    fn room_limit_is_enforced() {
+        let mut server = Server::new(RuleSet::default()).with_max_rooms(1);
+        room_with(&mut server, &[1]);
+        hello(&mut server, 2, PROTOCOL_VERSION);
+        received(&mut server);
+        send(&mut server, 2, ClientMessage::CreateRoom { rules: None });
+        let too_many = ServerMessage::RoomRejected {
+            reason: RoomError::TooManyRooms,
+        };
+        assert_eq!(received(&mut server), vec![(2, too_many)]);
+        assert_eq!(server.rooms.len(), 1);
+    }
+
+    #[test]
+    fn refused_seats_say_why() {
+        let mut server = Server::new(RuleSet::default());
+        let code = room_with(&mut server, &[1, 2]);
+        hello(&mut server, 3, PROTOCOL_VERSION);
+        received(&mut server);
+        let rejected = |reason| ServerMessage::RoomRejected { reason };
+        server.seat(1, code.clone());
+        assert_eq!(
+            received(&mut server),
+            vec![(1, rejected(RoomError::AlreadyInRoom))]
+        );
+        server.seat(3, code);
+        assert_eq!(received(&mut server), vec![(3, rejected(RoomError::Full))]);
+    }
+
+    #[test]
+    fn open_connections_keep_their_id() {
+        let mut server = Server::new(RuleSet::default());
+        hello(&mut server, 1, PROTOCOL_VERSION);
+        received(&mut server);
+        assert!(!server.client_connected(1));
+        send(&mut server, 1, ClientMessage::ListRooms);
+        let list = ServerMessage::RoomList { rooms: Vec::new() };
+        assert_eq!(received(&mut server), vec![(1, list)]);
+        assert_eq!(server.clients[&1].connection, Some(1));
+    }
+
+    #[test]
+    fn resume_after_dropped_connection() {
+        let mut server = Server::new(RuleSet::default());
+        server.client_connected(1);
+        send(
+            &mut server,
+            1,
+            ClientMessage::Hello {
+                version: PROTOCOL_VERSION,
+                name: "Player 1".into(),
+            },
+        );
+        send(&mut server, 1, ClientMessage::CreateRoom { rules: None });
+        let token = received(&mut server)
+            .into_iter()
+            .find_map(|(_, message)| match message {
+                ServerMessage::Session { token } => Some(token),
+                _ => None,
+            })
+            .unwrap();
+        hello(&mut server, 2, PROTOCOL_VERSION);
+        let code = server.room_of(1).unwrap();
+        send(&mut server, 2, ClientMessage::JoinRoom { code });
+        received(&mut server);
+
+        server.client_disconnected(1);
+        let away = ServerMessage::PlayerAway { id: 1 };
+        assert_eq!(received(&mut server), vec![(2, away)]);
+
+        let resume = ClientMessage::Resume {
+            version: PROTOCOL_VERSION,
+            token,
+        };
+        server.client_connected(10);
+        send(&mut server, 10, resume.clone());
+        let messages = received(&mut server);
+        let welcome = ServerMessage::Welcome {
+            version: PROTOCOL_VERSION,
+            id: 1,
+        };
+        assert_eq!(messages[0], (10, welcome));
+        let ServerMessage::Resumed {
+            seats, hand, table, ..
+        } = &messages[1].1
+        else {
+            panic!("No state after resuming");
+        };
+        assert_eq!(hand.len(), 3);
+        assert_eq!(table.len(), 4);
+        assert_eq!(seats.len(), 2);
+        assert!(seats.iter().all(|seat| seat.connected && seat.points == 0));
+        assert!(messages.contains(&(2, ServerMessage::PlayerBack { id: 1 })));
+
+        // Bot takes the seat over once the grace period is over
+        server.client_disconnected(10);
+        server.update(SEAT_GRACE_PERIOD / 2);
+        assert_eq!(received(&mut server).len(), 1);
+        server.update(SEAT_GRACE_PERIOD);
+        let messages = received(&mut server);
+        assert_eq!(messages[0], (2, ServerMessage::BotTookOver { id: 1 }));
+        assert_eq!(server.rooms.values().next().unwrap().players().len(), 2);
+        assert!(server.rooms.values().next().unwrap().gave_up(1));
+        server.client_connected(11);
+        send(&mut server, 11, resume);
+        let expired = ServerMessage::Rejected {
+            reason: RejectReason::SessionExpired,
+        };
+        assert_eq!(received(&mut server), vec![(11, expired)]);
+
+        // Room closes once no people are left to play with the bot. Both people lost, the bot
+        // playing for the first one doesn't make them a bot.
+        server.client_disconnected(2);
+        server.update(SEAT_GRACE_PERIOD * 2);
+        assert!(server.rooms.is_empty());
+        assert!(server.bots.is_empty());
+        let records: Vec<MatchRecord> = server.drain_finished_matches().collect();
+        assert_eq!(records.len(), 1);
+        assert!(records[0]
+            .players
+            .iter()
+            .all(|player| player.forfeited && !player.bot));
+    }
+
+    #[test]
+    fn spectators_never_see_hands() {
+        let delay = Duration::from_secs(10);
+        let mut server = Server::new(RuleSet::default()).with_spectator_delay(delay);
+        hello(&mut server, 1, PROTOCOL_VERSION);
+        send(&mut server, 1, ClientMessage::CreateRoom { rules: None });
+        received(&mut server);
+        let code = server.room_of(1).unwrap();
+        hello(&mut server, 3, PROTOCOL_VERSION);
+        received(&mut server);
+        let spectate = ClientMessage::Spectate { code: code.clone() };
+        send(&mut server, 3, spectate);
+        hello(&mut server, 2, PROTOCOL_VERSION);
+        send(&mut server, 2, ClientMessage::JoinRoom { code });
+        let messages = received(&mut server);
+        assert!(messages.iter().all(|(to, _)| *to != 3));
+
+        // Spectator gets the state from the moment they came in and then the game so far
+        server.update(delay);
+        let messages: Vec<ServerMessage> = received(&mut server)
+            .into_iter()
+            .filter(|(to, _)| *to == 3)
+            .map(|(_, message)| message)
+            .collect();
+        let ServerMessage::Spectating {
+            seats,
+            piles,
+            active_player,
+            ..
+        } = &messages[0]
+        else {
+            panic!("No state for the spectator");
+        };
+        assert_eq!(seats.len(), 1);
+        assert_eq!(piles[0].hand, 0);
+        assert!(active_player.is_none());
+        assert!(messages.contains(&ServerMessage::HandDealtTo { id: 1 }));
+        assert!(messages.contains(&ServerMessage::HandDealtTo { id: 2 }));
+        assert!(messages
+            .iter()
+            .all(|message| !matches!(message, ServerMessage::HandDealt { .. })));
+
+        // Spectators can't play and leave without disturbing the game
+        let card = Card::new(Suite::Coins, CardValue::Seven);
+        send(&mut server, 3, ClientMessage::PutCard { card });
+        let not_in_room = ServerMessage::RoomRejected {
+            reason: RoomError::NotInRoom,
+        };
+        assert_eq!(received(&mut server), vec![(3, not_in_room)]);
+        send(&mut server, 3, ClientMessage::LeaveRoom);
+        assert_eq!(received(&mut server), vec![(3, ServerMessage::RoomLeft)]);
+        assert!(server
+            .rooms
+            .values()
+            .all(|room| room.spectators().is_empty()));
+    }
+
+    fn rejected(to: PlayerId, reason: RejectReason) -> (PlayerId, ServerMessage) {
+        (to, ServerMessage::Rejected { reason })
+    }
+
+    #[test]
+    fn silent_connections_time_out() {
+        let mut server = Server::new(RuleSet::default());
+        server.client_connected(1);
+        server.client_connected(2);
+        send(
+            &mut server,
+            2,
+            ClientMessage::Hello {
+                version: PROTOCOL_VERSION,
+                name: "Player 2".into(),
+            },
+        );
+        received(&mut server);
+        server.update(HANDSHAKE_TIMEOUT - Duration::from_millis(1));
+        assert!(received(&mut server).is_empty());
+        // Only the one that never said hello
+        server.update(HANDSHAKE_TIMEOUT);
+        let timeout = rejected(1, RejectReason::HandshakeTimeout);
+        assert_eq!(received(&mut server), vec![timeout]);
+        assert_eq!(server.drain_disconnects().collect::<Vec<_>>(), vec![1]);
+        assert!(server.clients.contains_key(&2));
+    }
+
+    #[test]
+    fn oversize_messages_are_not_read() {
+        let mut server = Server::new(RuleSet::default());
+        hello(&mut server, 1, PROTOCOL_VERSION);
+        received(&mut server);
+        // Well formed, but longer than any message a client may send
+        let text = "a".repeat(MAX_MESSAGE_SIZE as usize);
+        let oversize = encode(&ClientMessage::Chat { text }).unwrap();
+        assert!(oversize.len() > MAX_MESSAGE_SIZE as usize);
+        server.handle_message(1, &oversize);
+        assert!(received(&mut server).is_empty());
+        assert_eq!(server.connections[&1].strikes, 1);
+        for _ in 1..MAX_STRIKES {
+            server.handle_message(1, &oversize);
+        }
+        let misbehaving = rejected(1, RejectReason::Misbehaving);
+        assert_eq!(received(&mut server), vec![misbehaving]);
+        assert_eq!(server.drain_disconnects().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn strikes_add_up_to_misbehaving() {
+        let mut server = Server::new(RuleSet::default());
+        let code = room_with(&mut server, &[1, 2]);
+        received(&mut server);
+        // Garbage and misplaced messages
+        server.handle_message(1, &[0xFF; 64]);
+        for _ in 1..MAX_STRIKES - 1 {
+            send(
+                &mut server,
+                1,
+                ClientMessage::Hello {
+                    version: PROTOCOL_VERSION,
+                    name: "Again".into(),
+                },
+            );
+        }
+        assert!(received(&mut server).is_empty());
+        assert!(server.drain_disconnects().next().is_none());
+        server.handle_message(1, &[]);
+        let misbehaving = rejected(1, RejectReason::Misbehaving);
+        assert!(received(&mut server).contains(&misbehaving));
+        assert_eq!(server.drain_disconnects().collect::<Vec<_>>(), vec![1]);
+        // Seat went to a bot, the game goes on
+        assert!(server.bots.contains_key(&1));
+        assert!(server.rooms[&code].is_started());
+    }
+
+    #[test]
+    fn floods_are_dropped() {
+        let mut server = Server::new(RuleSet::default());
+        hello(&mut server, 3, PROTOCOL_VERSION);
+        for _ in 0..MESSAGE_BURST + MAX_STRIKES {
+            send(&mut server, 3, ClientMessage::Leaderboard);
+        }
+        let messages = received(&mut server);
+        let boards = messages
+            .iter()
+            .filter(|(_, message)| matches!(message, ServerMessage::Leaderboard { .. }))
+            .count();
+        // Hello took a message of the burst
+        assert_eq!(boards, MESSAGE_BURST as usize - 1);
+        let misbehaving = rejected(3, RejectReason::Misbehaving);
+        assert_eq!(messages.last(), Some(&misbehaving));
+        assert!(!server.clients.contains_key(&3));
+    }
+
+    #[test]
+    fn idle_players_time_out() {
+        let mut server = Server::new(RuleSet::default()).with_time_control(Some(TimeControl {
+            turn: Duration::from_secs(10),
+            bank: Duration::from_secs(5),
+            max_timeouts: 2,
+        }));
+        room_with(&mut server, &[1, 2]);
+        let timer = received(&mut server)
+            .into_iter()
+            .find_map(|(_, message)| match message {
+                ServerMessage::TurnTimer { id, turn, bank } => Some((id, turn, bank)),
+                _ => None,
+            });
+        let Some((first, turn, bank)) = timer else {
+            panic!("No turn timer");
+        };
+        assert_eq!(turn, Duration::from_secs(10));
+        assert_eq!(bank, Duration::from_secs(5));
+        let second = if first == 1 { 2 } else { 1 };
+
+        // Bank is spent after the turn time
+        server.update(Duration::from_secs(14));
+        assert!(received(&mut server).is_empty());
+        server.update(Duration::from_secs(15));
+        let messages = received(&mut server);
+        assert!(messages.contains(&(second, ServerMessage::TimedOut { id: first })));
+        assert!(messages.iter().any(|(_, message)| matches!(
+            message,
+            ServerMessage::CardPut { id, .. } | ServerMessage::CardsTaken { id, .. } if *id == first
+        )));
+        assert!(messages.contains(&(
+            first,
+            ServerMessage::TurnTimer {
+                id: second,
+                turn: Duration::from_secs(10),
+                bank: Duration::from_secs(5),
+            }
+        )));
+
+        // Second timeout in a row with an empty bank costs the seat
+        server.update(Duration::from_secs(30));
+        received(&mut server);
+        server.update(Duration::from_secs(40));
+        let messages = received(&mut server);
+        assert!(messages.contains(&(second, ServerMessage::Forfeited { id: first })));
+        // Whoever is still at the table wins
+        assert!(messages.contains(&(second, ServerMessage::GameWon { id: second })));
+        let room = server.rooms.values().next().unwrap();
+        assert!(room.is_over());
+        assert!(server
+            .turn_timer(&server.room_of(second).unwrap())
+            .is_none());
+        let records: Vec<MatchRecord> = server.drain_finished_matches().collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].winner, second);
+        // Won game isn't brought back after a restart
+        let snapshots = server.drain_snapshots();
+        assert!(!snapshots.is_empty());
+        assert!(snapshots.iter().all(|(_, snapshot)| snapshot.is_none()));
+        server.update(Duration::from_secs(100));
+        assert!(received(&mut server).is_empty());
+    }
+
+    #[test]
+    fn bots_play_through_a_game() {
+        let mut server = Server::new(RuleSet::default());
+        let code = room_with(&mut server, &[1]);
+        hello(&mut server, 2, PROTOCOL_VERSION);
+        send(&mut server, 2, ClientMessage::JoinRoom { code });
+        received(&mut server);
+        // Room is full now, and only its host may add bots anyway
+        send(
+            &mut server,
+            2,
+            ClientMessage::AddBot {
+                difficulty: Difficulty::Hard,
+            },
+        );
+        let not_owner = ServerMessage::RoomRejected {
+            reason: RoomError::NotOwner,
+        };
+        assert_eq!(received(&mut server), vec![(2, not_owner)]);
+        // Leaving a game that has started loses it
+        send(&mut server, 2, ClientMessage::LeaveRoom);
+        let records: Vec<MatchRecord> = server.drain_finished_matches().collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].winner, 1);
+        assert!(records[0].players[1].forfeited);
+        send(
+            &mut server,
+            1,
+            ClientMessage::AddBot {
+                difficulty: Difficulty::Hard,
+            },
+        );
+        let bot = PlayerId::MAX;
+        assert!(received(&mut server)
+            .iter()
+            .any(|(_, message)| matches!(message, ServerMessage::RoundStarted { .. })));
+        // Connection that happens to have the id of the bot can't take its place
+        hello(&mut server, bot, PROTOCOL_VERSION);
+        let in_use = ServerMessage::Rejected {
+            reason: RejectReason::IdInUse,
+        };
+        assert_eq!(received(&mut server), vec![(bot, in_use)]);
+        assert!(server.bots.contains_key(&bot));
+
+        let mut now = Duration::ZERO;
+        let mut bot_moves = 0;
+        loop {
+            now += BOT_THINKING_TIME;
+            server.update(now);
+            let game = server.rooms.values().next().unwrap().game();
+            if game.active_player() == 1 {
+                match game.fallback_move() {
+                    Some(GameEvent::PutCard { card, .. }) => {
+                        send(&mut server, 1, ClientMessage::PutCard { card })
+                    }
+                    Some(GameEvent::TakeCards { take, with, .. }) => {
+                        send(&mut server, 1, ClientMessage::TakeCards { take, with })
+                    }
+                    _ => {}
+                }
+            }
+            let messages = received(&mut server);
+            assert!(!messages
+                .iter()
+                .any(|(_, message)| matches!(message, ServerMessage::MoveRejected { .. })));
+            bot_moves += messages
+                .iter()
+                .filter(|(_, message)| {
+                    matches!(message,
+                        ServerMessage::CardPut { id, .. } | ServerMessage::CardsTaken { id, .. }
+                            if *id == bot)
+                })
+                .count();
+            if messages
+                .iter()
+                .any(|(_, message)| matches!(message, ServerMessage::GameWon { .. }))
+            {
+                break;
+            }
+        }
+        assert!(bot_moves > 0);
+        let records: Vec<MatchRecord> = server.drain_finished_matches().collect();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert!(record.has_player("Player 1") && !record.has_player("Hard bot"));
+        assert!(record.players.iter().any(|player| player.bot));
+        assert!(!record.rounds.is_empty());
+        assert!(matches!(
+            record.events.last(),
+            Some(GameEvent::PlayerWon { id }) if *id == record.winner
+        ));
+
+        // Bot leaves together with the last person in the room
+        send(&mut server, 1, ClientMessage::LeaveRoom);
+        assert!(server.rooms.is_empty());
+        assert!(server.bots.is_empty());
+    }
+
+    #[test]
+    fn bot_completes_a_team_table() {
+        let mut server = Server::new(RuleSet {
+            players: TEAM_PLAYERS,
+            ..RuleSet::default()
+        });
+        room_with(&mut server, &[1, 2, 3]);
+        let difficulty = Difficulty::Normal;
+        send(&mut server, 1, ClientMessage::AddBot { difficulty });
+        let bot = PlayerId::MAX;
+        let game = server.rooms.values().next().unwrap().game();
+        assert_eq!(game.seats(), &[1, 2, 3, bot]);
+        assert_eq!(game.partner(bot), Some(2));
+
+        let mut now = Duration::ZERO;
+        loop {
+            now += BOT_THINKING_TIME;
+            server.update(now);
+            let game = server.rooms.values().next().unwrap().game();
+            let active = game.active_player();
+            match game.fallback_move() {
+                _ if active == bot => {}
+                Some(GameEvent::PutCard { card, .. }) => {
+                    send(&mut server, active, ClientMessage::PutCard { card })
+                }
+                Some(GameEvent::TakeCards { take, with, .. }) => {
+                    send(&mut server, active, ClientMessage::TakeCards { take, with })
+                }
+                _ => {}
+            }
+            let messages = received(&mut server);
+            assert!(!messages
+                .iter()
+                .any(|(_, message)| matches!(message, ServerMessage::MoveRejected { .. })));
+            if messages
+                .iter()
+                .any(|(_, message)| matches!(message, ServerMessage::GameWon { .. }))
+            {
+                break;
+            }
+        }
+        let records: Vec<MatchRecord> = server.drain_finished_matches().collect();
+        let record = &records[0];
+        // Partners finish with the same points and win together
+        let points: Vec<u8> = record.players.iter().map(|player| player.points).collect();
+        assert_eq!((points[0], points[1]), (points[2], points[3]));
+        let winners: Vec<PlayerId> = record.winners().map(|player| player.id).collect();
+        assert!(winners == [1, 3] || winners == [2, bot]);
+        for (id, name) in [(1, "Player 1"), (2, "Player 2"), (3, "Player 3")] {
+            let wins = server.ratings.get(name).unwrap().wins;
+            assert_eq!(wins, u32::from(winners.contains(&id)));
+        }
+
+        // Whoever forfeits loses together with their partner
+        let mut room = Room::new(record.rules.clone(), None);
+        for id in 1..=4 {
+            room.join(id, "Player", Duration::ZERO).unwrap();
+        }
+        let events = room.forfeit(2).unwrap();
+        assert!(matches!(events[..], [GameEvent::PlayerWon { id: 1 | 3 }]));
+    }
+}
@@ -0,0 +1,670 @@
+use crate::error::Result;
+
+use log::warn;
+use scopa_lib::card::{Card, CardValue, Suite};
+use scopa_lib::protocol::{Difficulty, SessionToken};
+use scopa_lib::rules::{PrimeTable, PrimieraRule, RedealRule, RuleSet};
+use scopa_lib::snapshot::{GameSnapshot, PlayerSnapshot};
+use scopa_lib::{GameEvent, PlayerId, Points, Results};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeatSnapshot {
+    pub id: PlayerId,
+    // Lets the player resume once the server is back
+    pub session: Option<SessionToken>,
+    // Set for seats played by the server
+    pub bot: Option<Difficulty>,
+    // Player gave up the game and a bot plays their seat
+    pub forfeited: bool,
+}
+
+// Running game of a room with whoever is seated at it. Spectators and clocks are left out, they
+// start over once the server is back.
+#[derive(Debug, Clone)]
+pub struct RoomSnapshot {
+    pub code: String,
+    pub game: GameSnapshot,
+    pub seats: Vec<SeatSnapshot>,
+    pub events: Vec<GameEvent>,
+}
+
+// Every snapshot format the server ever wrote. New formats are added at the end and older ones
+// are upgraded when loading, so an updated server picks up games saved by the previous one. Each
+// format has types of its own, changing the live structs must not change what was saved.
+#[derive(Debug, Serialize, Deserialize)]
+enum VersionedSnapshot {
+    V1(RoomSnapshotV1),
+}
+
+impl From<VersionedSnapshot> for RoomSnapshot {
+    fn from(value: VersionedSnapshot) -> Self {
+        match value {
+            VersionedSnapshot::V1(snapshot) => snapshot.into(),
+        }
+    }
+}
+
+// First format, written since games were saved at all. Nothing here may change, anything new
+// goes into a format of its own.
+#[derive(Debug, Serialize, Deserialize)]
+struct RoomSnapshotV1 {
+    code: String,
+    game: GameSnapshotV1,
+    seats: Vec<SeatSnapshotV1>,
+    events: Vec<GameEventV1>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SeatSnapshotV1 {
+    id: PlayerId,
+    session: Option<u64>,
+    bot: Option<DifficultyV1>,
+    #[serde(default)]
+    forfeited: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum DifficultyV1 {
+    Easy,
+    Normal,
+    Hard,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GameSnapshotV1 {
+    rules: RuleSetV1,
+    players: Vec<PlayerSnapshotV1>,
+    deck: Vec<CardV1>,
+    table: Vec<CardV1>,
+    active_player: PlayerId,
+    took_last: PlayerId,
+    first_seat: usize,
+    // Not there in games saved before shuffles were seeded
+    seed: Option<u64>,
+    round: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PlayerSnapshotV1 {
+    id: PlayerId,
+    name: String,
+    points: u8,
+    scopas: u8,
+    hand: Vec<CardV1>,
+    taken: Vec<CardV1>,
+}
+
+// Rules left out were the default ones
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct RuleSetV1 {
+    players: usize,
+    target_score: u8,
+    redeal: RedealRuleV1,
+    prime_table: [u8; 10],
+    primiera: PrimieraRuleV1,
+    primiera_needs_all_suites: bool,
+}
+
+impl Default for RuleSetV1 {
+    fn default() -> Self {
+        Self {
+            players: 2,
+            target_score: 11,
+            redeal: RedealRuleV1::Kings,
+            prime_table: [16, 12, 13, 14, 15, 18, 21, 10, 10, 10],
+            primiera: PrimieraRuleV1::Sum,
+            primiera_needs_all_suites: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum RedealRuleV1 {
+    Off,
+    Kings,
+    AnyValue,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum PrimieraRuleV1 {
+    Sum,
+    CountSevens,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CardV1 {
+    suite: SuiteV1,
+    value: CardValueV1,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum SuiteV1 {
+    Clubs,
+    Coins,
+    Cups,
+    Swords,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum CardValueV1 {
+    One,
+    Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Fante,
+    Cavallo,
+    Re,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum GameEventV1 {
+    PlayerConnected {
+        id: PlayerId,
+        name: String,
+    },
+    PlayerDisconnected {
+        id: PlayerId,
+        name: String,
+    },
+    StartRound {
+        active_player: PlayerId,
+    },
+    EndRound {
+        points: Vec<PointsV1>,
+    },
+    PlayerWon {
+        id: PlayerId,
+    },
+    DealHand {
+        id: PlayerId,
+        hand: [CardV1; 3],
+    },
+    CommitDeck {
+        commitment: [u8; 32],
+    },
+    RevealDeck {
+        seed: u64,
+    },
+    PlaceTable {
+        table: [CardV1; 4],
+    },
+    TableRedealt {
+        table: [CardV1; 4],
+    },
+    PutCard {
+        id: PlayerId,
+        card: CardV1,
+    },
+    TakeCards {
+        id: PlayerId,
+        take: Vec<CardV1>,
+        with: CardV1,
+    },
+    Scopa {
+        id: PlayerId,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PointsV1 {
+    id: PlayerId,
+    points: u8,
+    details: ResultsV1,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ResultsV1 {
+    takes: u8,
+    count_of_coins: u8,
+    seven_of_coins: bool,
+    primes: u8,
+    primiera_eligible: bool,
+    sevens: u8,
+    sixes: u8,
+    aces: u8,
+    scopas: u8,
+}
+
+impl From<RoomSnapshotV1> for RoomSnapshot {
+    fn from(value: RoomSnapshotV1) -> Self {
+        let game = value.game;
+        // Every round dealt so far started with this event
+        let rounds = value
+            .events
+            .iter()
+            .filter(|event| matches!(event, GameEventV1::StartRound { .. }))
+            .count() as u64;
+        Self {
+            code: value.code,
+            game: GameSnapshot {
+                rules: game.rules.into(),
+                players: game.players.into_iter().map(Into::into).collect(),
+                deck: cards(game.deck),
+                table: cards(game.table),
+                active_player: game.active_player,
+                took_last: game.took_last,
+                first_seat: game.first_seat,
+                // Rounds still to come are shuffled from a new seed, the ones before can't be
+                // replayed anyway
+                seed: game.seed.unwrap_or_else(rand::random),
+                round: game.round.unwrap_or(rounds),
+            },
+            seats: value.seats.into_iter().map(Into::into).collect(),
+            events: value.events.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<RoomSnapshot> for RoomSnapshotV1 {
+    fn from(value: RoomSnapshot) -> Self {
+        let game = value.game;
+        Self {
+            code: value.code,
+            game: GameSnapshotV1 {
+                rules: game.rules.into(),
+                players: game.players.into_iter().map(Into::into).collect(),
+                deck: cards(game.deck),
+                table: cards(game.table),
+                active_player: game.active_player,
+                took_last: game.took_last,
+                first_seat: game.first_seat,
+                seed: Some(game.seed),
+                round: Some(game.round),
+            },
+            seats: value.seats.into_iter().map(Into::into).collect(),
+            events: value.events.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+fn cards<A: Into<B>, B>(cards: Vec<A>) -> Vec<B> {
+    cards.into_iter().map(Into::into).collect()
+}
+
+impl From<SeatSnapshotV1> for SeatSnapshot {
+    fn from(value: SeatSnapshotV1) -> Self {
+        Self {
+            id: value.id,
+            session: value.session,
+            bot: value.bot.map(Into::into),
+            forfeited: value.forfeited,
+        }
+    }
+}
+
+impl From<SeatSnapshot> for SeatSnapshotV1 {
+    fn from(value: SeatSnapshot) -> Self {
+        Self {
+            id: value.id,
+            session: value.session,
+            bot: value.bot.map(Into::into),
+            forfeited: value.forfeited,
+        }
+    }
+}
+
+impl From<DifficultyV1> for Difficulty {
+    fn from(value: DifficultyV1) -> Self {
+        match value {
+            DifficultyV1::Easy => Difficulty::Easy,
+            DifficultyV1::Normal => Difficulty::Normal,
+            DifficultyV1::Hard => Difficulty::Hard,
+        }
+    }
+}
+
+impl From<Difficulty> for DifficultyV1 {
+    fn from(value: Difficulty) -> Self {
+        match value {
+            Difficulty::Easy => DifficultyV1::Easy,
+            Difficulty::Normal => DifficultyV1::Normal,
+            Difficulty::Hard => DifficultyV1::Hard,
+        }
+    }
+}
+
+impl From<PlayerSnapshotV1> for PlayerSnapshot {
+    fn from(value: PlayerSnapshotV1) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            points: value.points,
+            scopas: value.scopas,
+            hand: cards(value.hand),
+            taken: cards(value.taken),
+        }
+    }
+}
+
+impl From<PlayerSnapshot> for PlayerSnapshotV1 {
+    fn from(value: PlayerSnapshot) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            points: value.points,
+            scopas: value.scopas,
+            hand: cards(value.hand),
+            taken: cards(value.taken),
+        }
+    }
+}
+
+impl From<RuleSetV1> for RuleSet {
+    fn from(value: RuleSetV1) -> Self {
+        Self {
+            players: value.players,
+            target_score: value.target_score,
+            redeal: match value.redeal {
+                RedealRuleV1::Off => RedealRule::Off,
+                RedealRuleV1::Kings => RedealRule::Kings,
+                RedealRuleV1::AnyValue => RedealRule::AnyValue,
+            },
+            prime_table: PrimeTable::new(value.prime_table),
+            primiera: match value.primiera {
+                PrimieraRuleV1::Sum => PrimieraRule::Sum,
+                PrimieraRuleV1::CountSevens => PrimieraRule::CountSevens,
+            },
+            primiera_needs_all_suites: value.primiera_needs_all_suites,
+        }
+    }
+}
+
+impl From<RuleSet> for RuleSetV1 {
+    fn from(value: RuleSet) -> Self {
+        Self {
+            players: value.players,
+            target_score: value.target_score,
+            redeal: match value.redeal {
+                RedealRule::Off => RedealRuleV1::Off,
+                RedealRule::Kings => RedealRuleV1::Kings,
+                RedealRule::AnyValue => RedealRuleV1::AnyValue,
+            },
+            prime_table: value.prime_table.primes(),
+            primiera: match value.primiera {
+                PrimieraRule::Sum => PrimieraRuleV1::Sum,
+                PrimieraRule::CountSevens => PrimieraRuleV1::CountSevens,
+            },
+            primiera_needs_all_suites: value.primiera_needs_all_suites,
+        }
+    }
+}
+
+impl From<CardV1> for Card {
+    fn from(value: CardV1) -> Self {
+        let suite = match value.suite {
+            SuiteV1::Clubs => Suite::Clubs,
+            SuiteV1::Coins => Suite::Coins,
+            SuiteV1::Cups => Suite::Cups,
+            SuiteV1::Swords => Suite::Swords,
+        };
+        let value = match value.value {
+            CardValueV1::One => CardValue::One,
+            CardValueV1::Two => CardValue::Two,
+            CardValueV1::Three => CardValue::Three,
+            CardValueV1::Four => CardValue::Four,
+            CardValueV1::Five => CardValue::Five,
+            CardValueV1::Six => CardValue::Six,
+            CardValueV1::Seven => CardValue::Seven,
+            CardValueV1::Fante => CardValue::Fante,
+            CardValueV1::Cavallo => CardValue::Cavallo,
+            CardValueV1::Re => CardValue::Re,
+        };
+        Card::new(suite, value)
+    }
+}
+
+impl From<Card> for CardV1 {
+    fn from(value: Card) -> Self {
+        let suite = match value.suite {
+            Suite::Clubs => SuiteV1::Clubs,
+            Suite::Coins => SuiteV1::Coins,
+            Suite::Cups => SuiteV1::Cups,
+            Suite::Swords => SuiteV1::Swords,
+        };
+        let value = match value.value {
+            CardValue::One => CardValueV1::One,
+            CardValue::Two => CardValueV1::Two,
+            CardValue::Three => CardValueV1::Three,
+            CardValue::Four => CardValueV1::Four,
+            CardValue::Five => CardValueV1::Five,
+            CardValue::Six => CardValueV1::Six,
+            CardValue::Seven => CardValueV1::Seven,
+            CardValue::Fante => CardValueV1::Fante,
+            CardValue::Cavallo => CardValueV1::Cavallo,
+            CardValue::Re => CardValueV1::Re,
+        };
+        Self { suite, value }
+    }
+}
+
+impl From<GameEventV1> for GameEvent {
+    fn from(value: GameEventV1) -> Self {
+        match value {
+            GameEventV1::PlayerConnected { id, name } => GameEvent::PlayerConnected { id, name },
+            GameEventV1::PlayerDisconnected { id, name } => {
+                GameEvent::PlayerDisconnected { id, name }
+            }
+            GameEventV1::StartRound { active_player } => GameEvent::StartRound { active_player },
+            GameEventV1::EndRound { points } => GameEvent::EndRound {
+                points: points.into_iter().map(Into::into).collect(),
+            },
+            GameEventV1::PlayerWon { id } => GameEvent::PlayerWon { id },
+            GameEventV1::DealHand { id, hand } => GameEvent::DealHand {
+                id,
+                hand: hand.map(Into::into),
+            },
+            GameEventV1::CommitDeck { commitment } => GameEvent::CommitDeck { commitment },
+            GameEventV1::RevealDeck { seed } => GameEvent::RevealDeck { seed },
+            GameEventV1::PlaceTable { table } => GameEvent::PlaceTable {
+                table: table.map(Into::into),
+            },
+            GameEventV1::TableRedealt { table } => GameEvent::TableRedealt {
+                table: table.map(Into::into),
+            },
+            GameEventV1::PutCard { id, card } => GameEvent::PutCard {
+                id,
+                card: card.into(),
+            },
+            GameEventV1::TakeCards { id, take, with } => GameEvent::TakeCards {
+                id,
+                take: cards(take),
+                with: with.into(),
+            },
+            GameEventV1::Scopa { id } => GameEvent::Scopa { id },
+        }
+    }
+}
+
+impl From<GameEvent> for GameEventV1 {
+    fn from(value: GameEvent) -> Self {
+        match value {
+            GameEvent::PlayerConnected { id, name } => GameEventV1::PlayerConnected { id, name },
+            GameEvent::PlayerDisconnected { id, name } => {
+                GameEventV1::PlayerDisconnected { id, name }
+            }
+            GameEvent::StartRound { active_player } => GameEventV1::StartRound { active_player },
+            GameEvent::EndRound { points } => GameEventV1::EndRound {
+                points: points.into_iter().map(Into::into).collect(),
+            },
+            GameEvent::PlayerWon { id } => GameEventV1::PlayerWon { id },
+            GameEvent::DealHand { id, hand } => GameEventV1::DealHand {
+                id,
+                hand: hand.map(Into::into),
+            },
+            GameEvent::CommitDeck { commitment } => GameEventV1::CommitDeck { commitment },
+            GameEvent::RevealDeck { seed } => GameEventV1::RevealDeck { seed },
+            GameEvent::PlaceTable { table } => GameEventV1::PlaceTable {
+                table: table.map(Into::into),
+            },
+            GameEvent::TableRedealt { table } => GameEventV1::TableRedealt {
+                table: table.map(Into::into),
+            },
+            GameEvent::PutCard { id, card } => GameEventV1::PutCard {
+                id,
+                card: card.into(),
+            },
+            GameEvent::TakeCards { id, take, with } => GameEventV1::TakeCards {
+                id,
+                take: cards(take),
+                with: with.into(),
+            },
+            GameEvent::Scopa { id } => GameEventV1::Scopa { id },
+        }
+    }
+}
+
+impl From<PointsV1> for Points {
+    fn from(value: PointsV1) -> Self {
+        let details = value.details;
+        Self {
+            id: value.id,
+            points: value.points,
+            details: Results {
+                takes: details.takes,
+                count_of_coins: details.count_of_coins,
+                seven_of_coins: details.seven_of_coins,
+                primes: details.primes,
+                primiera_eligible: details.primiera_eligible,
+                sevens: details.sevens,
+                sixes: details.sixes,
+                aces: details.aces,
+                scopas: details.scopas,
+            },
+        }
+    }
+}
+
+impl From<Points> for PointsV1 {
+    fn from(value: Points) -> Self {
+        let details = value.details;
+        Self {
+            id: value.id,
+            points: value.points,
+            details: ResultsV1 {
+                takes: details.takes,
+                count_of_coins: details.count_of_coins,
+                seven_of_coins: details.seven_of_coins,
+                primes: details.primes,
+                primiera_eligible: details.primiera_eligible,
+                sevens: details.sevens,
+                sixes: details.sixes,
+                aces: details.aces,
+                scopas: details.scopas,
+            },
+        }
+    }
+}
+
+// Directory with a JSON file for every open room
+#[derive(Debug)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore {
+    pub fn open(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, code: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", code))
+    }
+
+    // Written next to the old file and renamed over it, so a crash never leaves half a snapshot
+    pub fn save(&self, snapshot: &RoomSnapshot) -> Result<()> {
+        let path = self.path(&snapshot.code);
+        let temporary = path.with_extension("json.tmp");
+        let json = serde_json::to_vec(&VersionedSnapshot::V1(snapshot.clone().into()))?;
+        fs::write(&temporary, json)?;
+        fs::rename(temporary, path)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, code: &str) -> Result<()> {
+        match fs::remove_file(self.path(code)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    // Snapshots which can't be read are skipped, one broken room shouldn't keep the server down
+    pub fn load_all(&self) -> Result<Vec<RoomSnapshot>> {
+        let mut snapshots = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension() != Some("json".as_ref()) {
+                continue;
+            }
+            match load(&path) {
+                Ok(snapshot) => snapshots.push(snapshot),
+                Err(e) => warn!("Skipping snapshot {}: {}", path.display(), e),
+            }
+        }
+        Ok(snapshots)
+    }
+}
+
+fn load(path: &Path) -> Result<RoomSnapshot> {
+    let snapshot: VersionedSnapshot = serde_json::from_slice(&fs::read(path)?)?;
+    Ok(snapshot.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scopa_lib::ScopaGame;
+
+    #[test]
+    fn first_snapshots_still_load() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/snapshot_v1.json");
+        let snapshot = load(&path).unwrap();
+        assert_eq!(snapshot.code, "4MQ3");
+        assert_eq!(snapshot.seats.len(), 2);
+        assert!(snapshot.seats.iter().all(|seat| !seat.forfeited));
+        assert_eq!(snapshot.game.round, 1);
+        let game = ScopaGame::restore(snapshot.game).unwrap();
+        assert_eq!(game.active_player(), 1);
+        assert_eq!(game.hand(1).map(|hand| hand.len()), Some(3));
+    }
+
+    #[test]
+    fn first_format_keeps_what_it_saved() {
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/snapshot_v1_second_round.json");
+        let json = fs::read(&path).unwrap();
+        let snapshot = load(&path).unwrap();
+        assert_eq!(snapshot.game.rules.primiera, PrimieraRule::CountSevens);
+        assert_eq!(snapshot.game.rules.redeal, RedealRule::AnyValue);
+        assert_eq!(snapshot.game.rules.prime_table.primes()[0], 20);
+        assert_eq!(snapshot.seats[1].bot, Some(Difficulty::Hard));
+        assert_eq!(snapshot.game.round, 2);
+        let points: Vec<Points> = snapshot
+            .events
+            .iter()
+            .find_map(|event| match event {
+                GameEvent::EndRound { points } => Some(points.clone()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(points[0].details.sevens, 4);
+        assert_eq!(points[1].details.count_of_coins, 7);
+        assert!(matches!(
+            snapshot.events.last(),
+            Some(GameEvent::Scopa { id: 1 })
+        ));
+        let game = ScopaGame::restore(snapshot.game.clone()).unwrap();
+        assert_eq!(game.points(1), Some(2));
+
+        // Saving it again writes the very same file
+        let saved = serde_json::to_vec(&VersionedSnapshot::V1(snapshot.into())).unwrap();
+        assert_eq!(saved, json);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::auth::{self, Accounts};
+use crate::server::{ConnectionId, Server};
+
+use log::{info, warn};
+use scopa_lib::protocol::{decode, ClientMessage, ServerMessage, TokenRequest, MAX_MESSAGE_SIZE};
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::{CloseFrame, WebSocketConfig};
+use tungstenite::{Message, WebSocket};
+
+// WebSocket connections speak the same protocol as renet clients, only in JSON text frames, one
+// message per frame: {"JoinRoom":{"code":"ABCD"}}, or just "ListRooms" for messages without
+// fields. Every connection runs on a thread of its own and talks to the server loop through
+// channels.
+
+// Top half of the ids, renet ids and tokens stay in the bottom one and bots count down from the
+// very top
+pub const FIRST_CONNECTION_ID: ConnectionId = 1 << 63;
+// Time given to the handshake and, on servers that check names, to the sign-in after it
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// Longest a message from the server waits while the connection listens for the client
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+// JSON takes more room than the binary encoding, but not this much more. Bigger frames close the
+// connection before they are read.
+const MAX_FRAME_SIZE: usize = 4 * MAX_MESSAGE_SIZE as usize;
+
+enum Event {
+    // Outgoing messages are encoded the way the server encodes them, None closes the connection
+    Connected {
+        id: ConnectionId,
+        identity: Option<String>,
+        outgoing: Sender<Option<Vec<u8>>>,
+    },
+    Message {
+        id: ConnectionId,
+        size: usize,
+        message: Result<ClientMessage, String>,
+    },
+    Disconnected {
+        id: ConnectionId,
+    },
+}
+
+pub struct WebSocketTransport {
+    addr: SocketAddr,
+    events: Receiver<Event>,
+    connections: HashMap<ConnectionId, Sender<Option<Vec<u8>>>>,
+    // Connections the server refused, whose ids belong to somebody else
+    refused: HashSet<ConnectionId>,
+}
+
+impl WebSocketTransport {
+    // With accounts, the first frame of every connection is a TokenRequest in JSON and hello has
+    // to come with the name signed in with. Connections over max_clients are closed right away.
+    pub fn listen(
+        addr: SocketAddr,
+        max_clients: usize,
+        accounts: Option<Arc<Mutex<Accounts>>>,
+    ) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let (events, receiver) = mpsc::channel();
+        let open = Arc::new(AtomicUsize::new(0));
+        std::thread::spawn(move || {
+            for (id, stream) in (FIRST_CONNECTION_ID..).zip(listener.incoming()) {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                if open.load(Ordering::Relaxed) >= max_clients {
+                    continue;
+                }
+                open.fetch_add(1, Ordering::Relaxed);
+                let (open, events, accounts) = (open.clone(), events.clone(), accounts.clone());
+                std::thread::spawn(move || {
+                    if let Err(e) = run_connection(stream, id, accounts.as_deref(), &events) {
+                        warn!("WebSocket connection {} failed: {}", id, e);
+                    }
+                    open.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+        });
+        Ok(Self {
+            addr,
+            events: receiver,
+            connections: HashMap::new(),
+            refused: HashSet::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn is_connected(&self, id: ConnectionId) -> bool {
+        self.connections.contains_key(&id)
+    }
+
+    // Hands everything that came in since the last call to the server
+    pub fn receive(&mut self, server: &mut Server) {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Event::Connected {
+                    id,
+                    identity,
+                    outgoing,
+                } => {
+                    if !server.client_connected(id) {
+                        self.refused.insert(id);
+                        let _ = outgoing.send(None);
+                        continue;
+                    }
+                    info!("WebSocket client {} connected", id);
+                    self.connections.insert(id, outgoing);
+                    if let Some(name) = identity {
+                        server.client_authenticated(id, name);
+                    }
+                }
+                Event::Message { id, size, message } if !self.refused.contains(&id) => {
+                    server.handle_decoded(id, size, message);
+                }
+                Event::Message { .. } => {}
+                // Also comes after the server itself dropped the connection, like with renet, but
+                // not after it refused it
+                Event::Disconnected { id } => {
+                    if self.refused.remove(&id) {
+                        continue;
+                    }
+                    info!("WebSocket client {} disconnected", id);
+                    self.connections.remove(&id);
+                    server.client_disconnected(id);
+                }
+            }
+        }
+    }
+
+    pub fn send(&mut self, id: ConnectionId, message: Vec<u8>) {
+        if let Some(outgoing) = self.connections.get(&id) {
+            let _ = outgoing.send(Some(message));
+        }
+    }
+
+    // Messages sent before are still delivered
+    pub fn disconnect(&mut self, id: ConnectionId) {
+        if let Some(outgoing) = self.connections.remove(&id) {
+            let _ = outgoing.send(None);
+        }
+    }
+}
+
+fn run_connection(
+    stream: TcpStream,
+    id: ConnectionId,
+    accounts: Option<&Mutex<Accounts>>,
+    events: &Sender<Event>,
+) -> Result<(), String> {
+    stream
+        .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+        .map_err(|e| e.to_string())?;
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_FRAME_SIZE),
+        max_frame_size: Some(MAX_FRAME_SIZE),
+        ..Default::default()
+    };
+    let mut socket =
+        tungstenite::accept_with_config(stream, Some(config)).map_err(|e| e.to_string())?;
+    let identity = match accounts {
+        Some(accounts) => match sign_in(&mut socket, accounts) {
+            Ok(name) => Some(name),
+            Err(reason) => {
+                let frame = CloseFrame {
+                    code: CloseCode::Policy,
+                    reason: reason.clone().into(),
+                };
+                let _ = socket.close(Some(frame));
+                let _ = socket.flush();
+                return Err(reason);
+            }
+        },
+        None => None,
+    };
+    socket
+        .get_ref()
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .map_err(|e| e.to_string())?;
+    let (outgoing, to_send) = mpsc::channel();
+    let connected = Event::Connected {
+        id,
+        identity,
+        outgoing,
+    };
+    if events.send(connected).is_err() {
+        return Ok(());
+    }
+    let result = relay(&mut socket, id, &to_send, events);
+    let _ = events.send(Event::Disconnected { id });
+    result
+}
+
+// Waits for the first frame, which has to be a TokenRequest. Returns why the client can't come in
+// otherwise.
+fn sign_in(
+    socket: &mut WebSocket<TcpStream>,
+    accounts: &Mutex<Accounts>,
+) -> Result<String, String> {
+    let text = match socket.read() {
+        Ok(Message::Text(text)) => text,
+        Ok(_) => return Err("Sign in first".into()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let request: TokenRequest = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+    let peer = socket.get_ref().peer_addr().map_err(|e| e.to_string())?;
+    auth::sign_in(accounts, &request, peer.ip()).map_err(|refusal| refusal.reason)
+}
+
+// Passes messages both ways until either side closes the connection
+fn relay(
+    socket: &mut WebSocket<TcpStream>,
+    id: ConnectionId,
+    to_send: &Receiver<Option<Vec<u8>>>,
+    events: &Sender<Event>,
+) -> Result<(), String> {
+    loop {
+        loop {
+            match to_send.try_recv() {
+                Ok(Some(message)) => {
+                    let json = decode::<ServerMessage>(&message)
+                        .map_err(|e| e.to_string())
+                        .and_then(|message| {
+                            serde_json::to_string(&message).map_err(|e| e.to_string())
+                        })?;
+                    socket
+                        .send(Message::Text(json))
+                        .map_err(|e| e.to_string())?;
+                }
+                // Server is done with the connection, or stopped altogether
+                Ok(None) | Err(TryRecvError::Disconnected) => {
+                    let _ = socket.close(None);
+                    let _ = socket.flush();
+                    return Ok(());
+                }
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        let message = match socket.read() {
+            Ok(Message::Text(text)) => {
+                let message = serde_json::from_str(&text).map_err(|e| e.to_string());
+                Event::Message {
+                    id,
+                    size: text.len(),
+                    message,
+                }
+            }
+            Ok(Message::Binary(bytes)) => Event::Message {
+                id,
+                size: bytes.len(),
+                message: Err("Messages go in text frames".into()),
+            },
+            Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => {
+                let _ = socket.flush();
+                return Ok(());
+            }
+            // Pings are answered by tungstenite itself
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                continue
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        if events.send(message).is_err() {
+            return Ok(());
+        }
+    }
+}
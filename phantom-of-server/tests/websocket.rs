@@ -0,0 +1,206 @@
+use phantom_of_server::server::Server;
+use phantom_of_server::websocket::{WebSocketTransport, FIRST_CONNECTION_ID};
+use scopa_lib::protocol::{ClientMessage, ServerMessage, MAX_MESSAGE_SIZE, PROTOCOL_VERSION};
+use scopa_lib::rules::RuleSet;
+use scopa_lib::snapshot::GameSnapshot;
+use scopa_lib::{GameEvent, PlayerId, ScopaGame};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+use tungstenite::WebSocket;
+
+// Drives the server the way network::run does, with the clock running faster than real time,
+// but not so fast that the handshake times out
+struct Harness {
+    server: Server,
+    transport: WebSocketTransport,
+    now: Duration,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let localhost = SocketAddr::from(([127, 0, 0, 1], 0));
+        Self {
+            server: Server::new(RuleSet::default()),
+            transport: WebSocketTransport::listen(localhost, 2, None).unwrap(),
+            now: Duration::ZERO,
+        }
+    }
+
+    fn pump(&mut self) {
+        std::thread::sleep(Duration::from_millis(1));
+        self.now += Duration::from_millis(100);
+        self.server.update(self.now);
+        self.transport.receive(&mut self.server);
+        for (id, message) in self.server.drain_outbox() {
+            self.transport.send(id, message);
+        }
+        for id in self.server.drain_disconnects() {
+            self.transport.disconnect(id);
+        }
+    }
+
+    fn wait_for<T>(
+        &mut self,
+        socket: &mut WebSocket<TcpStream>,
+        mut wanted: impl FnMut(ServerMessage) -> Option<T>,
+    ) -> T {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            self.pump();
+            if let Some(found) = read_json(socket).into_iter().find_map(&mut wanted) {
+                return found;
+            }
+        }
+        panic!("Server didn't answer over the WebSocket");
+    }
+
+    fn is_connected(&self, id: PlayerId) -> bool {
+        self.server
+            .player_statuses()
+            .iter()
+            .any(|player| player.id == id && player.connected)
+    }
+}
+
+// Connects like a browser would, reads don't wait for more than a moment
+fn websocket_client(addr: SocketAddr) -> WebSocket<TcpStream> {
+    let stream = TcpStream::connect(addr).unwrap();
+    let (socket, _) = tungstenite::client(format!("ws://{}/", addr), stream).unwrap();
+    socket
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(1)))
+        .unwrap();
+    socket
+}
+
+fn send_json(socket: &mut WebSocket<TcpStream>, message: &ClientMessage) {
+    let json = serde_json::to_string(message).unwrap();
+    socket.send(tungstenite::Message::Text(json)).unwrap();
+}
+
+fn read_json(socket: &mut WebSocket<TcpStream>) -> Vec<ServerMessage> {
+    let mut messages = Vec::new();
+    while let Ok(tungstenite::Message::Text(text)) = socket.read() {
+        messages.push(serde_json::from_str(&text).unwrap());
+    }
+    messages
+}
+
+#[test]
+fn full_game_over_websocket() {
+    let mut harness = Harness::new();
+    let addr = harness.transport.local_addr();
+    let mut sockets = [websocket_client(addr), websocket_client(addr)];
+
+    let mut ids = Vec::new();
+    for (n, socket) in sockets.iter_mut().enumerate() {
+        let hello = ClientMessage::Hello {
+            version: PROTOCOL_VERSION,
+            name: format!("Browser {}", n + 1),
+        };
+        send_json(socket, &hello);
+        ids.push(harness.wait_for(socket, |message| match message {
+            ServerMessage::Welcome { id, .. } => Some(id),
+            _ => None,
+        }));
+    }
+    assert!(ids.iter().all(|id| *id >= FIRST_CONNECTION_ID));
+    send_json(&mut sockets[0], &ClientMessage::CreateRoom { rules: None });
+    let code = harness.wait_for(&mut sockets[0], |message| match message {
+        ServerMessage::RoomJoined { code, .. } => Some(code),
+        _ => None,
+    });
+    send_json(
+        &mut sockets[1],
+        &ClientMessage::JoinRoom { code: code.clone() },
+    );
+
+    // Each side plays what the server would play for it, a move at a time
+    let mut moves_seen = 0;
+    let mut played: Option<GameSnapshot> = None;
+    let mut winner = None;
+    let deadline = Instant::now() + Duration::from_secs(60);
+    while winner.is_none() && Instant::now() < deadline {
+        harness.pump();
+        // Moves go to both players, they are counted once
+        for (seat, socket) in sockets.iter_mut().enumerate() {
+            for message in read_json(socket) {
+                match message {
+                    ServerMessage::CardPut { .. } | ServerMessage::CardsTaken { .. }
+                        if seat == 0 =>
+                    {
+                        moves_seen += 1
+                    }
+                    ServerMessage::GameWon { id } => winner = Some(id),
+                    ServerMessage::MoveRejected { reason } => panic!("{}", reason),
+                    _ => {}
+                }
+            }
+        }
+        if winner.is_some() {
+            continue;
+        }
+        // The admin's view of the room tells whose turn it is. Until it changes, the last move
+        // is still on its way to the server.
+        let Some(room) = harness
+            .server
+            .room_detail(&code)
+            .filter(|room| room.started)
+        else {
+            continue;
+        };
+        if played.as_ref() == Some(&room.game) {
+            continue;
+        }
+        played = Some(room.game.clone());
+        let game = ScopaGame::restore(room.game).unwrap();
+        let seat = ids
+            .iter()
+            .position(|id| *id == game.active_player())
+            .unwrap();
+        let message = match game.fallback_move() {
+            Some(GameEvent::PutCard { card, .. }) => ClientMessage::PutCard { card },
+            Some(GameEvent::TakeCards { take, with, .. }) => {
+                ClientMessage::TakeCards { take, with }
+            }
+            _ => continue,
+        };
+        send_json(&mut sockets[seat], &message);
+    }
+    assert!(ids.contains(&winner.expect("Game didn't finish")));
+    assert!(moves_seen > 20);
+
+    // Closed socket reaches the server like a dropped renet connection
+    let [mut first, _second] = sockets;
+    first.close(None).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while harness.is_connected(ids[0]) && Instant::now() < deadline {
+        let _ = first.flush();
+        harness.pump();
+    }
+    assert!(!harness.transport.is_connected(ids[0]));
+    assert!(!harness.is_connected(ids[0]));
+}
+
+#[test]
+fn oversize_frames_close_the_connection() {
+    let mut harness = Harness::new();
+    let mut socket = websocket_client(harness.transport.local_addr());
+    let hello = ClientMessage::Hello {
+        version: PROTOCOL_VERSION,
+        name: "Browser".into(),
+    };
+    send_json(&mut socket, &hello);
+    let id = harness.wait_for(&mut socket, |message| match message {
+        ServerMessage::Welcome { id, .. } => Some(id),
+        _ => None,
+    });
+    let text = "x".repeat(MAX_MESSAGE_SIZE as usize * 16);
+    send_json(&mut socket, &ClientMessage::Chat { text });
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while harness.transport.is_connected(id) && Instant::now() < deadline {
+        harness.pump();
+    }
+    assert!(!harness.transport.is_connected(id));
+    assert!(!harness.is_connected(id));
+}
@@ -1,8 +1,18 @@
+use crate::rules::PrimeTable;
+use crate::ScopaError;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
+// Which physical deck cards are named and drawn after
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DeckStyle {
+    #[default]
+    Italian,
+    French,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Suite {
     Clubs,
@@ -23,6 +33,22 @@ impl std::fmt::Display for Suite {
     }
 }
 
+impl Suite {
+    pub fn name(&self, style: DeckStyle) -> &'static str {
+        use Suite::*;
+        match (style, self) {
+            (DeckStyle::Italian, Clubs) => "Clubs",
+            (DeckStyle::Italian, Coins) => "Coins",
+            (DeckStyle::Italian, Cups) => "Cups",
+            (DeckStyle::Italian, Swords) => "Swords",
+            (DeckStyle::French, Clubs) => "Clubs",
+            (DeckStyle::French, Coins) => "Diamonds",
+            (DeckStyle::French, Cups) => "Hearts",
+            (DeckStyle::French, Swords) => "Spades",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, Serialize, Deserialize)]
 pub enum CardValue {
     One,
@@ -55,17 +81,22 @@ impl CardValue {
     }
 
     pub fn prime(&self) -> u8 {
+        PrimeTable::TRADITIONAL.prime(*self)
+    }
+
+    pub fn name(&self, style: DeckStyle) -> String {
         use CardValue::*;
-        match self {
-            Seven => 21,
-            Six => 18,
-            One => 16,
-            Five => 15,
-            Four => 14,
-            Three => 13,
-            Two => 12,
-            _ => 10,
-        }
+        let name = match (style, self) {
+            (_, One) => "Ace",
+            (DeckStyle::Italian, Fante) => "Fante",
+            (DeckStyle::Italian, Cavallo) => "Cavallo",
+            (DeckStyle::Italian, Re) => "Re",
+            (DeckStyle::French, Fante) => "Jack",
+            (DeckStyle::French, Cavallo) => "Queen",
+            (DeckStyle::French, Re) => "King",
+            _ => return self.value().to_string(),
+        };
+        name.into()
     }
 }
 
@@ -117,6 +148,10 @@ impl Card {
     pub fn prime(&self) -> u8 {
         self.value.prime()
     }
+
+    pub fn name(&self, style: DeckStyle) -> String {
+        format!("{} of {}", self.value.name(style), self.suite.name(style))
+    }
 }
 
 #[derive(Debug)]
@@ -141,18 +176,35 @@ impl Default for Deck {
 }
 
 impl Deck {
+    pub(crate) fn from_cards(cards: Vec<Card>) -> Self {
+        Self { cards }
+    }
+
+    // Deck which deals cards in the given order, starting with the first one
+    pub fn from_order(order: Vec<Card>) -> Result<Self, ScopaError> {
+        let mut seen = HashSet::with_capacity(order.len());
+        if let Some(card) = order.iter().find(|card| !seen.insert(**card)) {
+            return Err(ScopaError::Card(format!("{} is in the deck twice", card)));
+        }
+        Ok(Self::from_cards(order.into_iter().rev().collect()))
+    }
+
     pub fn shuffle(&mut self) {
-        self.cards.shuffle(&mut thread_rng());
+        self.shuffle_with(&mut thread_rng());
+    }
+
+    pub fn shuffle_with(&mut self, rng: &mut impl Rng) {
+        self.cards.shuffle(rng);
     }
 
     // Deal last 3 cards from the deck
     pub fn deal_hand(&mut self) -> [Card; 3] {
         // It is safe to unwrap because:
         // - scopa deck has 40 cards
-        // - on the first turn we place 4 on the table and deal 3 to each of two players, so 30
-        // cards left
-        // - each turn after that we deal 3 cards to each of two players - 6 cards per turn
-        // - 30 is a multiple of 6
+        // - on the first turn we place 4 on the table, so 36 cards are left for the hands
+        // - each turn we deal 3 cards to each of two, three or four players - 6, 9 or 12 cards per
+        //   turn
+        // - 36 is a multiple of 6, 9 and 12
         [
             self.cards.pop().unwrap(),
             self.cards.pop().unwrap(),
@@ -175,6 +227,11 @@ impl Deck {
         ]
     }
 
+    // Cards left in the deck, the last one is dealt first
+    pub fn cards(&self) -> &[Card] {
+        &self.cards
+    }
+
     pub fn len(&self) -> usize {
         self.cards.len()
     }
@@ -214,6 +271,10 @@ impl Table {
         self.table.iter().find(|c| c.value() == card.value())
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Card> {
+        self.table.iter()
+    }
+
     pub fn len(&self) -> usize {
         self.table.len()
     }
@@ -225,4 +286,8 @@ impl Table {
     pub fn clear(&mut self) {
         self.table.clear();
     }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = Card> + '_ {
+        self.table.drain()
+    }
 }
@@ -0,0 +1,164 @@
+use crate::card::{Card, Deck};
+use crate::rules::RedealRule;
+use crate::PlayerId;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+// Commit-reveal for the shuffles. Before a round is dealt the server sends a hash of the seed the
+// round is shuffled with, and once the round is over it reveals the seed. Anybody can then shuffle
+// the deck again and check that every card came out where the seed put it.
+
+// SHA-256 of the round seed
+pub type Commitment = [u8; 32];
+
+// Seed of a single round. It follows from the seed of the game, but revealing it tells nothing
+// about the game seed or the rounds still to come.
+pub fn round_seed(game_seed: u64, round: u64) -> u64 {
+    let hash = Sha256::new()
+        .chain_update(b"phantom-of-scopa round")
+        .chain_update(game_seed.to_le_bytes())
+        .chain_update(round.to_le_bytes())
+        .finalize();
+    let mut seed = [0; 8];
+    seed.copy_from_slice(&hash[..8]);
+    u64::from_le_bytes(seed)
+}
+
+pub fn commit(round_seed: u64) -> Commitment {
+    Sha256::new()
+        .chain_update(b"phantom-of-scopa deck")
+        .chain_update(round_seed.to_le_bytes())
+        .finalize()
+        .into()
+}
+
+// Shuffles the deck of a round and places the table, shuffling again while the table is void.
+// Returns the deck left for the hands, the table and the void tables before it.
+pub fn shuffle_round(round_seed: u64, redeal: RedealRule) -> (Deck, [Card; 4], Vec<[Card; 4]>) {
+    let mut rng = StdRng::seed_from_u64(round_seed);
+    let mut void = Vec::new();
+    loop {
+        let mut deck = Deck::default();
+        deck.shuffle_with(&mut rng);
+        let table = deck.place_table();
+        if !redeal.requires_redeal(&table) {
+            return (deck, table, void);
+        }
+        void.push(table);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FairnessError {
+    // Round started before we joined, so there is nothing to check it against
+    NotCommitted,
+    WrongSeed,
+    TableChanged,
+    HandChanged,
+    CardNotDealt { id: PlayerId, card: Card },
+}
+
+impl std::fmt::Display for FairnessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use FairnessError::*;
+        match self {
+            NotCommitted => write!(f, "The round started before you joined"),
+            WrongSeed => write!(f, "The revealed seed is not the one promised"),
+            TableChanged => write!(f, "The table is not the one the seed dealt"),
+            HandChanged => write!(f, "Your cards are not the ones the seed dealt"),
+            CardNotDealt { id, card } => {
+                write!(f, "{} was never dealt to player {}", card, id)
+            }
+        }
+    }
+}
+
+// What a player or a spectator saw of a round, checked against the seed once it is revealed
+#[derive(Debug, Clone, Default)]
+pub struct DeckAudit {
+    commitment: Option<Commitment>,
+    active_player: PlayerId,
+    // Void tables first, the one the round was played with last
+    tables: Vec<[Card; 4]>,
+    // Own hands in the order they were dealt, spectators don't get any
+    hands: Vec<[Card; 3]>,
+    played: Vec<(PlayerId, Card)>,
+}
+
+impl DeckAudit {
+    // Forgets the previous round
+    pub fn start(&mut self, active_player: PlayerId) {
+        *self = Self {
+            active_player,
+            ..Self::default()
+        };
+    }
+
+    pub fn committed(&mut self, commitment: Commitment) {
+        self.commitment = Some(commitment);
+    }
+
+    pub fn table(&mut self, table: [Card; 4]) {
+        self.tables.push(table);
+    }
+
+    pub fn hand(&mut self, hand: [Card; 3]) {
+        self.hands.push(hand);
+    }
+
+    pub fn played(&mut self, id: PlayerId, card: Card) {
+        self.played.push((id, card));
+    }
+
+    // Seats in the order the players move. Hands are dealt the same way the game deals them,
+    // starting with the player who moves first.
+    pub fn verify(
+        &self,
+        seed: u64,
+        seats: &[PlayerId],
+        me: PlayerId,
+        redeal: RedealRule,
+    ) -> Result<(), FairnessError> {
+        let Some(commitment) = self.commitment else {
+            return Err(FairnessError::NotCommitted);
+        };
+        if commit(seed) != commitment {
+            return Err(FairnessError::WrongSeed);
+        }
+        let (mut deck, table, mut tables) = shuffle_round(seed, redeal);
+        tables.push(table);
+        if tables != self.tables {
+            return Err(FairnessError::TableChanged);
+        }
+        let first = seats
+            .iter()
+            .position(|id| *id == self.active_player)
+            .unwrap_or_default();
+        let mut dealt: HashMap<PlayerId, Vec<Card>> = HashMap::with_capacity(seats.len());
+        for id in seats.iter().cycle().skip(first) {
+            if deck.len() < 3 {
+                break;
+            }
+            dealt.entry(*id).or_default().extend(deck.deal_hand());
+        }
+        let cards_of = |id: PlayerId| dealt.get(&id).map_or(&[][..], Vec::as_slice);
+        let seen: Vec<Card> = self.hands.iter().flatten().copied().collect();
+        if !cards_of(me).starts_with(&seen) {
+            return Err(FairnessError::HandChanged);
+        }
+        match self
+            .played
+            .iter()
+            .find(|(id, card)| !cards_of(*id).contains(card))
+        {
+            Some((id, card)) => Err(FairnessError::CardNotDealt {
+                id: *id,
+                card: *card,
+            }),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::card::{Card, CardValue, Deck, Suite};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FrenchSuit {
+    Hearts,
+    Diamonds,
+    Clubs,
+    Spades,
+}
+
+impl From<FrenchSuit> for Suite {
+    fn from(value: FrenchSuit) -> Self {
+        match value {
+            FrenchSuit::Hearts => Suite::Cups,
+            FrenchSuit::Diamonds => Suite::Coins,
+            FrenchSuit::Clubs => Suite::Clubs,
+            FrenchSuit::Spades => Suite::Swords,
+        }
+    }
+}
+
+impl From<Suite> for FrenchSuit {
+    fn from(value: Suite) -> Self {
+        match value {
+            Suite::Cups => FrenchSuit::Hearts,
+            Suite::Coins => FrenchSuit::Diamonds,
+            Suite::Clubs => FrenchSuit::Clubs,
+            Suite::Swords => FrenchSuit::Spades,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FrenchRank {
+    Ace,
+    Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+    Ten,
+    Jack,
+    Queen,
+    King,
+}
+
+impl FrenchRank {
+    pub const ALL: [FrenchRank; 13] = [
+        FrenchRank::Ace,
+        FrenchRank::Two,
+        FrenchRank::Three,
+        FrenchRank::Four,
+        FrenchRank::Five,
+        FrenchRank::Six,
+        FrenchRank::Seven,
+        FrenchRank::Eight,
+        FrenchRank::Nine,
+        FrenchRank::Ten,
+        FrenchRank::Jack,
+        FrenchRank::Queen,
+        FrenchRank::King,
+    ];
+
+    // Eights, nines and tens have no place in a scopa deck
+    pub fn to_scopa(&self) -> Option<CardValue> {
+        use FrenchRank::*;
+        let value = match self {
+            Ace => CardValue::One,
+            Two => CardValue::Two,
+            Three => CardValue::Three,
+            Four => CardValue::Four,
+            Five => CardValue::Five,
+            Six => CardValue::Six,
+            Seven => CardValue::Seven,
+            Eight | Nine | Ten => return None,
+            Jack => CardValue::Fante,
+            Queen => CardValue::Cavallo,
+            King => CardValue::Re,
+        };
+        Some(value)
+    }
+}
+
+impl From<CardValue> for FrenchRank {
+    fn from(value: CardValue) -> Self {
+        use CardValue::*;
+        match value {
+            One => FrenchRank::Ace,
+            Two => FrenchRank::Two,
+            Three => FrenchRank::Three,
+            Four => FrenchRank::Four,
+            Five => FrenchRank::Five,
+            Six => FrenchRank::Six,
+            Seven => FrenchRank::Seven,
+            Fante => FrenchRank::Jack,
+            Cavallo => FrenchRank::Queen,
+            Re => FrenchRank::King,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FrenchCard {
+    pub suit: FrenchSuit,
+    pub rank: FrenchRank,
+}
+
+impl FrenchCard {
+    pub fn new(suit: FrenchSuit, rank: FrenchRank) -> Self {
+        Self { suit, rank }
+    }
+
+    pub fn to_scopa(&self) -> Option<Card> {
+        self.rank
+            .to_scopa()
+            .map(|value| Card::new(self.suit.into(), value))
+    }
+}
+
+impl From<Card> for FrenchCard {
+    fn from(value: Card) -> Self {
+        Self {
+            suit: value.suite.into(),
+            rank: value.value.into(),
+        }
+    }
+}
+
+impl Deck {
+    // Standard 52 card French deck with 8s, 9s and 10s removed
+    pub fn french() -> Self {
+        use FrenchSuit::*;
+        let cards = [Diamonds, Clubs, Hearts, Spades]
+            .into_iter()
+            .flat_map(|suit| FrenchRank::ALL.map(|rank| FrenchCard::new(suit, rank)));
+        Self::from_french(cards)
+    }
+
+    pub fn from_french(cards: impl IntoIterator<Item = FrenchCard>) -> Self {
+        Self::from_cards(
+            cards
+                .into_iter()
+                .filter_map(|card| card.to_scopa())
+                .collect(),
+        )
+    }
+}
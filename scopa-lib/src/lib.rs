@@ -1,7 +1,14 @@
 #![allow(dead_code)]
 pub mod card;
+pub mod fairness;
+pub mod french;
+pub mod protocol;
+pub mod rules;
+pub mod scenario;
+pub mod snapshot;
 
 use card::*;
+use rules::*;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -49,11 +56,33 @@ impl TakenCards {
         put_into.push(card);
     }
 
-    fn primes(&self) -> u8 {
-        self.coins.iter().max().map_or(0, |c| c.prime())
-            + self.clubs.iter().max().map_or(0, |c| c.prime())
-            + self.cups.iter().max().map_or(0, |c| c.prime())
-            + self.swords.iter().max().map_or(0, |c| c.prime())
+    fn suites(&self) -> [&Vec<Card>; 4] {
+        [&self.coins, &self.clubs, &self.cups, &self.swords]
+    }
+
+    fn primes(&self, prime_table: &PrimeTable) -> u8 {
+        self.suites()
+            .iter()
+            .map(|suite| {
+                suite
+                    .iter()
+                    .map(|c| prime_table.prime(c.value))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .sum()
+    }
+
+    fn has_all_suites(&self) -> bool {
+        self.suites().iter().all(|suite| !suite.is_empty())
+    }
+
+    fn count_of(&self, value: CardValue) -> u8 {
+        self.suites()
+            .iter()
+            .flat_map(|suite| suite.iter())
+            .filter(|c| c.value == value)
+            .count() as u8
     }
 }
 
@@ -84,6 +113,12 @@ impl Player {
         self.taken.clear();
     }
 
+    fn clear_round(&mut self) {
+        self.scopas = 0;
+        self.hand.clear();
+        self.taken.clear();
+    }
+
     fn new_hand(&mut self, hand: &[Card; 3]) {
         self.hand = hand.into();
     }
@@ -94,19 +129,23 @@ impl Player {
         }
     }
 
-    fn results(&self) -> Results {
+    fn results(&self, rules: &RuleSet) -> Results {
         let takes = self.taken.count();
         let count_of_coins = self.taken.coins.len();
         let seven_of_coins = self.taken.coins.contains(&Card {
             suite: Suite::Coins,
             value: CardValue::Seven,
         });
-        let primes = self.taken.primes();
+        let primes = self.taken.primes(&rules.prime_table);
         Results {
             takes: takes as u8,
             count_of_coins: count_of_coins as u8,
             seven_of_coins,
             primes,
+            primiera_eligible: !rules.primiera_needs_all_suites || self.taken.has_all_suites(),
+            sevens: self.taken.count_of(CardValue::Seven),
+            sixes: self.taken.count_of(CardValue::Six),
+            aces: self.taken.count_of(CardValue::One),
             scopas: self.scopas,
         }
     }
@@ -114,18 +153,38 @@ impl Player {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Results {
-    takes: u8,
-    count_of_coins: u8,
-    seven_of_coins: bool,
-    primes: u8,
-    scopas: u8,
+    pub takes: u8,
+    pub count_of_coins: u8,
+    pub seven_of_coins: bool,
+    pub primes: u8,
+    // False when a missing suit rules the player out of primiera
+    pub primiera_eligible: bool,
+    pub sevens: u8,
+    pub sixes: u8,
+    pub aces: u8,
+    pub scopas: u8,
+}
+
+// Returns the only player with the best result. Players without a result are not competing.
+fn unique_best<K: Ord>(
+    results: &[(PlayerId, Results)],
+    key: impl Fn(&Results) -> Option<K>,
+) -> Option<PlayerId> {
+    let max = results.iter().filter_map(|(_, r)| key(r)).max()?;
+    let mut best = results
+        .iter()
+        .filter(|(_, r)| key(r).as_ref() == Some(&max));
+    match (best.next(), best.next()) {
+        (Some((id, _)), None) => Some(*id),
+        _ => None,
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Points {
-    id: PlayerId,
-    points: u8,
-    details: Results,
+    pub id: PlayerId,
+    pub points: u8,
+    pub details: Results,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -142,17 +201,30 @@ pub enum GameEvent {
         active_player: PlayerId,
     },
     EndRound {
-        points: [Points; 2],
+        points: Vec<Points>,
     },
     PlayerWon {
         id: PlayerId,
     },
     DealHand {
+        id: PlayerId,
         hand: [Card; 3],
     },
+    // Hash of the seed the round is shuffled with, sent before any card is dealt
+    CommitDeck {
+        commitment: fairness::Commitment,
+    },
+    // Seed of the round that just ended, so the shuffle can be checked against the commitment
+    RevealDeck {
+        seed: u64,
+    },
     PlaceTable {
         table: [Card; 4],
     },
+    // Initial table was void according to the rules, so cards were reshuffled and dealt again
+    TableRedealt {
+        table: [Card; 4],
+    },
     PutCard {
         id: PlayerId,
         card: Card,
@@ -162,6 +234,9 @@ pub enum GameEvent {
         take: Vec<Card>,
         with: Card,
     },
+    Scopa {
+        id: PlayerId,
+    },
 }
 
 #[derive(Debug)]
@@ -181,7 +256,10 @@ impl std::fmt::Display for ScopaError {
             Logic(msg) => write!(f, "Logic error: {}", msg),
             Card(msg) => write!(f, "Card error: {}", msg),
             OutOfTurn => write!(f, "Player made a move out of turn"),
-            PuttingOnFullTable => write!(f, "Trying to put a card on a full table"),
+            PuttingOnFullTable => write!(
+                f,
+                "Trying to put a card on a full table, when you can take from it"
+            ),
         }
     }
 }
@@ -194,31 +272,426 @@ impl std::error::Error for ScopaError {
 
 #[derive(Debug)]
 pub struct ScopaGame {
+    rules: RuleSet,
     players: HashMap<PlayerId, Player>,
+    // Players in the order they make their moves
+    seats: Vec<PlayerId>,
     deck: Deck,
     table: Table,
     active_player: PlayerId,
     took_last: PlayerId,
+    // Seat of the player who makes the first move in the current round
+    first_seat: usize,
+    // Every shuffle of the game follows from the seed, so a game can be replayed from its moves
+    seed: u64,
+    // Rounds dealt so far
+    round: u64,
 }
 
 impl Default for ScopaGame {
     fn default() -> Self {
+        Self::new(RuleSet::default())
+    }
+}
+
+impl ScopaGame {
+    pub fn new(rules: RuleSet) -> Self {
+        let players = rules.players;
         Self {
-            players: HashMap::with_capacity(2),
+            rules,
+            players: HashMap::with_capacity(players),
+            seats: Vec::with_capacity(players),
             deck: Deck::default(),
             table: Table::default(),
             active_player: PlayerId::default(),
             took_last: PlayerId::default(),
+            first_seat: 0,
+            seed: rand::random(),
+            round: 0,
         }
     }
-}
 
-impl ScopaGame {
-    pub fn new_round(&mut self) {
-        let mut deck = Deck::default();
-        deck.shuffle();
-        self.deck = deck;
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    pub fn seats(&self) -> &[PlayerId] {
+        &self.seats
+    }
+
+    pub fn active_player(&self) -> PlayerId {
+        self.active_player
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.seats.len() >= self.rules.players
+    }
+
+    pub fn player_name(&self, id: PlayerId) -> Option<&str> {
+        self.players.get(&id).map(|p| p.name.as_str())
+    }
+
+    pub fn hand(&self, id: PlayerId) -> Option<&[Card]> {
+        self.players.get(&id).map(|p| p.hand.as_slice())
+    }
+
+    // Player sitting opposite in a team game, who scores and wins together with this one
+    pub fn partner(&self, id: PlayerId) -> Option<PlayerId> {
+        if !self.rules.teams() {
+            return None;
+        }
+        let seat = self.seats.iter().position(|seat| *seat == id)?;
+        self.seats.get((seat + 2) % TEAM_PLAYERS).copied()
+    }
+
+    // One seat for everybody playing alone, or the first seat of each team
+    fn sides(&self) -> &[PlayerId] {
+        match self.rules.teams() {
+            true => &self.seats[..self.seats.len().min(2)],
+            false => &self.seats,
+        }
+    }
+
+    pub fn points(&self, id: PlayerId) -> Option<u8> {
+        self.players.get(&id).map(|p| p.points)
+    }
+
+    // Cards taken by the player this round
+    pub fn taken(&self, id: PlayerId) -> Option<Vec<Card>> {
+        self.players
+            .get(&id)
+            .map(|p| p.taken.suites().into_iter().flatten().copied().collect())
+    }
+
+    pub fn table(&self) -> impl Iterator<Item = &Card> {
+        self.table.iter()
+    }
+
+    // Every move the active player can make
+    pub fn legal_moves(&self) -> Vec<GameEvent> {
+        let id = self.active_player;
+        let Some(hand) = self.hand(id) else {
+            return Vec::new();
+        };
+        let mut moves = Vec::new();
+        for with in hand.iter().copied() {
+            // Any card of the same value can be taken when there are several
+            let same: Vec<Card> = self
+                .table
+                .iter()
+                .filter(|card| card.value() == with.value())
+                .copied()
+                .collect();
+            if !same.is_empty() {
+                for card in same {
+                    moves.push(GameEvent::TakeCards {
+                        id,
+                        take: vec![card],
+                        with,
+                    });
+                }
+                continue;
+            }
+            for take in self.cards_summing_to(with.value()) {
+                moves.push(GameEvent::TakeCards { id, take, with });
+            }
+            moves.push(GameEvent::PutCard { id, card: with });
+        }
+        moves.retain(|event| self.validate(event).is_ok());
+        moves
+    }
+
+    // Move made for the active player when they run out of time: their lowest card is put on the
+    // table, or used to take when the rules leave no other choice
+    pub fn fallback_move(&self) -> Option<GameEvent> {
+        let id = self.active_player;
+        let mut hand = self.hand(id)?.to_vec();
+        hand.sort_by_key(Card::value);
+        hand.into_iter().find_map(|with| {
+            let event = match self.table.contains_same_value(&with) {
+                Some(same) => GameEvent::TakeCards {
+                    id,
+                    take: vec![*same],
+                    with,
+                },
+                None => GameEvent::PutCard { id, card: with },
+            };
+            if self.validate(&event).is_ok() {
+                return Some(event);
+            }
+            // The table is full, so something has to be taken
+            let take = self.cards_summing_to(with.value()).into_iter().next()?;
+            Some(GameEvent::TakeCards { id, take, with })
+        })
+    }
+
+    // Whether some cards of the table add up to the card, same as a card of its value does
+    fn can_take(&self, with: &Card) -> bool {
+        let value = usize::from(with.value());
+        let mut sums = vec![false; value + 1];
+        sums[0] = true;
+        for card in self.table.iter() {
+            for sum in (usize::from(card.value())..=value).rev() {
+                sums[sum] |= sums[sum - usize::from(card.value())];
+            }
+        }
+        sums[value]
+    }
+
+    // Every combination of table cards with the given sum
+    fn cards_summing_to(&self, value: u8) -> Vec<Vec<Card>> {
+        let table: Vec<Card> = self.table.iter().copied().collect();
+        (1..1u32 << table.len())
+            .map(|mask| {
+                table
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| mask & (1 << i) != 0)
+                    .map(|(_, card)| *card)
+                    .collect::<Vec<Card>>()
+            })
+            .filter(|take| take.iter().map(Card::value).sum::<u8>() == value)
+            .collect()
+    }
+
+    pub fn add_player(&mut self, id: PlayerId, name: &str) -> Result<GameEvent, ScopaError> {
+        let event = GameEvent::PlayerConnected {
+            id,
+            name: name.into(),
+        };
+        self.validate(&event)?;
+        self.consume(&event);
+        Ok(event)
+    }
+
+    pub fn remove_player(&mut self, id: PlayerId) -> Result<GameEvent, ScopaError> {
+        let name = self.player_name(id).unwrap_or_default().into();
+        let event = GameEvent::PlayerDisconnected { id, name };
+        self.validate(&event)?;
+        self.consume(&event);
+        Ok(event)
+    }
+
+    // Starts the first round once every seat is taken
+    pub fn start(&mut self) -> Result<Vec<GameEvent>, ScopaError> {
+        if !SUPPORTED_PLAYERS.contains(&self.rules.players) {
+            return Err(ScopaError::Logic(format!(
+                "Scopa can't be played by {} players",
+                self.rules.players
+            )));
+        }
+        if !self.is_full() {
+            return Err(ScopaError::Logic("Waiting for more players".into()));
+        }
+        self.new_round()
+    }
+
+    // Shuffles a new deck, places the initial table and deals hands. If the table is void
+    // according to the rules, cards are reshuffled until a valid table comes out. Returns events
+    // for the clients.
+    pub fn new_round(&mut self) -> Result<Vec<GameEvent>, ScopaError> {
+        if self.seats.is_empty() {
+            return Err(ScopaError::Logic("Nobody is seated".into()));
+        }
+        let mut events = Vec::with_capacity(2 + self.seats.len());
         self.table.clear();
+        for player in self.players.values_mut() {
+            player.clear_round();
+        }
+        let active_player = self.seats[self.first_seat % self.seats.len()];
+        self.consume_into(GameEvent::StartRound { active_player }, &mut events);
+        self.round += 1;
+        let seed = self.round_seed();
+        let commitment = fairness::commit(seed);
+        self.consume_into(GameEvent::CommitDeck { commitment }, &mut events);
+        let (deck, table, void) = fairness::shuffle_round(seed, self.rules.redeal);
+        events.extend(
+            void.into_iter()
+                .map(|table| GameEvent::TableRedealt { table }),
+        );
+        self.deck = deck;
+        self.consume_into(GameEvent::PlaceTable { table }, &mut events);
+        self.deal_hands(&mut events);
+        Ok(events)
+    }
+
+    // Validates and applies a player's move. Returns the move itself followed by everything it
+    // caused: a scopa, new hands, the end of the round or of the whole game.
+    pub fn play(&mut self, event: GameEvent) -> Result<Vec<GameEvent>, ScopaError> {
+        let id = match &event {
+            GameEvent::PutCard { id, .. } | GameEvent::TakeCards { id, .. } => *id,
+            _ => return Err(ScopaError::Logic("Not a player's move".into())),
+        };
+        self.validate(&event)?;
+        let mut events = Vec::with_capacity(2);
+        let took = matches!(event, GameEvent::TakeCards { .. });
+        self.consume_into(event, &mut events);
+        let round_is_over = self.deck.is_empty() && self.hands_are_empty();
+        // Clearing the table with the very last move of a round doesn't count as a scopa
+        if took && self.table.is_empty() && !round_is_over {
+            self.consume_into(GameEvent::Scopa { id }, &mut events);
+        }
+        if round_is_over {
+            self.end_round(&mut events)?;
+        } else if self.hands_are_empty() {
+            self.deal_hands(&mut events);
+        }
+        Ok(events)
+    }
+
+    // Seed the current round was shuffled with
+    fn round_seed(&self) -> u64 {
+        fairness::round_seed(self.seed, self.round.saturating_sub(1))
+    }
+
+    fn hands_are_empty(&self) -> bool {
+        self.players.values().all(|p| p.hand.is_empty())
+    }
+
+    fn consume_into(&mut self, event: GameEvent, events: &mut Vec<GameEvent>) {
+        self.consume(&event);
+        events.push(event);
+    }
+
+    // Deal 3 cards to each player starting with the one who moves first
+    fn deal_hands(&mut self, events: &mut Vec<GameEvent>) {
+        let first = self
+            .seats
+            .iter()
+            .position(|id| *id == self.active_player)
+            .unwrap_or_default();
+        for i in 0..self.seats.len() {
+            let id = self.seats[(first + i) % self.seats.len()];
+            let hand = self.deck.deal_hand();
+            self.consume_into(GameEvent::DealHand { id, hand }, events);
+        }
+    }
+
+    fn end_round(&mut self, events: &mut Vec<GameEvent>) -> Result<(), ScopaError> {
+        // Cards left on the table go to the player who took last
+        let leftovers: Vec<Card> = self.table.drain().collect();
+        if let Some(player) = self.players.get_mut(&self.took_last) {
+            player.take_cards(leftovers);
+        }
+        let points = self.round_points();
+        self.consume_into(GameEvent::EndRound { points }, events);
+        let seed = self.round_seed();
+        self.consume_into(GameEvent::RevealDeck { seed }, events);
+        self.first_seat = (self.first_seat + 1) % self.seats.len();
+        match self.winner() {
+            Some(id) => self.consume_into(GameEvent::PlayerWon { id }, events),
+            None => events.extend(self.new_round()?),
+        }
+        Ok(())
+    }
+
+    // Games set up from the outside, by a scenario or a snapshot, must have hands and a deck that
+    // can be dealt from like in a game played from the start
+    fn check_deal(&self) -> Result<(), ScopaError> {
+        if self.players.values().any(|p| p.hand.len() > 3) {
+            return Err(ScopaError::Card(
+                "Hands can't have more than 3 cards".into(),
+            ));
+        }
+        let players = self.seats.len();
+        if players == 0 || !self.deck.len().is_multiple_of(3 * players) {
+            return Err(ScopaError::Logic(format!(
+                "{} cards left in the deck can't be dealt to {} players",
+                self.deck.len(),
+                players
+            )));
+        }
+        Ok(())
+    }
+
+    // Cards and scopas of a team count together, as if one player took them all
+    fn side_results(&self, id: PlayerId) -> Results {
+        let player = &self.players[&id];
+        let Some(partner) = self
+            .partner(id)
+            .and_then(|partner| self.players.get(&partner))
+        else {
+            return player.results(&self.rules);
+        };
+        let mut side = Player::new(&player.name);
+        side.scopas = player.scopas + partner.scopas;
+        for member in [player, partner] {
+            side.take_cards(
+                member
+                    .taken
+                    .suites()
+                    .into_iter()
+                    .flatten()
+                    .copied()
+                    .collect(),
+            );
+        }
+        side.results(&self.rules)
+    }
+
+    // Points scored by each player in the current round. Card, coin and primiera points are only
+    // awarded when a single side has the best result, ties give nothing to anyone. Partners get
+    // the points of their team.
+    fn round_points(&self) -> Vec<Points> {
+        let results: Vec<(PlayerId, Results)> = self
+            .sides()
+            .iter()
+            .map(|id| (*id, self.side_results(*id)))
+            .collect();
+        let primiera = match self.rules.primiera {
+            PrimieraRule::Sum => unique_best(&results, |r| r.primiera_eligible.then_some(r.primes)),
+            PrimieraRule::CountSevens => unique_best(&results, |r| {
+                r.primiera_eligible.then_some((r.sevens, r.sixes, r.aces))
+            }),
+        };
+        let awards = [
+            unique_best(&results, |r| Some(r.takes)),
+            unique_best(&results, |r| Some(r.count_of_coins)),
+            primiera,
+            results
+                .iter()
+                .find(|(_, r)| r.seven_of_coins)
+                .map(|(id, _)| *id),
+        ];
+        self.seats
+            .iter()
+            .enumerate()
+            .map(|(seat, id)| {
+                let (side, details) = &results[seat % results.len()];
+                let won = awards.iter().filter(|award| **award == Some(*side)).count() as u8;
+                Points {
+                    id: *id,
+                    points: details.scopas + won,
+                    details: details.clone(),
+                }
+            })
+            .collect()
+    }
+
+    // The game is won by the side with the most points once somebody reaches the target score.
+    // If the leaders are tied, another round is played. A team is named by its first player.
+    fn winner(&self) -> Option<PlayerId> {
+        let max = self.players.values().map(|p| p.points).max()?;
+        if max < self.rules.target_score {
+            return None;
+        }
+        let mut leaders = self
+            .sides()
+            .iter()
+            .filter(|id| self.players[id].points == max);
+        match (leaders.next(), leaders.next()) {
+            (Some(id), None) => Some(*id),
+            _ => None,
+        }
     }
 
     pub fn validate(&self, event: &GameEvent) -> Result<(), ScopaError> {
@@ -227,18 +700,19 @@ impl ScopaGame {
                 if self.players.contains_key(id) {
                     return Err(ScopaError::Player("Already connected".into()));
                 }
-            }
-            GameEvent::PlayerDisconnected { id, .. } => {
-                if !self.players.contains_key(id) {
-                    return Err(ScopaError::Player("Unknown player".into()));
+                if self.is_full() {
+                    return Err(ScopaError::Player("The game is full".into()));
                 }
             }
-            GameEvent::StartRound { active_player } => {
-                if !self.players.contains_key(active_player) {
-                    return Err(ScopaError::Player("Unknown player".into()));
-                }
+            GameEvent::PlayerDisconnected { id, .. } if !self.players.contains_key(id) => {
+                return Err(ScopaError::Player("Unknown player".into()));
             }
-            GameEvent::PlaceTable { .. } => {
+            GameEvent::StartRound { active_player }
+                if !self.players.contains_key(active_player) =>
+            {
+                return Err(ScopaError::Player("Unknown player".into()));
+            }
+            GameEvent::PlaceTable { table } => {
                 // Game places 4 cards on the table just once in the beginning of a round, so the table must be
                 // empty at this point
                 if !self.table.is_empty() {
@@ -246,6 +720,11 @@ impl ScopaGame {
                         "Table should be empty at this stage".into(),
                     ));
                 }
+                if self.rules.redeal.requires_redeal(table) {
+                    return Err(ScopaError::Logic(
+                        "Initial table is void and should be redealt".into(),
+                    ));
+                }
             }
             GameEvent::PutCard { id, card } => {
                 if !self.players.contains_key(id) {
@@ -254,12 +733,14 @@ impl ScopaGame {
                 if self.active_player != *id {
                     return Err(ScopaError::OutOfTurn);
                 }
-                // The table is full
-                if self.table.len() >= 10 {
+                // It is safe to unwrap because we already checked that player is connected
+                let hand = &self.players.get(id).unwrap().hand;
+                // The table is full, but a hand that can't take anything from it is put down all
+                // the same
+                if self.table.len() >= 10 && hand.iter().any(|with| self.can_take(with)) {
                     return Err(ScopaError::PuttingOnFullTable);
                 }
-                // It is safe to unwrap because we already checked that player is connected
-                if !self.players.get(id).unwrap().hand.contains(card) {
+                if !hand.contains(card) {
                     return Err(ScopaError::Card(
                         "Card does not exist in player's hand".into(),
                     ));
@@ -295,16 +776,31 @@ impl ScopaGame {
                         "Trying to take a card which is not present on the table".into(),
                     ));
                 }
+                if let Some(card) = take
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, card)| take[..i].contains(card).then_some(card))
+                {
+                    return Err(ScopaError::Logic(format!(
+                        "Trying to take {} more than once",
+                        card
+                    )));
+                }
                 if let Some(same_value) = self.table.contains_same_value(with) {
-                    if take.len() > 1 || take[0] != *same_value {
-                        return Err(ScopaError::Logic(
-                                "There is a card with the same value on the table ({same_value}). You should take it with your {with} instead.".into()
-                        ));
+                    if take.len() > 1 || take[0].value() != with.value() {
+                        return Err(ScopaError::Logic(format!(
+                            "There is a card with the same value on the table ({}). You should take it with your {} instead.",
+                            same_value, with
+                        )));
                     }
                 }
                 let take_sum: u8 = take.iter().map(|card| card.value()).sum();
                 if take_sum != with.value() {
-                    return Err(ScopaError::Logic("Trying to take cards with sum value {take_sum} with a card with value of {with.value()}".into()));
+                    return Err(ScopaError::Logic(format!(
+                        "Trying to take cards with sum value {} with a card with value of {}",
+                        take_sum,
+                        with.value()
+                    )));
                 }
             }
             _ => {}
@@ -313,13 +809,79 @@ impl ScopaGame {
     }
 
     fn consume(&mut self, event: &GameEvent) {
-        unimplemented!()
+        match event {
+            GameEvent::PlayerConnected { id, name } => {
+                self.players.insert(*id, Player::new(name));
+                self.seats.push(*id);
+            }
+            GameEvent::PlayerDisconnected { id, .. } => {
+                self.players.remove(id);
+                self.seats.retain(|seat| seat != id);
+            }
+            GameEvent::StartRound { active_player } => {
+                self.active_player = *active_player;
+            }
+            GameEvent::PlaceTable { table } => {
+                for card in table {
+                    self.table.put_card(*card);
+                }
+            }
+            GameEvent::DealHand { id, hand } => {
+                if let Some(player) = self.players.get_mut(id) {
+                    player.new_hand(hand);
+                }
+            }
+            GameEvent::PutCard { id, card } => {
+                if let Some(player) = self.players.get_mut(id) {
+                    player.hand.retain(|c| c != card);
+                }
+                self.table.put_card(*card);
+                self.next_turn();
+            }
+            GameEvent::TakeCards { id, take, with } => {
+                let mut taken: Vec<Card> = take
+                    .iter()
+                    .filter_map(|card| self.table.take_card(card))
+                    .collect();
+                taken.push(*with);
+                if let Some(player) = self.players.get_mut(id) {
+                    player.hand.retain(|c| c != with);
+                    player.take_cards(taken);
+                }
+                self.took_last = *id;
+                self.next_turn();
+            }
+            GameEvent::Scopa { id } => {
+                if let Some(player) = self.players.get_mut(id) {
+                    player.scopas += 1;
+                }
+            }
+            GameEvent::EndRound { points } => {
+                for p in points {
+                    if let Some(player) = self.players.get_mut(&p.id) {
+                        player.points += p.points;
+                    }
+                }
+            }
+            GameEvent::PlayerWon { .. }
+            | GameEvent::TableRedealt { .. }
+            | GameEvent::CommitDeck { .. }
+            | GameEvent::RevealDeck { .. } => {}
+        }
+    }
+
+    fn next_turn(&mut self) {
+        if let Some(seat) = self.seats.iter().position(|id| *id == self.active_player) {
+            self.active_player = self.seats[(seat + 1) % self.seats.len()];
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use scenario::ScenarioBuilder;
+    use std::collections::HashSet;
 
     fn player_with_cards() -> Player {
         let mut p = Player::new("test");
@@ -385,13 +947,13 @@ mod tests {
     #[test]
     fn get_primes() {
         let p = player_with_cards();
-        assert_eq!(p.taken.primes(), 67);
+        assert_eq!(p.taken.primes(&PrimeTable::default()), 67);
     }
 
     #[test]
     fn check_results() {
         let p = player_with_cards();
-        let r = p.results();
+        let r = p.results(&RuleSet::default());
         assert_eq!(r.scopas, 0);
         assert_eq!(r.takes, 11);
         assert_eq!(r.primes, 67);
@@ -399,6 +961,64 @@ mod tests {
         assert!(r.seven_of_coins);
     }
 
+    #[test]
+    fn custom_prime_table() {
+        let p = player_with_cards();
+        let flat = PrimeTable::new([1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        // Re of coins, Cavallo of swords, Six of cups and Re of clubs
+        assert_eq!(p.taken.primes(&flat), 35);
+
+        // Primes too high to add up, or a score too close to u8::MAX, aren't accepted from clients
+        let highest = PrimeTable::new([MAX_PRIME; 10]);
+        assert_eq!(p.taken.primes(&highest), 4 * MAX_PRIME);
+        let rules = |prime_table, target_score| RuleSet {
+            prime_table,
+            target_score,
+            ..RuleSet::default()
+        };
+        assert!(rules(highest, MAX_TARGET_SCORE).is_playable());
+        assert!(!rules(PrimeTable::new([255; 10]), 11).is_playable());
+        assert!(!rules(PrimeTable::default(), 255).is_playable());
+        assert!(!rules(PrimeTable::default(), 0).is_playable());
+    }
+
+    #[test]
+    fn primiera_rules() {
+        use CardValue::*;
+        use Suite::*;
+        let mut game = game_with_players(RuleSet::default());
+        // Player 1 has the best primes but no swords, player 2 has more sevens but no clubs
+        let piles = [
+            vec![
+                Card::new(Coins, Seven),
+                Card::new(Cups, Six),
+                Card::new(Clubs, Six),
+            ],
+            vec![
+                Card::new(Swords, Seven),
+                Card::new(Cups, Seven),
+                Card::new(Swords, Two),
+                Card::new(Coins, Two),
+            ],
+        ];
+        for (id, pile) in (1..=2).zip(piles) {
+            game.players.get_mut(&id).unwrap().take_cards(pile);
+        }
+        // Settebello plus primiera for the first player, carte for the second one
+        let points = game.round_points();
+        assert_eq!((points[0].points, points[1].points), (2, 1));
+
+        game.rules.primiera_needs_all_suites = true;
+        let points = game.round_points();
+        assert!(!points[0].details.primiera_eligible);
+        assert_eq!((points[0].points, points[1].points), (1, 1));
+
+        game.rules.primiera_needs_all_suites = false;
+        game.rules.primiera = PrimieraRule::CountSevens;
+        let points = game.round_points();
+        assert_eq!((points[0].points, points[1].points), (1, 2));
+    }
+
     #[test]
     fn compare_cards() {
         let c1 = Card {
@@ -427,6 +1047,431 @@ mod tests {
         assert_ne!(c1, c5);
     }
 
+    #[test]
+    fn french_deck() {
+        use french::*;
+        let deck = Deck::french();
+        assert_eq!(deck.len(), 40);
+        let italian: HashSet<Card> = Deck::default().cards().iter().copied().collect();
+        assert!(deck.cards().iter().all(|card| italian.contains(card)));
+
+        let ten = FrenchCard::new(FrenchSuit::Hearts, FrenchRank::Ten);
+        assert_eq!(ten.to_scopa(), None);
+        let queen = FrenchCard::new(FrenchSuit::Diamonds, FrenchRank::Queen);
+        let cavallo = Card::new(Suite::Coins, CardValue::Cavallo);
+        assert_eq!(queen.to_scopa(), Some(cavallo));
+        assert_eq!(FrenchCard::from(cavallo), queen);
+        assert_eq!(cavallo.name(DeckStyle::Italian), "Cavallo of Coins");
+        assert_eq!(cavallo.name(DeckStyle::French), "Queen of Diamonds");
+    }
+
+    #[test]
+    fn deck_from_order() {
+        use CardValue::*;
+        use Suite::*;
+        let order = vec![
+            Card::new(Coins, One),
+            Card::new(Coins, Two),
+            Card::new(Coins, Three),
+            Card::new(Coins, Four),
+        ];
+        let mut deck = Deck::from_order(order.clone()).unwrap();
+        assert_eq!(deck.place_table().to_vec(), order);
+        assert!(Deck::from_order(vec![Card::new(Cups, Re), Card::new(Cups, Re)]).is_err());
+    }
+
+    fn rest_of_deck(used: &[Card]) -> Vec<Card> {
+        Deck::default()
+            .cards()
+            .iter()
+            .filter(|card| !used.contains(card))
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn scenario_scopa() {
+        use CardValue::*;
+        use Suite::*;
+        let mut game = ScenarioBuilder::new(RuleSet::default())
+            .player(1, "first")
+            .player(2, "second")
+            .table(&[Card::new(Coins, Five)])
+            .hand(
+                1,
+                &[
+                    Card::new(Cups, Five),
+                    Card::new(Swords, Two),
+                    Card::new(Clubs, Three),
+                ],
+            )
+            .hand(
+                2,
+                &[
+                    Card::new(Coins, One),
+                    Card::new(Cups, One),
+                    Card::new(Swords, One),
+                ],
+            )
+            .taken(
+                2,
+                &[
+                    Card::new(Clubs, One),
+                    Card::new(Clubs, Two),
+                    Card::new(Clubs, Four),
+                ],
+            )
+            .build()
+            .unwrap();
+        assert_eq!(game.deck.len(), 30);
+        assert_eq!(game.active_player(), 1);
+        let events = game
+            .play(GameEvent::TakeCards {
+                id: 1,
+                take: vec![Card::new(Coins, Five)],
+                with: Card::new(Cups, Five),
+            })
+            .unwrap();
+        assert!(matches!(events[1], GameEvent::Scopa { id: 1 }));
+        assert_eq!(game.players[&1].scopas, 1);
+        assert_eq!(game.active_player(), 2);
+    }
+
+    #[test]
+    fn scenario_last_move() {
+        use CardValue::*;
+        use Suite::*;
+        let table = [Card::new(Coins, Five)];
+        let hand = [Card::new(Cups, Five)];
+        let taken = rest_of_deck(&[table[0], hand[0]]);
+        let mut game = ScenarioBuilder::new(RuleSet::default())
+            .player(1, "first")
+            .player(2, "second")
+            .table(&table)
+            .hand(1, &hand)
+            .taken(2, &taken)
+            .points(2, 10)
+            .scopas(2, 1)
+            .build()
+            .unwrap();
+        let events = game
+            .play(GameEvent::TakeCards {
+                id: 1,
+                take: table.to_vec(),
+                with: hand[0],
+            })
+            .unwrap();
+        // Clearing the table with the last card of the round is not a scopa
+        assert!(!events.iter().any(|e| matches!(e, GameEvent::Scopa { .. })));
+        let Some(GameEvent::EndRound { points }) = events.get(1) else {
+            panic!("Round should be over");
+        };
+        assert_eq!((points[0].points, points[1].points), (0, 5));
+        assert!(matches!(
+            events.last(),
+            Some(GameEvent::PlayerWon { id: 2 })
+        ));
+    }
+
+    #[test]
+    fn fallback_move() {
+        use CardValue::*;
+        use Suite::*;
+        let table = [Card::new(Clubs, Two)];
+        let first = [
+            Card::new(Cups, Five),
+            Card::new(Coins, Two),
+            Card::new(Swords, Re),
+        ];
+        let second = [
+            Card::new(Coins, Six),
+            Card::new(Cups, Six),
+            Card::new(Swords, Six),
+        ];
+        let used: Vec<Card> = table.iter().chain(&first).chain(&second).copied().collect();
+        let mut game = ScenarioBuilder::new(RuleSet::default())
+            .player(1, "first")
+            .player(2, "second")
+            .table(&table)
+            .hand(1, &first)
+            .hand(2, &second)
+            .taken(2, &rest_of_deck(&used))
+            .build()
+            .unwrap();
+        // A two can't be put next to another two, so it takes it
+        let fallback = game.fallback_move().unwrap();
+        assert!(matches!(
+            &fallback,
+            GameEvent::TakeCards { id: 1, take, with }
+                if take == &table.to_vec() && *with == first[1]
+        ));
+        // The two can only take the other two, the rest can only be put down
+        let moves = game.legal_moves();
+        assert_eq!(moves.len(), 3);
+        assert!(!moves
+            .iter()
+            .any(|event| matches!(event, GameEvent::PutCard { card, .. } if card.value() == 2)));
+        game.play(fallback).unwrap();
+        assert!(matches!(
+            game.fallback_move(),
+            Some(GameEvent::PutCard { id: 2, card }) if card.value() == 6
+        ));
+    }
+
+    #[test]
+    fn nothing_to_take_from_a_full_table() {
+        use CardValue::*;
+        use Suite::*;
+        let table = [
+            Card::new(Coins, Two),
+            Card::new(Coins, Three),
+            Card::new(Coins, Four),
+            Card::new(Coins, Five),
+            Card::new(Coins, Six),
+            Card::new(Coins, Seven),
+            Card::new(Coins, Fante),
+            Card::new(Coins, Cavallo),
+            Card::new(Coins, Re),
+            Card::new(Cups, Two),
+        ];
+        let first = [
+            Card::new(Coins, One),
+            Card::new(Cups, One),
+            Card::new(Swords, One),
+        ];
+        let second = [
+            Card::new(Cups, Three),
+            Card::new(Cups, Four),
+            Card::new(Clubs, One),
+        ];
+        let used: Vec<Card> = table.iter().chain(&first).chain(&second).copied().collect();
+        let mut game = ScenarioBuilder::new(RuleSet::default())
+            .player(1, "first")
+            .player(2, "second")
+            .table(&table)
+            .hand(1, &first)
+            .hand(2, &second)
+            .taken(2, &rest_of_deck(&used))
+            .build()
+            .unwrap();
+        // No ace on the table and nothing adds up to one, so an ace is put down
+        assert_eq!(game.legal_moves().len(), 3);
+        let fallback = game.fallback_move().unwrap();
+        assert!(matches!(
+            fallback,
+            GameEvent::PutCard { id: 1, card } if card.value() == 1
+        ));
+        game.play(fallback).unwrap();
+        assert_eq!(game.table().count(), 11);
+        // Whoever can take from a full table has to
+        let put = GameEvent::PutCard {
+            id: 2,
+            card: second[2],
+        };
+        assert!(matches!(
+            game.validate(&put),
+            Err(ScopaError::PuttingOnFullTable)
+        ));
+        assert!(game
+            .legal_moves()
+            .iter()
+            .all(|event| matches!(event, GameEvent::TakeCards { .. })));
+    }
+
+    #[test]
+    fn either_card_of_the_same_value_can_be_taken() {
+        use CardValue::*;
+        use Suite::*;
+        let table = [Card::new(Clubs, Two), Card::new(Cups, Two)];
+        let first = [Card::new(Coins, Two)];
+        let second = [Card::new(Swords, Six)];
+        let used: Vec<Card> = table.iter().chain(&first).chain(&second).copied().collect();
+        let game = ScenarioBuilder::new(RuleSet::default())
+            .player(1, "first")
+            .player(2, "second")
+            .table(&table)
+            .hand(1, &first)
+            .hand(2, &second)
+            .taken(2, &rest_of_deck(&used))
+            .build()
+            .unwrap();
+        for card in table {
+            let take = GameEvent::TakeCards {
+                id: 1,
+                take: vec![card],
+                with: first[0],
+            };
+            assert!(game.validate(&take).is_ok());
+        }
+        assert_eq!(game.legal_moves().len(), 2);
+    }
+
+    #[test]
+    fn cards_are_taken_only_once() {
+        use CardValue::*;
+        use Suite::*;
+        let table = [Card::new(Coins, Two), Card::new(Cups, Five)];
+        let first = [Card::new(Clubs, Four)];
+        let second = [Card::new(Swords, Six)];
+        let used: Vec<Card> = table.iter().chain(&first).chain(&second).copied().collect();
+        let mut game = ScenarioBuilder::new(RuleSet::default())
+            .player(1, "first")
+            .player(2, "second")
+            .table(&table)
+            .hand(1, &first)
+            .hand(2, &second)
+            .taken(2, &rest_of_deck(&used))
+            .build()
+            .unwrap();
+        let twice = GameEvent::TakeCards {
+            id: 1,
+            take: vec![table[0], table[0]],
+            with: first[0],
+        };
+        assert!(game.validate(&twice).is_err());
+        assert!(game.play(twice).is_err());
+        assert_eq!(game.table().count(), 2);
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut game = game_with_players(RuleSet::default());
+        game.start().unwrap();
+        let event = game.fallback_move().unwrap();
+        game.play(event).unwrap();
+        let snapshot = game.snapshot();
+        let restored = ScopaGame::restore(snapshot.clone()).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(restored.active_player(), game.active_player());
+        assert_eq!(restored.hand(1), game.hand(1));
+        assert_eq!(restored.deck.cards(), game.deck.cards());
+
+        let mut broken = snapshot.clone();
+        broken.table.push(broken.deck[0]);
+        assert!(ScopaGame::restore(broken).is_err());
+        let mut broken = snapshot;
+        broken.deck.pop();
+        assert!(ScopaGame::restore(broken).is_err());
+
+        // Shuffles carry on the same way
+        let mut restored = ScopaGame::restore(game.snapshot()).unwrap();
+        let expected = format!("{:?}", game.new_round().unwrap());
+        assert_eq!(format!("{:?}", restored.new_round().unwrap()), expected);
+
+        // Room still waiting for players
+        let waiting = ScopaGame::new(RuleSet::default()).snapshot();
+        assert!(ScopaGame::restore(waiting).is_ok());
+        assert!(ScopaGame::default().new_round().is_err());
+    }
+
+    #[test]
+    fn restored_games_can_be_dealt() {
+        let mut game = game_with_players(RuleSet::default());
+        game.start().unwrap();
+        let snapshot = game.snapshot();
+        assert!(ScopaGame::restore(snapshot.clone()).is_ok());
+
+        let mut four_cards = snapshot.clone();
+        let card = four_cards.table.pop().unwrap();
+        four_cards.players[0].hand.push(card);
+        assert!(matches!(
+            ScopaGame::restore(four_cards),
+            Err(ScopaError::Card(_))
+        ));
+        // 29 cards can't be dealt to two players
+        let mut uneven_deck = snapshot;
+        let card = uneven_deck.deck.pop().unwrap();
+        uneven_deck.table.push(card);
+        assert!(matches!(
+            ScopaGame::restore(uneven_deck),
+            Err(ScopaError::Logic(_))
+        ));
+    }
+
+    #[test]
+    fn same_seed_deals_same_cards() {
+        let deal = |seed| {
+            let mut game = game_with_players(RuleSet::default()).with_seed(seed);
+            format!("{:?}", game.start().unwrap())
+        };
+        assert_eq!(deal(42), deal(42));
+        assert_ne!(deal(42), deal(43));
+    }
+
+    #[test]
+    fn scenario_errors() {
+        use CardValue::*;
+        use Suite::*;
+        let two_players = || {
+            ScenarioBuilder::new(RuleSet::default())
+                .player(1, "first")
+                .player(2, "second")
+        };
+        let card = Card::new(Coins, Seven);
+        assert!(two_players()
+            .hand(1, &[card])
+            .table(&[card])
+            .build()
+            .is_err());
+        assert!(two_players().hand(3, &[card]).build().is_err());
+        assert!(two_players().active_player(3).build().is_err());
+        // 39 cards left in the deck can't be dealt evenly
+        assert!(two_players().table(&[card]).build().is_err());
+        // Explicit deck order has to contain the rest of the cards
+        assert!(two_players().deck(vec![card]).build().is_err());
+        assert!(two_players().deck(rest_of_deck(&[])).build().is_err());
+        assert!(ScenarioBuilder::new(RuleSet::default())
+            .player(1, "alone")
+            .build()
+            .is_err());
+        // Same player can't take two seats
+        let seated_twice = ScenarioBuilder::new(RuleSet::default())
+            .player(1, "first")
+            .player(1, "second")
+            .build();
+        assert!(matches!(seated_twice, Err(ScopaError::Player(_))));
+        let nobody = RuleSet {
+            players: 0,
+            ..RuleSet::default()
+        };
+        assert!(ScenarioBuilder::new(nobody).build().is_err());
+    }
+
+    #[test]
+    fn protocol_encoding() {
+        use protocol::*;
+        let hello = ClientMessage::Hello {
+            version: 1,
+            name: "a".into(),
+        };
+        // Encoding is part of the protocol and must not change silently
+        assert_eq!(encode(&hello).unwrap(), vec![0, 1, 1, b'a']);
+        assert_eq!(
+            decode::<ClientMessage>(&encode(&hello).unwrap()).unwrap(),
+            hello
+        );
+
+        let mut trailing = encode(&hello).unwrap();
+        trailing.push(0);
+        assert!(decode::<ClientMessage>(&trailing).is_err());
+        // Length prefix claiming a huge name
+        assert!(decode::<ClientMessage>(&[0, 1, 0xfc, 0xff, 0xff, 0xff, 0x7f]).is_err());
+        // Only incoming messages are limited
+        let long = ClientMessage::Chat {
+            text: "a".repeat(MAX_MESSAGE_SIZE as usize),
+        };
+        assert!(encode(&long).unwrap().len() > MAX_MESSAGE_SIZE as usize);
+
+        let mut game = game_with_players(RuleSet::default());
+        for event in game.start().unwrap() {
+            let message = ServerMessage::from(&event);
+            assert_eq!(
+                decode::<ServerMessage>(&encode(&message).unwrap()).unwrap(),
+                message
+            );
+        }
+    }
+
     #[test]
     fn table_contains_same_value() {
         let mut table = Table::default();
@@ -469,4 +1514,280 @@ mod tests {
 
         assert_eq!(table.contains_same_value(&card), None);
     }
+
+    #[test]
+    fn redeal_rules() {
+        use CardValue::*;
+        use Suite::*;
+        let three_kings = [
+            Card::new(Coins, Re),
+            Card::new(Cups, Re),
+            Card::new(Swords, Re),
+            Card::new(Clubs, Two),
+        ];
+        let three_twos = [
+            Card::new(Coins, Two),
+            Card::new(Cups, Two),
+            Card::new(Swords, Two),
+            Card::new(Clubs, Re),
+        ];
+        let two_kings = [
+            Card::new(Coins, Re),
+            Card::new(Cups, Re),
+            Card::new(Swords, Two),
+            Card::new(Clubs, Two),
+        ];
+        assert!(!RedealRule::Off.requires_redeal(&three_kings));
+        assert!(RedealRule::Kings.requires_redeal(&three_kings));
+        assert!(!RedealRule::Kings.requires_redeal(&three_twos));
+        assert!(RedealRule::AnyValue.requires_redeal(&three_kings));
+        assert!(RedealRule::AnyValue.requires_redeal(&three_twos));
+        assert!(!RedealRule::AnyValue.requires_redeal(&two_kings));
+
+        let game = ScopaGame::default();
+        assert!(game
+            .validate(&GameEvent::PlaceTable { table: three_kings })
+            .is_err());
+        assert!(game
+            .validate(&GameEvent::PlaceTable { table: two_kings })
+            .is_ok());
+    }
+
+    fn game_with_players(rules: RuleSet) -> ScopaGame {
+        let mut game = ScopaGame::new(rules);
+        for id in 1..=game.rules.players as PlayerId {
+            game.add_player(id, &format!("player {}", id)).unwrap();
+        }
+        game
+    }
+
+    // Takes the first card with the same value, the first combination of cards adding up to the
+    // card's value or puts the card on the table otherwise
+    fn first_legal_move(game: &ScopaGame) -> GameEvent {
+        let id = game.active_player;
+        let table: Vec<Card> = game.table.iter().copied().collect();
+        for with in game.players[&id].hand.iter().copied() {
+            let take = match game.table.contains_same_value(&with) {
+                Some(same) => Some(vec![*same]),
+                None => (1..1u32 << table.len())
+                    .map(|mask| {
+                        table
+                            .iter()
+                            .enumerate()
+                            .filter(|(i, _)| mask & (1 << i) != 0)
+                            .map(|(_, c)| *c)
+                            .collect::<Vec<Card>>()
+                    })
+                    .find(|take| take.iter().map(|c| c.value()).sum::<u8>() == with.value()),
+            };
+            let event = match take {
+                Some(take) => GameEvent::TakeCards { id, take, with },
+                None => GameEvent::PutCard { id, card: with },
+            };
+            if game.validate(&event).is_ok() {
+                return event;
+            }
+        }
+        panic!("Player {} has no legal moves", id);
+    }
+
+    fn play_until_won(game: &mut ScopaGame) -> Vec<GameEvent> {
+        let mut events = game.start().unwrap();
+        while !matches!(events.last(), Some(GameEvent::PlayerWon { .. })) {
+            let event = first_legal_move(game);
+            events.extend(game.play(event).unwrap());
+        }
+        events
+    }
+
+    #[test]
+    fn revealed_seeds_match_the_deals() {
+        use fairness::{DeckAudit, FairnessError};
+        let rules = RuleSet {
+            redeal: RedealRule::AnyValue,
+            ..RuleSet::default()
+        };
+        let mut game = game_with_players(rules.clone()).with_seed(7);
+        let seats = game.seats().to_vec();
+        let mut audit = DeckAudit::default();
+        let mut verified = 0;
+        for event in play_until_won(&mut game) {
+            match event {
+                GameEvent::StartRound { active_player } => audit.start(active_player),
+                GameEvent::CommitDeck { commitment } => audit.committed(commitment),
+                GameEvent::TableRedealt { table } | GameEvent::PlaceTable { table } => {
+                    audit.table(table)
+                }
+                GameEvent::DealHand { id: 1, hand } => audit.hand(hand),
+                GameEvent::PutCard { id, card } | GameEvent::TakeCards { id, with: card, .. } => {
+                    audit.played(id, card)
+                }
+                GameEvent::RevealDeck { seed } => {
+                    assert_eq!(audit.verify(seed, &seats, 1, rules.redeal), Ok(()));
+                    assert_eq!(
+                        audit.verify(seed + 1, &seats, 1, rules.redeal),
+                        Err(FairnessError::WrongSeed)
+                    );
+                    let mut swapped = audit.clone();
+                    let card = Card::new(Suite::Coins, CardValue::Seven);
+                    swapped.played(1, card);
+                    swapped.played(2, card);
+                    assert!(matches!(
+                        swapped.verify(seed, &seats, 1, rules.redeal),
+                        Err(FairnessError::CardNotDealt { .. })
+                    ));
+                    verified += 1;
+                }
+                _ => {}
+            }
+        }
+        assert!(verified > 0);
+        assert_eq!(
+            DeckAudit::default().verify(0, &seats, 1, rules.redeal),
+            Err(FairnessError::NotCommitted)
+        );
+    }
+
+    #[test]
+    fn new_round_places_valid_table() {
+        let mut game = game_with_players(RuleSet {
+            redeal: RedealRule::AnyValue,
+            ..RuleSet::default()
+        });
+        for _ in 0..100 {
+            let events = game.new_round().unwrap();
+            let table = events
+                .iter()
+                .find_map(|e| match e {
+                    GameEvent::PlaceTable { table } => Some(table),
+                    _ => None,
+                })
+                .expect("Round should start with the table placed");
+            assert!(!RedealRule::AnyValue.requires_redeal(table));
+            assert_eq!(game.table.len(), 4);
+            assert_eq!(game.deck.len(), 30);
+        }
+    }
+
+    #[test]
+    fn game_is_full() {
+        let game = game_with_players(RuleSet::default());
+        assert!(game.is_full());
+        assert!(game
+            .validate(&GameEvent::PlayerConnected {
+                id: 42,
+                name: "late".into()
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn three_player_round() {
+        let mut game = game_with_players(RuleSet {
+            players: 3,
+            ..RuleSet::default()
+        });
+        let events = game.start().unwrap();
+        let dealt = events
+            .iter()
+            .filter(|e| matches!(e, GameEvent::DealHand { .. }))
+            .count();
+        assert_eq!(dealt, 3);
+        assert_eq!(game.deck.len(), 27);
+        // Players move in the order they are seated
+        let first = game.active_player;
+        let event = first_legal_move(&game);
+        game.play(event).unwrap();
+        assert_eq!(game.active_player, game.seats[1]);
+        assert_ne!(game.active_player, first);
+    }
+
+    #[test]
+    fn play_full_games() {
+        for players in SUPPORTED_PLAYERS {
+            let mut game = game_with_players(RuleSet {
+                players,
+                ..RuleSet::default()
+            });
+            let events = play_until_won(&mut game);
+            for event in &events {
+                if let GameEvent::EndRound { points } = event {
+                    assert_eq!(points.len(), players);
+                    let sides = game.sides().len();
+                    let takes: u8 = points[..sides].iter().map(|p| p.details.takes).sum();
+                    assert_eq!(takes, 40);
+                }
+            }
+            let Some(GameEvent::PlayerWon { id }) = events.last() else {
+                unreachable!()
+            };
+            let winner = game.players[id].points;
+            assert!(winner >= game.rules.target_score);
+            assert!(game.players.iter().all(|(other, p)| other == id
+                || Some(*other) == game.partner(*id)
+                || p.points < winner));
+        }
+    }
+
+    #[test]
+    fn three_way_ties_give_no_points() {
+        use CardValue::*;
+        use Suite::*;
+        let mut game = game_with_players(RuleSet {
+            players: 3,
+            ..RuleSet::default()
+        });
+        // Same number of cards, coins and primes for everybody, settebello goes to player 1
+        let piles = [
+            [Card::new(Coins, Seven), Card::new(Cups, Six)],
+            [Card::new(Coins, Six), Card::new(Cups, Seven)],
+            [Card::new(Coins, Six), Card::new(Swords, Seven)],
+        ];
+        for (id, pile) in (1..=3).zip(piles) {
+            game.players.get_mut(&id).unwrap().take_cards(pile.into());
+        }
+        let points = game.round_points();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].points, 1);
+        assert_eq!(points[1].points, 0);
+        assert_eq!(points[2].points, 0);
+    }
+
+    #[test]
+    fn teams_score_together() {
+        use CardValue::*;
+        use Suite::*;
+        let mut game = game_with_players(RuleSet {
+            players: TEAM_PLAYERS,
+            ..RuleSet::default()
+        });
+        assert_eq!(game.partner(1), Some(3));
+        assert_eq!(game.partner(4), Some(2));
+        // Partners 2 and 4 have more cards, coins and primes between them, player 1 has the
+        // settebello and player 3 a scopa
+        let piles = [
+            vec![Card::new(Coins, Seven)],
+            vec![Card::new(Coins, One), Card::new(Cups, One)],
+            vec![Card::new(Swords, Two)],
+            vec![Card::new(Coins, Two), Card::new(Clubs, Two)],
+        ];
+        for (id, pile) in (1..=4).zip(piles) {
+            game.players.get_mut(&id).unwrap().take_cards(pile);
+        }
+        game.players.get_mut(&3).unwrap().scopas = 1;
+        let points = game.round_points();
+        let points: Vec<u8> = points.iter().map(|p| p.points).collect();
+        assert_eq!(points, vec![2, 3, 2, 3]);
+        assert_eq!(game.round_points()[1].details.takes, 4);
+
+        // Tied teams play on, the first player of the leading team names the winner
+        for id in 1..=4 {
+            game.players.get_mut(&id).unwrap().points = DEFAULT_TARGET_SCORE;
+        }
+        assert_eq!(game.winner(), None);
+        for id in [2, 4] {
+            game.players.get_mut(&id).unwrap().points += 1;
+        }
+        assert_eq!(game.winner(), Some(2));
+    }
 }
@@ -0,0 +1,525 @@
+use crate::card::Card;
+use crate::fairness::Commitment;
+use crate::rules::RuleSet;
+use crate::{GameEvent, PlayerId, Points};
+
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Netcode protocol id shared by the client and the server
+pub const PROTOCOL_ID: u64 = 0x5C0A;
+// Bumped with every release that changes the messages below incompatibly
+pub const PROTOCOL_VERSION: u16 = 1;
+pub const MAX_MESSAGE_SIZE: u64 = 4096;
+pub const MAX_NAME_LENGTH: usize = 32;
+pub const ROOM_CODE_LENGTH: usize = 4;
+pub const LEADERBOARD_SIZE: usize = 10;
+// In characters
+pub const MAX_CHAT_LENGTH: usize = 200;
+// Connect tokens are handed out over HTTP on the TCP port with the number of the game port
+pub const TOKEN_PATH: &str = "/token";
+// Servers on the local network broadcast their announcements to this UDP port
+pub const DISCOVERY_PORT: u16 = 6970;
+// Clients forget servers they haven't heard from for a few of these
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2);
+// Biggest announcement a server sends, with room to spare
+pub const MAX_ANNOUNCEMENT_SIZE: usize = 256;
+
+// Lets a player who lost the connection take their seat back
+pub type SessionToken = u64;
+
+// How well a bot plays
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    // Any move will do
+    Easy,
+    // Takes as much as it can
+    #[default]
+    Normal,
+    // Weighs the cards it takes and the chances it leaves
+    Hard,
+}
+
+impl std::fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difficulty::Easy => write!(f, "Easy"),
+            Difficulty::Normal => write!(f, "Normal"),
+            Difficulty::Hard => write!(f, "Hard"),
+        }
+    }
+}
+
+// Preset reactions, shown for a moment over the area of whoever sent them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Emote {
+    Scopa,
+    WellPlayed,
+    GoodLuck,
+    Thanks,
+    Oops,
+}
+
+impl Emote {
+    pub const ALL: [Emote; 5] = [
+        Emote::Scopa,
+        Emote::WellPlayed,
+        Emote::GoodLuck,
+        Emote::Thanks,
+        Emote::Oops,
+    ];
+}
+
+impl std::fmt::Display for Emote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Emote::Scopa => write!(f, "Scopa!"),
+            Emote::WellPlayed => write!(f, "Well played"),
+            Emote::GoodLuck => write!(f, "Good luck"),
+            Emote::Thanks => write!(f, "Thanks"),
+            Emote::Oops => write!(f, "Oops"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClientMessage {
+    // Must be the first message after connecting
+    Hello { version: u16, name: String },
+    // Replaces hello when coming back after a dropped connection
+    Resume { version: u16, token: SessionToken },
+    // Asks for the rooms which still have free seats
+    ListRooms,
+    // Creates a room and takes a seat in it. Server picks its default rules if none are given.
+    CreateRoom { rules: Option<RuleSet> },
+    JoinRoom { code: String },
+    // Watches the game in the room without taking a seat
+    Spectate { code: String },
+    LeaveRoom,
+    // Seats a bot in the room, only the player who has been seated the longest may do that
+    AddBot { difficulty: Difficulty },
+    // Asks for the best rated players and the player's own rating
+    Leaderboard,
+    // Waits for a game against players with a similar rating under the default rules
+    FindMatch,
+    CancelMatch,
+    // Takes the bot offered while waiting for a match
+    PlayBot,
+    // Goes to everybody in the room. Spectators only talk among themselves, so they can't help
+    // the players.
+    Chat { text: String },
+    Emote { emote: Emote },
+    // Stops or resumes chat and emotes of the given player reaching this one
+    Mute { id: PlayerId, muted: bool },
+    PutCard { card: Card },
+    TakeCards { take: Vec<Card>, with: Card },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectReason {
+    VersionMismatch { server: u16 },
+    InvalidName,
+    // Seat was given away or the game is over
+    SessionExpired,
+    // Server admin sent the player away
+    Kicked,
+    Banned,
+    // Client didn't say hello soon enough after connecting
+    HandshakeTimeout,
+    // Client kept sending malformed, misplaced or too many messages
+    Misbehaving,
+    // Hello came with a name other than the one the connect token was issued for
+    NameMismatch,
+    // Another player, maybe one who is away or a bot, already has the id of the connection
+    IdInUse,
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use RejectReason::*;
+        match self {
+            VersionMismatch { server } => write!(
+                f,
+                "Server speaks protocol version {}, but this client speaks version {}",
+                server, PROTOCOL_VERSION
+            ),
+            InvalidName => write!(
+                f,
+                "Player name should have from 1 to {} characters",
+                MAX_NAME_LENGTH
+            ),
+            SessionExpired => write!(
+                f,
+                "Your seat is no longer held, the game went on without you"
+            ),
+            Kicked => write!(f, "You were kicked from the server"),
+            Banned => write!(f, "You are banned from this server"),
+            HandshakeTimeout => write!(f, "The server didn't hear from your client in time"),
+            Misbehaving => write!(f, "Your client sent too many invalid messages"),
+            NameMismatch => write!(f, "You signed in to the server under another name"),
+            IdInUse => write!(f, "Your client id is taken, please connect again"),
+        }
+    }
+}
+
+// Body of the request for a connect token, encoded like the messages. The first request for a name
+// claims it, later ones have to come with the same secret.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenRequest {
+    pub version: u16,
+    pub name: String,
+    pub secret: String,
+}
+
+// Broadcast by servers every few seconds, encoded like the messages. The server is found at the
+// address the announcement came from, on the port given in it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerAnnouncement {
+    pub version: u16,
+    pub name: String,
+    pub port: u16,
+    pub rooms: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoomError {
+    NotFound,
+    Full,
+    InvalidRules,
+    AlreadyInRoom,
+    NotInRoom,
+    TooManyRooms,
+    NotOwner,
+    InQueue,
+    NotInQueue,
+}
+
+impl std::fmt::Display for RoomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use RoomError::*;
+        match self {
+            NotFound => write!(f, "There is no room with this code"),
+            Full => write!(f, "The room is full"),
+            InvalidRules => write!(f, "These rules can't be played"),
+            AlreadyInRoom => write!(f, "Leave your current room first"),
+            NotInRoom => write!(f, "You are not in a room"),
+            TooManyRooms => write!(f, "The server can't open more rooms right now"),
+            NotOwner => write!(f, "Only the host of the room can do that"),
+            InQueue => write!(f, "Stop looking for a match first"),
+            NotInQueue => write!(f, "You are not looking for a match"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChatError {
+    NotInRoom,
+    Empty,
+    TooLong,
+    TooFast,
+}
+
+impl std::fmt::Display for ChatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ChatError::*;
+        match self {
+            NotInRoom => write!(f, "Join a room to chat"),
+            Empty => write!(f, "Message is empty"),
+            TooLong => write!(f, "Messages can have up to {} characters", MAX_CHAT_LENGTH),
+            TooFast => write!(f, "You are sending messages too fast, slow down"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub code: String,
+    pub players: Vec<String>,
+    pub rules: RuleSet,
+    pub spectators: usize,
+}
+
+// What everybody at the table may know about a player
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeatState {
+    pub id: PlayerId,
+    pub name: String,
+    pub points: u8,
+    pub connected: bool,
+}
+
+// Public part of what a player holds, shown to spectators
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PileState {
+    pub id: PlayerId,
+    pub hand: u8,
+    pub taken: Vec<Card>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoundScore {
+    pub id: PlayerId,
+    pub points: u8,
+    pub cards: u8,
+    pub coins: u8,
+    pub settebello: bool,
+    pub primes: u8,
+    pub scopas: u8,
+}
+
+impl From<&Points> for RoundScore {
+    fn from(value: &Points) -> Self {
+        Self {
+            id: value.id,
+            points: value.points,
+            cards: value.details.takes,
+            coins: value.details.count_of_coins,
+            settebello: value.details.seven_of_coins,
+            primes: value.details.primes,
+            scopas: value.details.scopas,
+        }
+    }
+}
+
+// Matches against bots are not rated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerRating {
+    // Starting from 1
+    pub rank: u32,
+    pub name: String,
+    pub rating: i32,
+    pub matches: u32,
+    pub wins: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServerMessage {
+    Welcome {
+        version: u16,
+        id: PlayerId,
+    },
+    Rejected {
+        reason: RejectReason,
+    },
+    MoveRejected {
+        reason: String,
+    },
+    RoomList {
+        rooms: Vec<RoomInfo>,
+    },
+    RoomJoined {
+        code: String,
+        rules: RuleSet,
+    },
+    RoomLeft,
+    RoomRejected {
+        reason: RoomError,
+    },
+    // Sent on taking a seat, keep it to resume after a dropped connection
+    Session {
+        token: SessionToken,
+    },
+    // Game state as seen by the resuming player, other hands are left out
+    Resumed {
+        code: String,
+        rules: RuleSet,
+        seats: Vec<SeatState>,
+        hand: Vec<Card>,
+        table: Vec<Card>,
+        active_player: PlayerId,
+    },
+    // Player's connection dropped, the seat is held for a while
+    PlayerAway {
+        id: PlayerId,
+    },
+    // Public state of the room for a new spectator, active player is None until the game starts
+    Spectating {
+        code: String,
+        rules: RuleSet,
+        seats: Vec<SeatState>,
+        piles: Vec<PileState>,
+        table: Vec<Card>,
+        active_player: Option<PlayerId>,
+    },
+    // Spectators are told who got a new hand instead of the hand itself
+    HandDealtTo {
+        id: PlayerId,
+    },
+    PlayerBack {
+        id: PlayerId,
+    },
+    // Clock of the player to move, sent whenever a turn starts. Turn time is spent first, then
+    // the bank which lasts for the whole game.
+    TurnTimer {
+        id: PlayerId,
+        turn: Duration,
+        bank: Duration,
+    },
+    // Player left for good and a bot plays in their seat from now on
+    BotTookOver {
+        id: PlayerId,
+    },
+    // Player ran out of time, the server moves for them
+    TimedOut {
+        id: PlayerId,
+    },
+    // Player ran out of time too many times and lost their seat
+    Forfeited {
+        id: PlayerId,
+    },
+    PlayerJoined {
+        id: PlayerId,
+        name: String,
+    },
+    PlayerLeft {
+        id: PlayerId,
+        name: String,
+    },
+    RoundStarted {
+        active_player: PlayerId,
+    },
+    // Hash of the seed the round is shuffled with, the seed itself comes once the round is over
+    DeckCommitted {
+        commitment: Commitment,
+    },
+    DeckRevealed {
+        seed: u64,
+    },
+    TablePlaced {
+        table: [Card; 4],
+    },
+    TableRedealt {
+        table: [Card; 4],
+    },
+    // Only sent to the owner of the hand
+    HandDealt {
+        hand: [Card; 3],
+    },
+    CardPut {
+        id: PlayerId,
+        card: Card,
+    },
+    CardsTaken {
+        id: PlayerId,
+        take: Vec<Card>,
+        with: Card,
+    },
+    Scopa {
+        id: PlayerId,
+    },
+    RoundEnded {
+        scores: Vec<RoundScore>,
+    },
+    GameWon {
+        id: PlayerId,
+    },
+    // Player is waiting for a match, together with the given number of players
+    Queued {
+        players: u32,
+    },
+    QueueLeft,
+    // Nobody to play with turned up for a while, PlayBot starts a game against a bot
+    BotOffered,
+    // Best rated players and the player's own rating, None until they finish a rated match
+    Leaderboard {
+        top: Vec<PlayerRating>,
+        me: Option<PlayerRating>,
+    },
+    Chat {
+        id: PlayerId,
+        name: String,
+        text: String,
+    },
+    Emote {
+        id: PlayerId,
+        emote: Emote,
+    },
+    ChatRejected {
+        reason: ChatError,
+    },
+    // Message from whoever runs the server
+    Announcement {
+        text: String,
+    },
+    // Server admin ended the game in the room before anybody won
+    RoomClosed,
+    // Server stops after the given time, games are picked up again once it is back if it saves
+    // them
+    ShuttingDown {
+        after: Duration,
+    },
+}
+
+impl From<&GameEvent> for ServerMessage {
+    fn from(value: &GameEvent) -> Self {
+        use GameEvent::*;
+        match value.clone() {
+            PlayerConnected { id, name } => ServerMessage::PlayerJoined { id, name },
+            PlayerDisconnected { id, name } => ServerMessage::PlayerLeft { id, name },
+            StartRound { active_player } => ServerMessage::RoundStarted { active_player },
+            CommitDeck { commitment } => ServerMessage::DeckCommitted { commitment },
+            RevealDeck { seed } => ServerMessage::DeckRevealed { seed },
+            PlaceTable { table } => ServerMessage::TablePlaced { table },
+            TableRedealt { table } => ServerMessage::TableRedealt { table },
+            DealHand { hand, .. } => ServerMessage::HandDealt { hand },
+            PutCard { id, card } => ServerMessage::CardPut { id, card },
+            TakeCards { id, take, with } => ServerMessage::CardsTaken { id, take, with },
+            Scopa { id } => ServerMessage::Scopa { id },
+            EndRound { points } => ServerMessage::RoundEnded {
+                scores: points.iter().map(RoundScore::from).collect(),
+            },
+            PlayerWon { id } => ServerMessage::GameWon { id },
+        }
+    }
+}
+
+impl ClientMessage {
+    // Move of the given player, or None for the messages which are not moves
+    pub fn to_move(&self, id: PlayerId) -> Option<GameEvent> {
+        match self.clone() {
+            ClientMessage::PutCard { card } => Some(GameEvent::PutCard { id, card }),
+            ClientMessage::TakeCards { take, with } => {
+                Some(GameEvent::TakeCards { id, take, with })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ProtocolError(bincode::Error);
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Malformed message: {}", self.0)
+    }
+}
+
+impl std::error::Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+// Encoding must stay the same between versions, so options are spelled out instead of relying on
+// bincode defaults
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_little_endian()
+        .with_varint_encoding()
+        .reject_trailing_bytes()
+}
+
+pub fn encode<T: Serialize>(message: &T) -> Result<Vec<u8>, ProtocolError> {
+    options().serialize(message).map_err(ProtocolError)
+}
+
+// Only what comes from the other side is limited, so a forged length can't make us allocate
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ProtocolError> {
+    options()
+        .with_limit(MAX_MESSAGE_SIZE)
+        .deserialize(bytes)
+        .map_err(ProtocolError)
+}
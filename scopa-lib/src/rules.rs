@@ -0,0 +1,115 @@
+use crate::card::{Card, CardValue};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+// Two or three players play alone, four play in two teams
+pub const SUPPORTED_PLAYERS: RangeInclusive<usize> = 2..=4;
+// Partners sit opposite each other, so the turn goes back and forth between the teams. Hands are
+// dealt three cards at a time, Scopone's deal of the whole deck isn't supported.
+pub const TEAM_PLAYERS: usize = 4;
+pub const DEFAULT_TARGET_SCORE: u8 = 11;
+// Far enough from u8::MAX that the points of a last round can't overflow the score
+pub const MAX_TARGET_SCORE: u8 = 100;
+// Primes of the four best cards still add up to less than u8::MAX
+pub const MAX_PRIME: u8 = 63;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RedealRule {
+    // Initial table is always accepted
+    Off,
+    // Deal is void if three or four Kings land on the initial table
+    #[default]
+    Kings,
+    // Deal is void if three or four cards of any one value land on the initial table
+    AnyValue,
+}
+
+impl RedealRule {
+    pub fn requires_redeal(&self, table: &[Card; 4]) -> bool {
+        let count_of = |value: CardValue| table.iter().filter(|c| c.value == value).count();
+        match self {
+            RedealRule::Off => false,
+            RedealRule::Kings => count_of(CardValue::Re) >= 3,
+            RedealRule::AnyValue => table.iter().any(|card| count_of(card.value) >= 3),
+        }
+    }
+}
+
+// Primes of card values from One to Re
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrimeTable([u8; 10]);
+
+impl PrimeTable {
+    pub const TRADITIONAL: PrimeTable = PrimeTable([16, 12, 13, 14, 15, 18, 21, 10, 10, 10]);
+
+    pub fn new(primes: [u8; 10]) -> Self {
+        Self(primes)
+    }
+
+    pub fn prime(&self, value: CardValue) -> u8 {
+        self.0[value.value() as usize - 1]
+    }
+
+    pub fn primes(&self) -> [u8; 10] {
+        self.0
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.0.iter().all(|prime| *prime <= MAX_PRIME)
+    }
+}
+
+impl Default for PrimeTable {
+    fn default() -> Self {
+        Self::TRADITIONAL
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PrimieraRule {
+    // Highest sum of primes of the best card in each suit wins
+    #[default]
+    Sum,
+    // Most sevens win, ties are broken by sixes and then by aces
+    CountSevens,
+}
+
+// Rules left out when deserializing are the default ones
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleSet {
+    // Two or three players each playing alone, or four in teams of two
+    pub players: usize,
+    pub target_score: u8,
+    pub redeal: RedealRule,
+    pub prime_table: PrimeTable,
+    pub primiera: PrimieraRule,
+    // Player who didn't take a card of every suit can't win primiera
+    pub primiera_needs_all_suites: bool,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self {
+            players: 2,
+            target_score: DEFAULT_TARGET_SCORE,
+            redeal: RedealRule::default(),
+            prime_table: PrimeTable::default(),
+            primiera: PrimieraRule::default(),
+            primiera_needs_all_suites: false,
+        }
+    }
+}
+
+impl RuleSet {
+    pub fn teams(&self) -> bool {
+        self.players == TEAM_PLAYERS
+    }
+
+    // Rules coming from the outside, e.g. from a client creating a room, may not make sense
+    pub fn is_playable(&self) -> bool {
+        SUPPORTED_PLAYERS.contains(&self.players)
+            && (1..=MAX_TARGET_SCORE).contains(&self.target_score)
+            && self.prime_table.is_valid()
+    }
+}
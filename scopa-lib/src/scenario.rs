@@ -0,0 +1,155 @@
+use crate::card::*;
+use crate::rules::*;
+use crate::{PlayerId, ScopaError, ScopaGame};
+use std::collections::HashSet;
+
+// Sets up a game in the middle of a round: hands, table, taken piles, scores and the player to
+// move. Cards which are not placed anywhere stay in the deck.
+#[derive(Debug)]
+pub struct ScenarioBuilder {
+    game: ScopaGame,
+    table: Vec<Card>,
+    deck: Option<Vec<Card>>,
+    active_player: Option<PlayerId>,
+    unknown_players: Vec<PlayerId>,
+    // First player who couldn't be seated, reported by build
+    seating_error: Option<ScopaError>,
+}
+
+impl ScenarioBuilder {
+    pub fn new(rules: RuleSet) -> Self {
+        Self {
+            game: ScopaGame::new(rules),
+            table: Vec::with_capacity(10),
+            deck: None,
+            active_player: None,
+            unknown_players: Vec::new(),
+            seating_error: None,
+        }
+    }
+
+    pub fn player(mut self, id: PlayerId, name: &str) -> Self {
+        if let Err(e) = self.game.add_player(id, name) {
+            self.seating_error.get_or_insert(e);
+        }
+        self
+    }
+
+    pub fn hand(self, id: PlayerId, hand: &[Card]) -> Self {
+        self.with_player(id, |player| player.hand = hand.into())
+    }
+
+    pub fn taken(self, id: PlayerId, taken: &[Card]) -> Self {
+        self.with_player(id, |player| player.take_cards(taken.into()))
+    }
+
+    pub fn points(self, id: PlayerId, points: u8) -> Self {
+        self.with_player(id, |player| player.points = points)
+    }
+
+    pub fn scopas(self, id: PlayerId, scopas: u8) -> Self {
+        self.with_player(id, |player| player.scopas = scopas)
+    }
+
+    pub fn table(mut self, table: &[Card]) -> Self {
+        self.table = table.into();
+        self
+    }
+
+    // Order in which the rest of the deck is dealt. Remaining cards are shuffled if it's not set.
+    pub fn deck(mut self, order: Vec<Card>) -> Self {
+        self.deck = Some(order);
+        self
+    }
+
+    pub fn active_player(mut self, id: PlayerId) -> Self {
+        self.active_player = Some(id);
+        self
+    }
+
+    pub fn took_last(mut self, id: PlayerId) -> Self {
+        self.game.took_last = id;
+        self
+    }
+
+    fn with_player(mut self, id: PlayerId, f: impl FnOnce(&mut crate::Player)) -> Self {
+        match self.game.players.get_mut(&id) {
+            Some(player) => f(player),
+            None => self.unknown_players.push(id),
+        }
+        self
+    }
+
+    pub fn build(mut self) -> Result<ScopaGame, ScopaError> {
+        if let Some(e) = self.seating_error {
+            return Err(e);
+        }
+        if let Some(id) = self.unknown_players.first() {
+            return Err(ScopaError::Player(format!("Unknown player {}", id)));
+        }
+        if !SUPPORTED_PLAYERS.contains(&self.game.rules.players) {
+            return Err(ScopaError::Logic(format!(
+                "Scopa can't be played by {} players",
+                self.game.rules.players
+            )));
+        }
+        let players = self.game.seats.len();
+        if players != self.game.rules.players {
+            return Err(ScopaError::Logic(format!(
+                "Rules are for {} players, but there are {}",
+                self.game.rules.players, players
+            )));
+        }
+
+        // Every card of the deck must be in exactly one place
+        let mut placed = HashSet::with_capacity(40);
+        let hands = self.game.players.values().flat_map(|p| p.hand.iter());
+        let taken = self
+            .game
+            .players
+            .values()
+            .flat_map(|p| p.taken.suites().into_iter().flatten());
+        let deck = self.deck.iter().flatten();
+        for card in hands.chain(taken).chain(self.table.iter()).chain(deck) {
+            if !placed.insert(*card) {
+                return Err(ScopaError::Card(format!("{} is used twice", card)));
+            }
+        }
+        let deck = match self.deck {
+            Some(order) => order,
+            None => {
+                let mut rest = Deck::default();
+                rest.shuffle();
+                let rest: Vec<Card> = rest
+                    .cards()
+                    .iter()
+                    .filter(|card| !placed.contains(card))
+                    .copied()
+                    .collect();
+                placed.extend(rest.iter().copied());
+                rest
+            }
+        };
+        if placed.len() != 40 {
+            return Err(ScopaError::Card(
+                "All 40 cards must be in the deck, on the table or with players".into(),
+            ));
+        }
+        self.game.deck = Deck::from_order(deck)?;
+        self.game.check_deal()?;
+        for card in self.table {
+            self.game.table.put_card(card);
+        }
+
+        let active_player = self.active_player.unwrap_or(self.game.seats[0]);
+        let Some(seat) = self.game.seats.iter().position(|id| *id == active_player) else {
+            return Err(ScopaError::Player(format!(
+                "Unknown player {}",
+                active_player
+            )));
+        };
+        self.game.active_player = active_player;
+        self.game.first_seat = seat;
+        Ok(self.game)
+    }
+}
@@ -0,0 +1,144 @@
+use crate::card::*;
+use crate::rules::RuleSet;
+use crate::{Player, PlayerId, ScopaError, ScopaGame};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerSnapshot {
+    pub id: PlayerId,
+    pub name: String,
+    pub points: u8,
+    pub scopas: u8,
+    pub hand: Vec<Card>,
+    pub taken: Vec<Card>,
+}
+
+// Complete state of a game, enough to carry on with it exactly where it was left, e.g. after a
+// server restart
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameSnapshot {
+    pub rules: RuleSet,
+    // In seat order
+    pub players: Vec<PlayerSnapshot>,
+    // Last card is dealt first
+    pub deck: Vec<Card>,
+    pub table: Vec<Card>,
+    pub active_player: PlayerId,
+    pub took_last: PlayerId,
+    pub first_seat: usize,
+    pub seed: u64,
+    pub round: u64,
+}
+
+impl ScopaGame {
+    pub fn snapshot(&self) -> GameSnapshot {
+        let players = self
+            .seats
+            .iter()
+            .map(|id| {
+                let player = &self.players[id];
+                PlayerSnapshot {
+                    id: *id,
+                    name: player.name.clone(),
+                    points: player.points,
+                    scopas: player.scopas,
+                    hand: player.hand.clone(),
+                    taken: player
+                        .taken
+                        .suites()
+                        .into_iter()
+                        .flatten()
+                        .copied()
+                        .collect(),
+                }
+            })
+            .collect();
+        let mut table: Vec<Card> = self.table.iter().copied().collect();
+        // Table is a set, keep the snapshot stable
+        table.sort_by_key(|card| (card.value(), card.suite as u8));
+        GameSnapshot {
+            rules: self.rules.clone(),
+            players,
+            deck: self.deck.cards().to_vec(),
+            table,
+            active_player: self.active_player,
+            took_last: self.took_last,
+            first_seat: self.first_seat,
+            seed: self.seed,
+            round: self.round,
+        }
+    }
+
+    // Snapshots are read from disk, so they are checked as thoroughly as a scenario
+    pub fn restore(snapshot: GameSnapshot) -> Result<Self, ScopaError> {
+        if !snapshot.rules.is_playable() {
+            return Err(ScopaError::Logic("Rules can't be played".into()));
+        }
+        if snapshot.players.len() > snapshot.rules.players {
+            return Err(ScopaError::Logic(format!(
+                "Rules are for {} players, but there are {}",
+                snapshot.rules.players,
+                snapshot.players.len()
+            )));
+        }
+        let mut placed = HashSet::with_capacity(40);
+        let cards = snapshot
+            .players
+            .iter()
+            .flat_map(|p| p.hand.iter().chain(p.taken.iter()))
+            .chain(snapshot.deck.iter())
+            .chain(snapshot.table.iter());
+        for card in cards {
+            if !placed.insert(*card) {
+                return Err(ScopaError::Card(format!("{} is used twice", card)));
+            }
+        }
+        if placed.len() != 40 {
+            return Err(ScopaError::Card(
+                "All 40 cards must be in the deck, on the table or with players".into(),
+            ));
+        }
+        let seats: Vec<PlayerId> = snapshot.players.iter().map(|p| p.id).collect();
+        // Nobody is to move before the first deal
+        let dealt = snapshot.deck.len() < 40;
+        if dealt && !seats.contains(&snapshot.active_player) {
+            return Err(ScopaError::Player(format!(
+                "Unknown player {}",
+                snapshot.active_player
+            )));
+        }
+
+        let mut players = HashMap::with_capacity(snapshot.rules.players);
+        for saved in snapshot.players {
+            let mut player = Player::new(&saved.name);
+            player.points = saved.points;
+            player.scopas = saved.scopas;
+            player.hand = saved.hand;
+            player.take_cards(saved.taken);
+            if players.insert(saved.id, player).is_some() {
+                return Err(ScopaError::Player(format!("{} is seated twice", saved.id)));
+            }
+        }
+        let mut table = Table::default();
+        for card in snapshot.table {
+            table.put_card(card);
+        }
+        let game = Self {
+            rules: snapshot.rules,
+            players,
+            seats,
+            deck: Deck::from_cards(snapshot.deck),
+            table,
+            active_player: snapshot.active_player,
+            took_last: snapshot.took_last,
+            first_seat: snapshot.first_seat,
+            seed: snapshot.seed,
+            round: snapshot.round,
+        };
+        if dealt {
+            game.check_deal()?;
+        }
+        Ok(game)
+    }
+}